@@ -18,6 +18,8 @@ const testConfig: GatedTransferConfig = {
   proverBinary: "/nonexistent/binary",
   sepoliaRpcUrl: "http://localhost:8545",
   testUsdtAddress: "0x0000000000000000000000000000000000000001",
+  chainId: 11155111,
+  senderAddress: "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
 };
 
 describe("runProver", () => {
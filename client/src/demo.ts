@@ -13,6 +13,9 @@ const config: GatedTransferConfig = {
   proverBinary: process.env.PROVER_BINARY || resolve(__dirname, "../../prover/target/release/zkml-prover"),
   sepoliaRpcUrl: process.env.SEPOLIA_RPC_URL || "https://sepolia.infura.io/v3/YOUR_KEY",
   testUsdtAddress: process.env.TEST_USDT_ADDRESS || "0x0000000000000000000000000000000000000000",
+  chainId: 11155111, // Sepolia
+  // Replaced with the WDK wallet address once the wallet is initialized
+  senderAddress: "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
 };
 
 const RECIPIENT = process.env.RECIPIENT_ADDRESS || "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
@@ -63,6 +66,9 @@ async function main() {
   console.log("╚══════════════════════════════════════════╝\n");
 
   const { transferFn: executeTransfer, isRealWdk, walletAddress } = await resolveTransferFn();
+  if (walletAddress) {
+    config.senderAddress = walletAddress;
+  }
 
   console.log("Config:");
   console.log(`  Co-signer URL: ${config.cosignerUrl}`);
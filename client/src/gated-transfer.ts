@@ -1,4 +1,5 @@
 import { execFileSync } from "child_process";
+import { randomUUID } from "crypto";
 import { resolve, dirname } from "path";
 import { fileURLToPath } from "url";
 
@@ -34,6 +35,16 @@ export interface GatedTransferConfig {
   proverBinary: string;
   sepoliaRpcUrl: string;
   testUsdtAddress: string;
+  chainId: number;
+  senderAddress: string;
+}
+
+export interface CosignerTx {
+  from: string;
+  to: string;
+  amount: string;
+  token: string;
+  chain_id: number;
 }
 
 /**
@@ -69,13 +80,17 @@ export function runProver(
 
 /**
  * Submit proof to the co-signer service for verification.
+ *
+ * Retries that reuse `idempotencyKey` with the same request get the original
+ * approval back instead of consuming a new nonce.
  */
 export async function submitToCosigner(
   proof: string,
   programIo: string,
-  tx: { to: string; amount: string; token: string },
+  tx: CosignerTx,
   cosignerUrl: string,
-  modelHash: string
+  modelHash: string,
+  idempotencyKey: string = randomUUID()
 ): Promise<CosignerResponse> {
   console.log(`[Cosigner] Submitting proof for verification...`);
 
@@ -89,7 +104,10 @@ export async function submitToCosigner(
 
   const resp = await fetch(`${cosignerUrl}/verify`, {
     method: "POST",
-    headers: { "Content-Type": "application/json" },
+    headers: {
+      "Content-Type": "application/json",
+      "Idempotency-Key": idempotencyKey,
+    },
     body,
     signal: AbortSignal.timeout(120_000),
   });
@@ -152,7 +170,13 @@ export async function gatedTransfer(
     cosignerResponse = await submitToCosigner(
       proverResult.proof,
       proverResult.program_io,
-      { to: recipient, amount, token: config.testUsdtAddress },
+      {
+        from: config.senderAddress,
+        to: recipient,
+        amount,
+        token: config.testUsdtAddress,
+        chain_id: config.chainId,
+      },
       config.cosignerUrl,
       proverResult.model_hash
     );
@@ -0,0 +1,255 @@
+//! The approval message the cosigner signs, and how to check a signature on
+//! one offline.
+
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use tiny_keccak::{Hasher, Keccak};
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TxDetails {
+    pub from: String, // sender address; with chain_id selects the nonce domain
+    pub to: String,
+    pub amount: String,
+    pub token: String,
+    pub chain_id: u64,
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Parse a base-unit amount: a non-empty string of decimal digits.
+pub fn parse_amount(s: &str) -> Option<u128> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parse a `0x`-prefixed, 40-digit hex address in any case.
+pub fn parse_address(s: &str) -> Result<[u8; 20], String> {
+    s.strip_prefix("0x")
+        .filter(|h| h.len() == 40)
+        .and_then(|h| hex::decode(h).ok())
+        .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok())
+        .ok_or_else(|| format!("{s} is not an address"))
+}
+
+impl TxDetails {
+    /// Check every field can be encoded in the approval digest.
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, value) in [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("token", &self.token),
+        ] {
+            parse_address(value).map_err(|e| format!("Invalid {field}: {e}"))?;
+        }
+        parse_amount(&self.amount).map(|_| ()).ok_or_else(|| {
+            format!(
+                "Invalid amount {:?}: expected decimal base units",
+                self.amount
+            )
+        })
+    }
+}
+
+/// Digest the cosigner signs for an approval, the same bytes as Solidity's
+/// `keccak256(abi.encodePacked(uint64 chainId, address from, address to,
+/// uint256 amount, address token, uint64 nonce, uint64 timestamp))`.
+/// Every field has a fixed width, so no two transfers share an encoding,
+/// and the address casing doesn't matter.
+///
+/// Approvals carry no domain tag, since the contract rebuilds these exact
+/// bytes. Every other message a cosigner or issuer key signs starts with a
+/// tag of its own (`zkml-<kind>-v1`), so a signature over one kind can't be
+/// passed off as another.
+pub fn approval_digest(tx: &TxDetails, nonce: u64, timestamp: u64) -> Result<[u8; 32], String> {
+    tx.validate()?;
+    let amount = parse_amount(&tx.amount).expect("validated");
+    let mut msg = Vec::with_capacity(8 + 20 + 20 + 32 + 20 + 8 + 8);
+    msg.extend_from_slice(&tx.chain_id.to_be_bytes());
+    msg.extend_from_slice(&parse_address(&tx.from)?);
+    msg.extend_from_slice(&parse_address(&tx.to)?);
+    msg.extend_from_slice(&[0u8; 16]);
+    msg.extend_from_slice(&amount.to_be_bytes());
+    msg.extend_from_slice(&parse_address(&tx.token)?);
+    msg.extend_from_slice(&nonce.to_be_bytes());
+    msg.extend_from_slice(&timestamp.to_be_bytes());
+    Ok(keccak256(&msg))
+}
+
+/// Ethereum address (lowercase `0x` hex) of a public key.
+pub fn address(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    format!("0x{}", hex::encode(&hash[12..]))
+}
+
+/// Whether `signature` (hex `r || s`) over `message` was made by the key
+/// with Ethereum address `signer`.
+pub fn signed_by(message: &[u8], signature: &str, signer: &str) -> bool {
+    let Some(sig) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+    else {
+        return false;
+    };
+    // The signature carries no recovery id, so try both
+    [0u8, 1].into_iter().any(|id| {
+        RecoveryId::from_byte(id)
+            .and_then(|id| VerifyingKey::recover_from_msg(message, &sig, id).ok())
+            .is_some_and(|key| address(&key).eq_ignore_ascii_case(signer))
+    })
+}
+
+/// Check that `signature` (hex `r || s`, as returned by `/verify`) is a valid
+/// approval of `tx` under `nonce` and `timestamp` by `signer` (an address).
+pub fn verify_approval(
+    tx: &TxDetails,
+    nonce: u64,
+    timestamp: u64,
+    signature: &str,
+    signer: &str,
+) -> Result<(), String> {
+    if signed_by(&approval_digest(tx, nonce, timestamp)?, signature, signer) {
+        Ok(())
+    } else {
+        Err(format!("Signature is not a valid approval by {signer}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::{signature::Signer, SigningKey};
+
+    fn tx() -> TxDetails {
+        TxDetails {
+            from: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            to: "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc".to_string(),
+            amount: "100".to_string(),
+            token: "0xB8CE59FC3717ada4C02eaDF9682A9e934F625ebb".to_string(),
+            chain_id: 9745,
+        }
+    }
+
+    fn hardhat_key() -> SigningKey {
+        // First Hardhat/Anvil development account
+        let key_bytes =
+            hex::decode("ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80")
+                .unwrap();
+        SigningKey::from_bytes((&key_bytes[..]).into()).unwrap()
+    }
+
+    #[test]
+    fn test_keccak256_known_input() {
+        // keccak256("") = c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470
+        let hash = keccak256(b"");
+        assert_eq!(
+            hex::encode(hash),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn test_keccak256_hello() {
+        // keccak256("hello") known value
+        let hash = keccak256(b"hello");
+        assert_eq!(
+            hex::encode(hash),
+            "1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8"
+        );
+    }
+
+    #[test]
+    fn test_approval_digest_binds_domain() {
+        let tx = tx();
+        let digest = approval_digest(&tx, 1, 1_700_000_000).unwrap();
+
+        let other_chain = TxDetails {
+            chain_id: 1,
+            ..tx.clone()
+        };
+        assert_ne!(Ok(digest), approval_digest(&other_chain, 1, 1_700_000_000));
+
+        let other_sender = TxDetails {
+            from: "0x90f79bf6eb2c4f870365e785982e1f101e93b906".to_string(),
+            ..tx.clone()
+        };
+        assert_ne!(Ok(digest), approval_digest(&other_sender, 1, 1_700_000_000));
+        assert_ne!(Ok(digest), approval_digest(&tx, 2, 1_700_000_000));
+
+        // Casing doesn't change the digest
+        let lowercase = TxDetails {
+            from: tx.from.to_lowercase(),
+            token: tx.token.to_lowercase(),
+            ..tx.clone()
+        };
+        assert_eq!(Ok(digest), approval_digest(&lowercase, 1, 1_700_000_000));
+    }
+
+    #[test]
+    fn test_approval_digest_encoding() {
+        // keccak256(abi.encodePacked(uint64(9745), from, to, uint256(100),
+        // token, uint64(1), uint64(1700000000)))
+        let digest = approval_digest(&tx(), 1, 1_700_000_000).unwrap();
+        let packed = hex::decode(concat!(
+            "0000000000002611",
+            "70997970c51812dc3a010c7d01b50e0d17dc79c8",
+            "3c44cdddb6a900fa2b585dd299e03d12fa4293bc",
+            "0000000000000000000000000000000000000000000000000000000000000064",
+            "b8ce59fc3717ada4c02eadf9682a9e934f625ebb",
+            "0000000000000001",
+            "000000006553f100",
+        ))
+        .unwrap();
+        assert_eq!(digest, keccak256(&packed));
+
+        // Fields that used to run together are now rejected
+        let shifted = TxDetails {
+            amount: "1".to_string(),
+            token: "00xB8CE59FC3717ada4C02eaDF9682A9e934F625eb".to_string(),
+            ..tx()
+        };
+        assert!(approval_digest(&shifted, 1, 1_700_000_000).is_err());
+        let bad_amount = TxDetails {
+            amount: "1e6".to_string(),
+            ..tx()
+        };
+        assert!(approval_digest(&bad_amount, 1, 1_700_000_000).is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            address(hardhat_key().verifying_key()),
+            "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        );
+    }
+
+    #[test]
+    fn test_verify_approval() {
+        let key = hardhat_key();
+        let signer = address(key.verifying_key());
+        let tx = tx();
+        let (sig, _) = key.sign(&approval_digest(&tx, 7, 1_700_000_000).unwrap());
+        let sig = hex::encode(sig.to_bytes());
+
+        assert!(verify_approval(&tx, 7, 1_700_000_000, &sig, &signer).is_ok());
+        assert!(verify_approval(&tx, 8, 1_700_000_000, &sig, &signer).is_err());
+        assert!(verify_approval(
+            &tx,
+            7,
+            1_700_000_000,
+            &sig,
+            "0x70997970c51812dc3a010c7d01b50e0d17dc79c8"
+        )
+        .is_err());
+        assert!(verify_approval(&tx, 7, 1_700_000_000, "zz", &signer).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Content-addressed archive of submitted proofs. Each proof is stored once,
+//! under the SHA-256 of its bytes, together with its `program_io` and every
+//! cosigner decision made on it, so auditors can re-verify historical
+//! approvals without the service.
+
+use crate::persist::write_atomic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Default archive directory.
+pub const DEFAULT_PROOF_ARCHIVE_DIR: &str = "./proof_archive";
+
+/// How long proofs are kept after their last decision (default 90 days).
+pub const DEFAULT_RETENTION_SECS: u64 = 90 * 24 * 60 * 60;
+
+/// One cosigner decision on an archived proof.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchivedDecision {
+    /// Unix seconds when the decision was made.
+    pub time: u64,
+    /// `approved`, `replayed`, `rejected` or `error`.
+    pub outcome: String,
+    pub status: u16,
+    pub reason: Option<String>,
+    /// Sequence number of the matching audit log entry.
+    pub audit_seq: Option<u64>,
+    pub model_hash: String,
+    /// Set only when it differs from the envelope's `program_io`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_io: Option<String>,
+    pub chain_id: u64,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+    pub token: String,
+    pub nonce: Option<u64>,
+    /// Signed approval timestamp.
+    pub timestamp: Option<u64>,
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+/// A stored proof with everything needed to re-verify it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProofEnvelope {
+    /// SHA-256 (hex) of the decoded proof bytes; the archive key.
+    pub proof_hash: String,
+    /// Hex-encoded serialized proof, as submitted.
+    pub proof: String,
+    /// JSON-serialized `ProgramIO` from the first submission.
+    pub program_io: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub decisions: Vec<ArchivedDecision>,
+}
+
+impl ProofEnvelope {
+    /// `program_io` the given decision was made with.
+    pub fn program_io_for<'a>(&'a self, decision: &'a ArchivedDecision) -> &'a str {
+        decision.program_io.as_deref().unwrap_or(&self.program_io)
+    }
+}
+
+/// SHA-256 (hex) of proof bytes, as used for archive keys and audit entries.
+pub fn proof_hash(proof_bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(proof_bytes))
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == 64
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read an envelope file and check it is stored under its own proof hash.
+pub fn read_envelope(path: &Path) -> io::Result<ProofEnvelope> {
+    let envelope: ProofEnvelope = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| invalid(format!("Unreadable envelope {}: {e}", path.display())))?;
+    let bytes = hex::decode(&envelope.proof).map_err(|e| {
+        invalid(format!(
+            "Envelope {} has bad proof hex: {e}",
+            path.display()
+        ))
+    })?;
+    if proof_hash(&bytes) != envelope.proof_hash {
+        return Err(invalid(format!(
+            "Envelope {} does not match its proof hash",
+            path.display()
+        )));
+    }
+    Ok(envelope)
+}
+
+/// Every envelope file under `dir`, sorted by path.
+pub fn envelope_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for shard in fs::read_dir(dir)? {
+        let shard = shard?.path();
+        if !shard.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&shard)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Proof envelopes stored as `<dir>/<hash[..2]>/<hash>.json`.
+pub struct ProofArchive {
+    dir: PathBuf,
+    /// Held while an envelope is read and rewritten, so concurrent decisions
+    /// on one proof aren't lost and pruning can't delete one mid-update.
+    writes: Mutex<()>,
+}
+
+impl ProofArchive {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(ProofArchive {
+            dir,
+            writes: Mutex::new(()),
+        })
+    }
+
+    fn path_for(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(format!("{hash}.json"))
+    }
+
+    /// Store `proof_hex` (if new) and add `decision` to its envelope.
+    /// Returns the proof hash.
+    pub fn record(
+        &self,
+        proof_hex: &str,
+        program_io: &str,
+        mut decision: ArchivedDecision,
+    ) -> io::Result<String> {
+        let bytes = hex::decode(proof_hex).map_err(|e| invalid(format!("Bad proof hex: {e}")))?;
+        let hash = proof_hash(&bytes);
+        let path = self.path_for(&hash);
+        let _writes = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut envelope = match read_envelope(&path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ProofEnvelope {
+                proof_hash: hash.clone(),
+                proof: proof_hex.to_string(),
+                program_io: program_io.to_string(),
+                created_at: decision.time,
+                updated_at: decision.time,
+                decisions: Vec::new(),
+            },
+            Err(e) => return Err(e),
+        };
+        if envelope.program_io != program_io {
+            decision.program_io = Some(program_io.to_string());
+        }
+        envelope.updated_at = decision.time;
+        envelope.decisions.push(decision);
+
+        fs::create_dir_all(path.parent().expect("archive paths have a shard dir"))?;
+        let json = serde_json::to_vec(&envelope).map_err(io::Error::other)?;
+        write_atomic(&path, &json)?;
+        Ok(hash)
+    }
+
+    /// The envelope stored under `hash`, if any.
+    pub fn get(&self, hash: &str) -> io::Result<Option<ProofEnvelope>> {
+        let hash = hash.to_ascii_lowercase();
+        if !is_hash(&hash) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "proof hash must be 64 hex characters",
+            ));
+        }
+        match read_envelope(&self.path_for(&hash)) {
+            Ok(envelope) => Ok(Some(envelope)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete envelopes whose last decision is older than `expire_before`.
+    /// The write lock is taken per envelope, not across the scan.
+    pub fn prune(&self, expire_before: u64) -> io::Result<usize> {
+        let mut removed = 0;
+        for path in envelope_paths(&self.dir)? {
+            let _writes = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+            let updated_at = match read_envelope(&path) {
+                Ok(envelope) => envelope.updated_at,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    log::warn!("Skipping unreadable proof envelope: {e}");
+                    continue;
+                }
+            };
+            if updated_at < expire_before {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(time: u64) -> ArchivedDecision {
+        ArchivedDecision {
+            time,
+            outcome: "approved".to_string(),
+            status: 200,
+            reason: None,
+            audit_seq: Some(1),
+            model_hash: "ef".repeat(32),
+            program_io: None,
+            chain_id: 9745,
+            from: "0x70997970c51812dc3a010c7d01b50e0d17dc79c8".to_string(),
+            to: "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc".to_string(),
+            amount: "1000000".to_string(),
+            token: "0x0000000000000000000000000000000000000001".to_string(),
+            nonce: Some(1),
+            timestamp: Some(time),
+            signature: Some("00".repeat(64)),
+            key_id: None,
+        }
+    }
+
+    #[test]
+    fn test_record_get_and_prune() {
+        let dir = std::env::temp_dir().join("test_proof_archive");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = ProofArchive::open(&dir).unwrap();
+
+        let hash = archive
+            .record("deadbeef", r#"{"io":1}"#, decision(100))
+            .unwrap();
+        assert_eq!(hash, proof_hash(&[0xde, 0xad, 0xbe, 0xef]));
+        // Same proof again, with a different program_io
+        archive
+            .record("deadbeef", r#"{"io":2}"#, decision(200))
+            .unwrap();
+
+        let envelope = archive.get(&hash.to_uppercase()).unwrap().unwrap();
+        assert_eq!(envelope.created_at, 100);
+        assert_eq!(envelope.updated_at, 200);
+        assert_eq!(envelope.decisions.len(), 2);
+        assert_eq!(
+            envelope.program_io_for(&envelope.decisions[0]),
+            r#"{"io":1}"#
+        );
+        assert_eq!(
+            envelope.program_io_for(&envelope.decisions[1]),
+            r#"{"io":2}"#
+        );
+
+        assert!(archive.get("not-a-hash").is_err());
+        assert!(archive.get(&"00".repeat(32)).unwrap().is_none());
+
+        assert_eq!(archive.prune(200).unwrap(), 0);
+        assert_eq!(archive.prune(201).unwrap(), 1);
+        assert!(archive.get(&hash).unwrap().is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_tampered_envelope_is_rejected() {
+        let dir = std::env::temp_dir().join("test_proof_archive_tamper");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = ProofArchive::open(&dir).unwrap();
+        let hash = archive.record("abcd", "{}", decision(1)).unwrap();
+
+        let path = archive.path_for(&hash);
+        let json = fs::read_to_string(&path).unwrap();
+        fs::write(&path, json.replace("\"abcd\"", "\"abce\"")).unwrap();
+        assert!(archive.get(&hash).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
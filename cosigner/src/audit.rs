@@ -0,0 +1,643 @@
+use crate::persist::write_atomic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Default location of the audit log.
+pub const DEFAULT_AUDIT_LOG_PATH: &str = "./audit_log.jsonl";
+
+/// `prev_hash` of the first entry.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One cosigner decision. Everything here is covered by the entry hash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Position in the log, starting at 1.
+    pub seq: u64,
+    /// Unix seconds when the decision was made.
+    pub time: u64,
+    /// `verify`, `batch`, `job`, `cosign` (coordinator requests), or `admin`
+    /// for key ring changes.
+    pub endpoint: String,
+    /// `approved`, `replayed`, `rejected` or `error`; `key_promoted` for
+    /// admin entries.
+    pub outcome: String,
+    /// HTTP status returned for the decision.
+    pub status: u16,
+    /// Rejection reason code, as in the request metrics.
+    pub reason_code: Option<String>,
+    pub reason: Option<String>,
+    /// SHA-256 (hex) of the serialized verify request.
+    pub request_digest: String,
+    /// SHA-256 (hex) of the decoded proof bytes, if the proof was valid hex.
+    pub proof_hash: Option<String>,
+    pub model_hash: String,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+    pub token: String,
+    pub chain_id: u64,
+    pub nonce: Option<u64>,
+    pub signature: Option<String>,
+    /// Signed approval timestamp.
+    pub timestamp: Option<u64>,
+    /// Key that signed the approval, or the newly promoted key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Spending policy version the decision was made under, if one is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_version: Option<String>,
+    /// Policy rule that refused the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_rule: Option<String>,
+    /// Denylist (`name@version`) that matched the sender or recipient.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denylist: Option<String>,
+    /// `hash` of the previous entry, or [`GENESIS_HASH`].
+    pub prev_hash: String,
+}
+
+/// A line of the audit log: the record plus its chained hash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    /// SHA-256 (hex) of the record's JSON serialization.
+    pub hash: String,
+}
+
+impl AuditRecord {
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("AuditRecord serializes");
+        hex::encode(Sha256::digest(json))
+    }
+}
+
+/// Latest sequence number and hash, kept beside the log so that truncating
+/// the log (which leaves a valid chain) is still detected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditHead {
+    pub seq: u64,
+    pub hash: String,
+}
+
+impl Default for AuditHead {
+    fn default() -> Self {
+        AuditHead {
+            seq: 0,
+            hash: GENESIS_HASH.to_string(),
+        }
+    }
+}
+
+/// Head file for the log at `log_path`: `<log_path>.head`.
+pub fn head_path(log_path: &Path) -> PathBuf {
+    let mut name = log_path.as_os_str().to_os_string();
+    name.push(".head");
+    PathBuf::from(name)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read and check every entry of the log at `path`: each line parses, `seq`
+/// counts up from 1, `prev_hash` links to the previous entry and `hash`
+/// matches the record. Returns the entries in order.
+pub fn read_log(path: &Path) -> io::Result<Vec<AuditEntry>> {
+    read_log_until(path, u64::MAX)
+}
+
+/// [`read_log`], stopping after entry `last_seq`. Lets readers skip a line
+/// that is still being appended.
+pub fn read_log_until(path: &Path, last_seq: u64) -> io::Result<Vec<AuditEntry>> {
+    let mut entries = Vec::new();
+    scan(path, 0, 1, last_seq, |entry| {
+        entries.push(entry);
+        true
+    })?;
+    Ok(entries)
+}
+
+/// Read the log at `path` from byte `offset`, where entry `first_seq`
+/// starts, up to entry `last_seq`, checking each entry as [`read_log`] does.
+/// Entries go to `visit` until it returns false. The first entry's
+/// `prev_hash` is only checked when reading from the start; line numbers in
+/// errors count from `offset`.
+pub fn scan(
+    path: &Path,
+    offset: u64,
+    first_seq: u64,
+    last_seq: u64,
+    mut visit: impl FnMut(AuditEntry) -> bool,
+) -> io::Result<()> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut expected_seq = first_seq;
+    let mut prev_hash = (first_seq == 1).then(|| GENESIS_HASH.to_string());
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        if expected_seq > last_seq {
+            break;
+        }
+        let line = line?;
+        let line_no = i + 1;
+        let entry: AuditEntry = serde_json::from_str(&line)
+            .map_err(|e| invalid(format!("line {line_no}: unreadable entry: {e}")))?;
+        if entry.record.seq != expected_seq {
+            return Err(invalid(format!(
+                "line {line_no}: expected seq {expected_seq}, found {}",
+                entry.record.seq
+            )));
+        }
+        if prev_hash.is_some_and(|prev| entry.record.prev_hash != prev) {
+            return Err(invalid(format!(
+                "line {line_no}: prev_hash does not match the previous entry"
+            )));
+        }
+        if entry.record.hash() != entry.hash {
+            return Err(invalid(format!(
+                "line {line_no}: hash does not match the entry contents"
+            )));
+        }
+        expected_seq += 1;
+        prev_hash = Some(entry.hash.clone());
+        if !visit(entry) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read the head file, if any.
+pub fn read_head(path: &Path) -> io::Result<Option<AuditHead>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| invalid(format!("Corrupt audit head {}: {e}", path.display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check the log chain and that it still contains the entry recorded in
+/// `head`. The log may run past the head by entries appended just before a
+/// crash, but never stop short of it. A missing head is only accepted for an
+/// empty log. Returns the head of the log as read.
+pub fn verify(log_path: &Path, head: Option<&AuditHead>) -> io::Result<AuditHead> {
+    let entries = read_log(log_path)?;
+    let last = entries
+        .last()
+        .map(|e| AuditHead {
+            seq: e.record.seq,
+            hash: e.hash.clone(),
+        })
+        .unwrap_or_default();
+    match head {
+        Some(head) if head.seq > last.seq => Err(invalid(format!(
+            "log ends at seq {} but head records seq {}: entries were truncated",
+            last.seq, head.seq
+        ))),
+        Some(head) if head.seq > 0 && entries[head.seq as usize - 1].hash != head.hash => {
+            Err(invalid(format!(
+                "entry {} does not match the head: entries were rewritten",
+                head.seq
+            )))
+        }
+        None if last.seq > 0 => Err(invalid("log has entries but no head file".to_string())),
+        _ => Ok(last),
+    }
+}
+
+/// Append-only, hash-chained log of cosigner decisions, one JSON entry per
+/// line. Every append is fsynced before the head file is advanced.
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    /// Length of the log up to the end of the head entry.
+    len: u64,
+    /// Byte offset of each entry, by `seq - 1`, so queries can start
+    /// mid-log.
+    offsets: Vec<u64>,
+    head_path: PathBuf,
+    head: AuditHead,
+}
+
+/// Drop a torn last line, left by a crash or a failed write mid-append, if it
+/// lies past the entry recorded in `head`. A damaged line the head covers is
+/// left for [`verify`] to refuse.
+fn truncate_torn_tail(path: &Path, head: Option<&AuditHead>) -> io::Result<()> {
+    let bytes = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let complete = bytes.ends_with(b"\n");
+    let body = if complete {
+        &bytes[..bytes.len() - 1]
+    } else {
+        &bytes[..]
+    };
+    if body.is_empty() {
+        return Ok(());
+    }
+    let start = body.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    if complete && serde_json::from_slice::<AuditEntry>(&body[start..]).is_ok() {
+        return Ok(());
+    }
+    let lines_before = bytes[..start].iter().filter(|&&b| b == b'\n').count() as u64;
+    if lines_before < head.map_or(0, |h| h.seq) {
+        return Ok(());
+    }
+    log::warn!(
+        "Dropping torn last line of audit log {} ({} bytes)",
+        path.display(),
+        bytes.len() - start
+    );
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(start as u64)?;
+    file.sync_all()
+}
+
+impl AuditLog {
+    /// Open the log, verifying the existing chain against its head. A log
+    /// that fails verification is refused rather than extended.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let head_path = head_path(path);
+        let recorded = read_head(&head_path)?;
+        truncate_torn_tail(path, recorded.as_ref())?;
+        let head = verify(path, recorded.as_ref())?;
+        if recorded.as_ref() != Some(&head) && head.seq > 0 {
+            // An append was fsynced but the crash came before the head moved
+            let head_json = serde_json::to_vec(&head).map_err(io::Error::other)?;
+            write_atomic(&head_path, &head_json)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        let mut offsets = Vec::with_capacity(head.seq as usize);
+        let mut offset = 0;
+        for line in BufReader::new(File::open(path)?).split(b'\n') {
+            offsets.push(offset);
+            offset += line?.len() as u64 + 1;
+        }
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file,
+            len,
+            offsets,
+            head_path,
+            head,
+        })
+    }
+
+    pub fn head(&self) -> &AuditHead {
+        &self.head
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Byte offset where entry `seq` starts, if it is in the log.
+    pub fn offset(&self, seq: u64) -> Option<u64> {
+        let index = usize::try_from(seq.checked_sub(1)?).ok()?;
+        self.offsets.get(index).copied()
+    }
+
+    /// Chain `record` onto the log, filling in `seq` and `prev_hash`.
+    pub fn append(&mut self, mut record: AuditRecord) -> io::Result<AuditEntry> {
+        record.seq = self.head.seq + 1;
+        record.prev_hash = self.head.hash.clone();
+        let entry = AuditEntry {
+            hash: record.hash(),
+            record,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+        let head = AuditHead {
+            seq: entry.record.seq,
+            hash: entry.hash.clone(),
+        };
+        let head_json = serde_json::to_vec(&head).map_err(io::Error::other)?;
+
+        let written = self
+            .file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data())
+            .and_then(|()| write_atomic(&self.head_path, &head_json));
+        if let Err(e) = written {
+            // Cut off whatever part of the line landed, so the next append
+            // (or the next start) finds the log ending at the head
+            if let Err(cut) = self.file.set_len(self.len) {
+                log::error!("Failed to truncate audit log after a failed append: {cut}");
+            }
+            return Err(e);
+        }
+        self.offsets.push(self.len);
+        self.len += line.len() as u64;
+        self.head = head;
+        Ok(entry)
+    }
+}
+
+/// Filters for querying the audit log. Unset fields match everything;
+/// addresses compare case-insensitively.
+#[derive(Deserialize, Default, Debug)]
+pub struct AuditQuery {
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub token: Option<String>,
+    pub chain_id: Option<u64>,
+    pub outcome: Option<String>,
+    pub nonce: Option<u64>,
+    /// Earliest decision time, inclusive (unix seconds).
+    pub since: Option<u64>,
+    /// Latest decision time, exclusive (unix seconds).
+    pub until: Option<u64>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        let same = |filter: &Option<String>, value: &str| {
+            filter
+                .as_deref()
+                .is_none_or(|f| f.eq_ignore_ascii_case(value))
+        };
+        same(&self.sender, &record.from)
+            && same(&self.recipient, &record.to)
+            && same(&self.token, &record.token)
+            && same(&self.outcome, &record.outcome)
+            && self.chain_id.is_none_or(|c| c == record.chain_id)
+            && self.nonce.is_none_or(|n| record.nonce == Some(n))
+            && self.since.is_none_or(|t| record.time >= t)
+            && self.until.is_none_or(|t| record.time < t)
+    }
+}
+
+const CSV_HEADER: [&str; 24] = [
+    "seq",
+    "time",
+    "endpoint",
+    "outcome",
+    "status",
+    "reason_code",
+    "reason",
+    "request_digest",
+    "proof_hash",
+    "model_hash",
+    "from",
+    "to",
+    "amount",
+    "token",
+    "chain_id",
+    "nonce",
+    "signature",
+    "timestamp",
+    "key_id",
+    "policy_version",
+    "policy_rule",
+    "denylist",
+    "prev_hash",
+    "hash",
+];
+
+/// Write `entries` as CSV with a header row, one column per entry field.
+pub fn write_csv<W: Write>(entries: &[AuditEntry], out: W) -> io::Result<()> {
+    let mut w = csv::Writer::from_writer(out);
+    w.write_record(CSV_HEADER).map_err(io::Error::other)?;
+    for e in entries {
+        let r = &e.record;
+        let opt = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_default();
+        w.write_record([
+            r.seq.to_string().as_str(),
+            &r.time.to_string(),
+            &r.endpoint,
+            &r.outcome,
+            &r.status.to_string(),
+            r.reason_code.as_deref().unwrap_or(""),
+            r.reason.as_deref().unwrap_or(""),
+            &r.request_digest,
+            r.proof_hash.as_deref().unwrap_or(""),
+            &r.model_hash,
+            &r.from,
+            &r.to,
+            &r.amount,
+            &r.token,
+            &r.chain_id.to_string(),
+            &opt(r.nonce),
+            r.signature.as_deref().unwrap_or(""),
+            &opt(r.timestamp),
+            r.key_id.as_deref().unwrap_or(""),
+            r.policy_version.as_deref().unwrap_or(""),
+            r.policy_rule.as_deref().unwrap_or(""),
+            r.denylist.as_deref().unwrap_or(""),
+            &r.prev_hash,
+            &e.hash,
+        ])
+        .map_err(io::Error::other)?;
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(head_path(&path));
+        path
+    }
+
+    fn record(outcome: &str) -> AuditRecord {
+        AuditRecord {
+            seq: 0,
+            time: 1_700_000_000,
+            endpoint: "verify".to_string(),
+            outcome: outcome.to_string(),
+            status: 200,
+            reason_code: None,
+            reason: None,
+            request_digest: "ab".repeat(32),
+            proof_hash: Some("cd".repeat(32)),
+            model_hash: "ef".repeat(32),
+            from: "0x70997970c51812dc3a010c7d01b50e0d17dc79c8".to_string(),
+            to: "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc".to_string(),
+            amount: "1000000".to_string(),
+            token: "0x0000000000000000000000000000000000000001".to_string(),
+            chain_id: 9745,
+            nonce: Some(1),
+            signature: Some("00".repeat(64)),
+            timestamp: Some(1_700_000_000),
+            key_id: None,
+            policy_version: None,
+            policy_rule: None,
+            denylist: None,
+            prev_hash: String::new(),
+        }
+    }
+
+    fn cleanup(path: &Path) {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(head_path(path));
+    }
+
+    #[test]
+    fn test_chain_survives_reopen() {
+        let path = temp_log("test_audit_reopen.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        let first = log.append(record("approved")).unwrap();
+        assert_eq!(first.record.seq, 1);
+        assert_eq!(first.record.prev_hash, GENESIS_HASH);
+        drop(log);
+
+        let mut log = AuditLog::open(&path).unwrap();
+        let second = log.append(record("rejected")).unwrap();
+        assert_eq!(second.record.seq, 2);
+        assert_eq!(second.record.prev_hash, first.hash);
+
+        let head = verify(&path, read_head(&head_path(&path)).unwrap().as_ref()).unwrap();
+        assert_eq!(head.seq, 2);
+        assert_eq!(head.hash, second.hash);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_detects_tampering() {
+        let path = temp_log("test_audit_tamper.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.append(record("rejected")).unwrap();
+        log.append(record("approved")).unwrap();
+        drop(log);
+
+        let original = fs::read_to_string(&path).unwrap();
+        fs::write(&path, original.replacen("rejected", "approved", 1)).unwrap();
+        let err = AuditLog::open(&path).err().unwrap();
+        assert!(err.to_string().contains("line 1"), "{err}");
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_query_filters_and_csv() {
+        let path = temp_log("test_audit_query.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.append(record("approved")).unwrap();
+        let mut rejected = record("rejected");
+        rejected.nonce = None;
+        rejected.signature = None;
+        rejected.reason = Some("bad, \"quoted\" proof".to_string());
+        log.append(rejected).unwrap();
+        drop(log);
+
+        let entries = read_log(&path).unwrap();
+        let query = AuditQuery {
+            sender: Some("0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string()),
+            outcome: Some("rejected".to_string()),
+            ..Default::default()
+        };
+        let hits: Vec<_> = entries
+            .iter()
+            .filter(|e| query.matches(&e.record))
+            .collect();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].record.seq, 2);
+
+        let by_nonce = AuditQuery {
+            nonce: Some(1),
+            until: Some(1_700_000_001),
+            ..Default::default()
+        };
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|e| by_nonce.matches(&e.record))
+                .count(),
+            1
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&entries, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("seq,time,endpoint,outcome"));
+        assert_eq!(lines.count(), 2);
+        assert!(csv.contains(r#""bad, ""quoted"" proof""#));
+
+        assert_eq!(read_log_until(&path, 1).unwrap().len(), 1);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_detects_truncation() {
+        let path = temp_log("test_audit_truncate.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.append(record("approved")).unwrap();
+        log.append(record("approved")).unwrap();
+        drop(log);
+
+        // Dropping the last line leaves a valid chain, but not the recorded head
+        let original = fs::read_to_string(&path).unwrap();
+        let first_line = original.lines().next().unwrap();
+        fs::write(&path, format!("{first_line}\n")).unwrap();
+        let err = AuditLog::open(&path).err().unwrap();
+        assert!(err.to_string().contains("truncated"), "{err}");
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_scan_from_offset() {
+        let path = temp_log("test_audit_scan.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        for outcome in ["approved", "rejected", "approved", "rejected"] {
+            log.append(record(outcome)).unwrap();
+        }
+        drop(log);
+        let log = AuditLog::open(&path).unwrap();
+        assert_eq!(log.offset(0), None);
+        assert_eq!(log.offset(5), None);
+
+        // Start at entry 2 and stop after the first match
+        let mut seen = Vec::new();
+        scan(&path, log.offset(2).unwrap(), 2, 4, |entry| {
+            seen.push(entry.record.seq);
+            entry.record.outcome != "approved"
+        })
+        .unwrap();
+        assert_eq!(seen, vec![2, 3]);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_drops_torn_tail() {
+        let path = temp_log("test_audit_torn.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.append(record("approved")).unwrap();
+        drop(log);
+        let original = fs::read_to_string(&path).unwrap();
+
+        // A crash mid-append leaves part of a line past the head
+        fs::write(&path, format!("{original}{{\"seq\":2,\"ti")).unwrap();
+        let mut log = AuditLog::open(&path).unwrap();
+        assert_eq!(log.append(record("rejected")).unwrap().record.seq, 2);
+        drop(log);
+        assert_eq!(read_log(&path).unwrap().len(), 2);
+
+        // A damaged line the head covers is still refused
+        let lines: Vec<&str> = original.lines().collect();
+        fs::write(&path, &lines[0][..20]).unwrap();
+        assert!(AuditLog::open(&path).is_err());
+        cleanup(&path);
+    }
+}
@@ -0,0 +1,43 @@
+//! Check a cosigner audit log offline: every entry's hash, the chain of
+//! `prev_hash` links, and that the log still reaches its recorded head.
+//!
+//! Usage: audit-verify <audit_log.jsonl> [head file]
+//!
+//! The head file defaults to `<audit_log.jsonl>.head`. Exits non-zero if the
+//! log was modified, reordered or truncated.
+
+use std::path::{Path, PathBuf};
+use zkml_cosigner::audit;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: audit-verify <audit_log.jsonl> [head file]");
+        std::process::exit(2);
+    }
+    let log_path = Path::new(&args[1]);
+    let head_path = args
+        .get(2)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| audit::head_path(log_path));
+
+    let result = audit::read_head(&head_path)
+        .and_then(|head| audit::verify(log_path, head.as_ref()).map(|last| (head, last)));
+    match result {
+        Ok((head, last)) => {
+            println!("OK: {} entries, last hash {}", last.seq, last.hash);
+            if let Some(head) = head.filter(|h| h.seq < last.seq) {
+                // Entries appended after the head, e.g. just before a crash
+                println!(
+                    "note: {} entries past the recorded head (seq {})",
+                    last.seq - head.seq,
+                    head.seq
+                );
+            }
+        }
+        Err(e) => {
+            println!("FAIL: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Check M-of-N approval bundles offline against a signer set.
+//!
+//! Usage: bundle-verify --threshold <M> --signer <0x address>... <bundle.json>...
+//!
+//! Takes the `bundle` objects returned by the coordinator's `/verify` (or
+//! the whole response). Pass `--signer` once per cosigner in the set (see
+//! the coordinator's `/v1/signers`). Exits non-zero if any bundle has fewer
+//! than M valid signatures from distinct signers in the set.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+use zkml_cosigner::multisig::{self, ApprovalBundle};
+
+const USAGE: &str =
+    "Usage: bundle-verify --threshold <M> --signer <0x address>... <bundle.json>...";
+
+/// A bare bundle or a coordinator response wrapping one.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BundleFile {
+    Response { bundle: ApprovalBundle },
+    Bundle(ApprovalBundle),
+}
+
+struct Args {
+    threshold: usize,
+    signers: Vec<String>,
+    files: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut threshold = None;
+    let mut signers = Vec::new();
+    let mut files = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => threshold = args.next().and_then(|s| s.parse().ok()),
+            "--signer" => signers.extend(args.next()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    match threshold {
+        Some(threshold) if !files.is_empty() => {
+            multisig::check_threshold(threshold, signers.len())?;
+            Ok(Args {
+                threshold,
+                signers,
+                files,
+            })
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn check_file(args: &Args, path: &PathBuf) -> Result<ApprovalBundle, String> {
+    let json = std::fs::read(path).map_err(|e| e.to_string())?;
+    let bundle = match serde_json::from_slice(&json).map_err(|e| e.to_string())? {
+        BundleFile::Response { bundle } | BundleFile::Bundle(bundle) => bundle,
+    };
+    multisig::verify_bundle(&bundle, &args.signers, args.threshold)?;
+    Ok(bundle)
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let mut failed = 0;
+    for path in &args.files {
+        match check_file(&args, path) {
+            Ok(bundle) => println!(
+                "PASS {}: chain {} from {} nonce {}",
+                path.display(),
+                bundle.tx.chain_id,
+                bundle.tx.from,
+                bundle.nonce
+            ),
+            Err(e) => {
+                println!("FAIL {}: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,363 @@
+//! M-of-N coordinator: takes `/verify` requests and fans them out to N
+//! independently keyed cosigners, returning a bundle of their signatures over
+//! one approval digest once M have approved.
+//!
+//! Configuration:
+//!   COORDINATOR_COSIGNERS     comma-separated `<0x signer address>@<cosigner url>`
+//!   COORDINATOR_THRESHOLD     approvals required (M)
+//!   COSIGN_API_TOKEN          bearer token the cosigners expect on `/v1/cosign`
+//!   COORDINATOR_TIMEOUT_SECS  per-cosigner request timeout (default 120)
+//!   PORT, BIND_ADDRESS        listen address (default 127.0.0.1:3002)
+//!
+//! The coordinator picks the nonce (the highest next nonce any cosigner
+//! reports) and the timestamp, so every cosigner signs the same digest. If a
+//! cosigner has already used the nonce the round is retried with a new one.
+//! Bundles are checked offline with `bundle-verify`.
+
+use actix_web::{http::StatusCode, web, App, HttpResponse, HttpServer};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinSet;
+use zkml_cosigner::{
+    approval::{signed_by, TxDetails},
+    credential::TrustCredential,
+    multisig::{self, ApprovalBundle, BundleSignature, CosignRequest, CosignerSpec},
+};
+
+/// Rounds tried before giving up on nonce conflicts.
+const NONCE_ATTEMPTS: usize = 3;
+
+#[derive(Deserialize)]
+struct VerifyRequest {
+    proof: String,
+    program_io: String,
+    tx: TxDetails,
+    model_hash: String,
+    trust_credential: Option<TrustCredential>,
+}
+
+/// The parts of a cosigner's `VerifyResponse` the coordinator needs.
+#[derive(Deserialize)]
+struct CosignerResponse {
+    #[serde(default)]
+    approved: bool,
+    signature: Option<String>,
+    nonce: Option<u64>,
+    timestamp: Option<u64>,
+    key_id: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NonceResponse {
+    next_nonce: u64,
+}
+
+/// Why one cosigner did not approve.
+#[derive(Serialize)]
+struct Refusal {
+    cosigner: String,
+    status: u16,
+    reason: String,
+}
+
+struct Coordinator {
+    cosigners: Vec<CosignerSpec>,
+    threshold: usize,
+    token: String,
+    client: reqwest::Client,
+}
+
+/// Ask one cosigner to approve `body` and check its signature.
+async fn cosign_one(
+    client: reqwest::Client,
+    cosigner: CosignerSpec,
+    token: String,
+    body: Arc<CosignRequest>,
+    digest: Vec<u8>,
+) -> Result<BundleSignature, Refusal> {
+    let refusal = |status: u16, reason: String| Refusal {
+        cosigner: cosigner.url.clone(),
+        status,
+        reason,
+    };
+    let resp = client
+        .post(format!("{}/v1/cosign", cosigner.url))
+        .bearer_auth(&token)
+        .json(&*body)
+        .send()
+        .await
+        .map_err(|e| refusal(502, format!("Cosigner unreachable: {e}")))?;
+    let status = resp.status().as_u16();
+    let resp: CosignerResponse = resp
+        .json()
+        .await
+        .map_err(|e| refusal(502, format!("Unreadable cosigner response: {e}")))?;
+    let signature = match resp {
+        CosignerResponse {
+            approved: true,
+            signature: Some(signature),
+            nonce: Some(nonce),
+            timestamp: Some(timestamp),
+            ..
+        } if nonce == body.nonce && timestamp == body.timestamp => signature,
+        CosignerResponse { approved: true, .. } => {
+            return Err(refusal(
+                502,
+                "Approval is not for the requested nonce and timestamp".to_string(),
+            ))
+        }
+        CosignerResponse { reason, .. } => {
+            return Err(refusal(
+                status,
+                reason.unwrap_or_else(|| format!("Refused with status {status}")),
+            ))
+        }
+    };
+    if !signed_by(&digest, &signature, &cosigner.address) {
+        return Err(refusal(
+            502,
+            format!("Signature is not by {}", cosigner.address),
+        ));
+    }
+    Ok(BundleSignature {
+        signer: cosigner.address,
+        key_id: resp.key_id,
+        signature,
+    })
+}
+
+impl Coordinator {
+    /// The highest next nonce reported for the sender by at least M cosigners.
+    async fn next_nonce(&self, tx: &TxDetails) -> Result<u64, String> {
+        let mut pending = JoinSet::new();
+        for cosigner in &self.cosigners {
+            let request = self.client.get(format!(
+                "{}/nonce/{}/{}",
+                cosigner.url, tx.chain_id, tx.from
+            ));
+            pending.spawn(async move {
+                let resp = request.send().await.ok()?.error_for_status().ok()?;
+                resp.json::<NonceResponse>().await.ok()
+            });
+        }
+        let mut nonces = Vec::new();
+        while let Some(joined) = pending.join_next().await {
+            nonces.extend(joined.ok().flatten().map(|r| r.next_nonce));
+        }
+        if nonces.len() < self.threshold {
+            return Err(format!(
+                "Only {} of {} cosigners reported a nonce; {} are required",
+                nonces.len(),
+                self.cosigners.len(),
+                self.threshold
+            ));
+        }
+        Ok(nonces.into_iter().max().expect("threshold is at least 1"))
+    }
+
+    /// One round: every cosigner is asked to approve under `nonce`.
+    async fn round(&self, req: &VerifyRequest, nonce: u64) -> Result<ApprovalBundle, Vec<Refusal>> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut bundle = ApprovalBundle::new(req.tx.clone(), nonce, timestamp, self.threshold)
+            .expect("tx is validated in approve");
+        let digest = hex::decode(&bundle.digest).expect("bundle digest is hex");
+        let body = Arc::new(CosignRequest {
+            proof: req.proof.clone(),
+            program_io: req.program_io.clone(),
+            tx: req.tx.clone(),
+            model_hash: req.model_hash.clone(),
+            trust_credential: req.trust_credential.clone(),
+            nonce,
+            timestamp,
+        });
+
+        let mut pending = JoinSet::new();
+        for cosigner in &self.cosigners {
+            pending.spawn(cosign_one(
+                self.client.clone(),
+                cosigner.clone(),
+                self.token.clone(),
+                Arc::clone(&body),
+                digest.clone(),
+            ));
+        }
+        let mut refusals = Vec::new();
+        while let Some(joined) = pending.join_next().await {
+            match joined {
+                Ok(Ok(signature)) => {
+                    bundle.signatures.push(signature);
+                    if bundle.signatures.len() >= self.threshold {
+                        // Dropping the set abandons the slower cosigners
+                        return Ok(bundle);
+                    }
+                }
+                Ok(Err(refusal)) => refusals.push(refusal),
+                Err(e) => refusals.push(Refusal {
+                    cosigner: "unknown".to_string(),
+                    status: 500,
+                    reason: format!("Cosigner request failed: {e}"),
+                }),
+            }
+            if refusals.len() > self.cosigners.len() - self.threshold {
+                break;
+            }
+        }
+        Err(refusals)
+    }
+
+    /// Collect M approvals of `req`, retrying with a fresh nonce when a
+    /// cosigner had already used the chosen one.
+    async fn approve(&self, req: &VerifyRequest) -> Result<ApprovalBundle, HttpResponse> {
+        req.tx
+            .validate()
+            .map_err(|e| refused(StatusCode::BAD_REQUEST, e, Vec::new()))?;
+        let mut attempt = 1;
+        loop {
+            let nonce = self
+                .next_nonce(&req.tx)
+                .await
+                .map_err(|e| refused(StatusCode::SERVICE_UNAVAILABLE, e, Vec::new()))?;
+            let refusals = match self.round(req, nonce).await {
+                Ok(bundle) => return Ok(bundle),
+                Err(refusals) => refusals,
+            };
+            let conflict = refusals.iter().any(|r| r.status == 409);
+            if conflict && attempt < NONCE_ATTEMPTS {
+                log::warn!("Nonce {nonce} was already used by a cosigner; retrying");
+                attempt += 1;
+                continue;
+            }
+            // Cosigners run the same checks, so a client error from one is the answer
+            let status = refusals
+                .iter()
+                .map(|r| r.status)
+                .find(|&s| (400..500).contains(&s) && s != 409)
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            let reason = format!(
+                "{} of {} cosigners refused; {} approvals are required",
+                refusals.len(),
+                self.cosigners.len(),
+                self.threshold
+            );
+            return Err(refused(status, reason, refusals));
+        }
+    }
+}
+
+fn refused(status: StatusCode, reason: String, cosigners: Vec<Refusal>) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "approved": false,
+        "reason": reason,
+        "cosigners": cosigners,
+    }))
+}
+
+async fn verify(data: web::Data<Coordinator>, req: web::Json<VerifyRequest>) -> HttpResponse {
+    match data.approve(&req).await {
+        Ok(bundle) => {
+            log::info!(
+                "Approved nonce {} for {} on chain {} with {} signatures",
+                bundle.nonce,
+                bundle.tx.from,
+                bundle.tx.chain_id,
+                bundle.signatures.len()
+            );
+            HttpResponse::Ok().json(serde_json::json!({"approved": true, "bundle": bundle}))
+        }
+        Err(resp) => resp,
+    }
+}
+
+/// The signer set and threshold bundles are checked against.
+async fn signers(data: web::Data<Coordinator>) -> HttpResponse {
+    let signers: Vec<&str> = data.cosigners.iter().map(|c| c.address.as_str()).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "threshold": data.threshold,
+        "signers": signers,
+    }))
+}
+
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+}
+
+fn config() -> Result<Coordinator, String> {
+    let cosigners = multisig::parse_cosigners(
+        &std::env::var("COORDINATOR_COSIGNERS")
+            .map_err(|_| "Set COORDINATOR_COSIGNERS".to_string())?,
+    )?;
+    let threshold: usize = std::env::var("COORDINATOR_THRESHOLD")
+        .map_err(|_| "Set COORDINATOR_THRESHOLD".to_string())?
+        .parse()
+        .map_err(|e| format!("Invalid COORDINATOR_THRESHOLD: {e}"))?;
+    multisig::check_threshold(threshold, cosigners.len())?;
+    let token = std::env::var("COSIGN_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+        .ok_or("Set COSIGN_API_TOKEN")?;
+    let timeout_secs: u64 = std::env::var("COORDINATOR_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(120);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+    Ok(Coordinator {
+        cosigners,
+        threshold,
+        token,
+        client,
+    })
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::init();
+    let coordinator = match config() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    log::info!(
+        "Coordinating {} of {} cosigners: {}",
+        coordinator.threshold,
+        coordinator.cosigners.len(),
+        coordinator
+            .cosigners
+            .iter()
+            .map(|c| format!("{} at {}", c.address, c.url))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let data = web::Data::new(coordinator);
+
+    let port: u16 = std::env::var("PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(3002);
+    log::info!("Starting coordinator on port {port}");
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            // Proofs are ~110KB
+            .app_data(web::JsonConfig::default().limit(512 * 1024))
+            .route("/health", web::get().to(health))
+            .route("/v1/signers", web::get().to(signers))
+            .route("/verify", web::post().to(verify))
+    })
+    .bind((
+        std::env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string()),
+        port,
+    ))?
+    .run()
+    .await
+}
@@ -0,0 +1,56 @@
+//! Sign a merchant directory for the cosigner.
+//!
+//! Usage: merchant-directory <entries.json>
+//!
+//! Reads `{"version", "default_category", "merchants": {"0x...": 0-3}}`,
+//! signs it with one key, loaded the same way as the cosigner's local keys
+//! (`COSIGNER_KEYSTORE` with `COSIGNER_KEYSTORE_PASSWORD_FILE`, or
+//! `COSIGNER_PRIVATE_KEY`), and prints the signed directory. Its address must
+//! be in the cosigner's `MERCHANT_DIRECTORY_SIGNERS`.
+
+use std::sync::Arc;
+use zkml_cosigner::{
+    directory::{DirectoryEntries, MerchantDirectory},
+    signer::{self, Signer},
+};
+
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: merchant-directory <entries.json>");
+        std::process::exit(2);
+    }
+    let entries = std::fs::read(&args[1])
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            serde_json::from_slice::<DirectoryEntries>(&json).map_err(|e| e.to_string())
+        })
+        .and_then(DirectoryEntries::normalize)
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid directory {}: {e}", args[1]);
+            std::process::exit(1);
+        });
+    let signers = signer::local_from_env().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    let [signer] = <[Arc<dyn Signer>; 1]>::try_from(signers).unwrap_or_else(|signers| {
+        eprintln!("Configure exactly one key, found {}", signers.len());
+        std::process::exit(1);
+    });
+
+    let signature = signer.sign(&entries.digest()).unwrap_or_else(|e| {
+        eprintln!("Failed to sign: {e}");
+        std::process::exit(1);
+    });
+    let directory = MerchantDirectory {
+        entries,
+        issuer: signer.address().to_string(),
+        signature,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&directory).expect("MerchantDirectory serializes")
+    );
+}
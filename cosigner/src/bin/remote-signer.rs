@@ -0,0 +1,110 @@
+//! Stand-in remote signer speaking the protocol in `zkml_cosigner::signer`.
+//!
+//! Usage: remote-signer (--listen <host:port> | --unix <socket path>)
+//!
+//! Holds one key, loaded the same way as the cosigner's local keys
+//! (`COSIGNER_KEYSTORE` with `COSIGNER_KEYSTORE_PASSWORD_FILE`, or
+//! `COSIGNER_PRIVATE_KEY`). Point the cosigner at it with
+//! `COSIGNER_REMOTE_SIGNER=http://<host:port>` or `unix:<socket path>`.
+//! Meant for tests and local setups; a production signer should run
+//! isolated from the cosigner, e.g. under its own user or on an HSM host.
+//!
+//! `--listen` requires `COSIGNER_REMOTE_SIGNER_TOKEN` and refuses requests
+//! that don't carry it. `--unix` creates the socket with mode 0600, so only
+//! this user can connect; the token is checked there too when set.
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+use zeroize::Zeroizing;
+use zkml_cosigner::signer::{self, Signer};
+
+const USAGE: &str = "Usage: remote-signer (--listen <host:port> | --unix <socket path>)";
+
+fn serve<S: Read + Write + Send + 'static>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    signer: Arc<dyn Signer>,
+    token: Option<Zeroizing<String>>,
+) {
+    let token = Arc::new(token);
+    for stream in incoming {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let signer = Arc::clone(&signer);
+        let token = Arc::clone(&token);
+        std::thread::spawn(move || {
+            let token = token.as_deref().map(String::as_str);
+            if let Err(e) = signer::serve_connection(stream, signer.as_ref(), token) {
+                log::warn!("Signer request failed: {e}");
+            }
+        });
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    }
+
+    let signers = match signer::local_from_env() {
+        Ok(signers) => signers,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let [signer] = <[Arc<dyn Signer>; 1]>::try_from(signers).unwrap_or_else(|signers| {
+        eprintln!("Configure exactly one key, found {}", signers.len());
+        std::process::exit(1);
+    });
+    log::info!("Signing as {} ({})", signer.address(), signer.describe());
+    let token = signer::token_from_env();
+
+    match args[1].as_str() {
+        "--listen" => {
+            if token.is_none() {
+                eprintln!(
+                    "Set {} to listen on TCP; anyone who can connect could sign otherwise",
+                    signer::TOKEN_ENV
+                );
+                std::process::exit(2);
+            }
+            let listener = std::net::TcpListener::bind(&args[2]).unwrap_or_else(|e| {
+                eprintln!("Failed to listen on {}: {e}", args[2]);
+                std::process::exit(1);
+            });
+            log::info!("Listening on http://{}", args[2]);
+            serve(listener.incoming(), signer, token);
+        }
+        #[cfg(unix)]
+        "--unix" => {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::remove_file(&args[2]);
+            let listener = std::os::unix::net::UnixListener::bind(&args[2]).unwrap_or_else(|e| {
+                eprintln!("Failed to listen on {}: {e}", args[2]);
+                std::process::exit(1);
+            });
+            // Connecting needs write access to the socket file
+            let owner_only = std::fs::Permissions::from_mode(0o600);
+            if let Err(e) = std::fs::set_permissions(&args[2], owner_only) {
+                eprintln!("Failed to restrict {}: {e}", args[2]);
+                std::process::exit(1);
+            }
+            log::info!("Listening on unix:{}", args[2]);
+            serve(listener.incoming(), signer, token);
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
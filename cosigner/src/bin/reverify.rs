@@ -0,0 +1,260 @@
+//! Re-verify archived proofs offline, without the cosigner service.
+//!
+//! Usage: reverify --model <authorization.onnx> --signer <0x address>... <envelope dir or file>...
+//!
+//! Takes a proof archive directory (`PROOF_ARCHIVE_DIR`) or individual
+//! envelopes saved from `GET /v1/proofs/{hash}`. Rebuilds the verifier
+//! preprocessing from the model and re-checks every approval in them: the
+//! model hash, the SNARK proof, the model's output class, the cosigner's
+//! signature and that no nonce was issued twice. Pass `--signer` once per
+//! key in the ring (see `/v1/keys`) to accept approvals from retired keys. Prints one line per decision
+//! and exits non-zero if any approval fails.
+
+use ark_bn254::Fr;
+use ark_serialize::CanonicalDeserialize;
+use jolt_core::{poly::commitment::dory::DoryCommitmentScheme, transcripts::KeccakTranscript};
+use onnx_tracer::{model, ProgramIO};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::PathBuf};
+use zkml_cosigner::{
+    approval::{self, TxDetails},
+    archive::{self, ArchivedDecision, ProofEnvelope},
+};
+use zkml_jolt_core::jolt::{JoltSNARK, JoltVerifierPreprocessing};
+
+/// Must match the cosigner's and prover's Jolt lookup table size.
+const JOLT_TABLE_SIZE: usize = 1 << 14;
+
+#[allow(clippy::upper_case_acronyms)]
+type PCS = DoryCommitmentScheme;
+
+const USAGE: &str =
+    "Usage: reverify --model <authorization.onnx> --signer <0x address>... <envelope dir or file>...";
+
+struct Args {
+    model: PathBuf,
+    signers: Vec<String>,
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut model = None;
+    let mut signers = Vec::new();
+    let mut inputs = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--model" => model = args.next().map(PathBuf::from),
+            "--signer" => signers.extend(args.next().map(|s| s.to_lowercase())),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    match model {
+        Some(model) if !signers.is_empty() && !inputs.is_empty() => Ok(Args {
+            model,
+            signers,
+            inputs,
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Envelope files named on the command line; directories are read as archives.
+fn envelope_files(inputs: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            files.extend(archive::envelope_paths(input)?);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the model output is class 0 (authorized), as the cosigner requires.
+fn authorizes(program_io: &ProgramIO) -> bool {
+    program_io
+        .output
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.cmp(b.1))
+        .is_some_and(|(idx, _)| idx == 0)
+}
+
+/// Decode and verify one proof against `program_io`, as `/verify` does.
+fn verify_snark(
+    preprocessing: &JoltVerifierPreprocessing<Fr, PCS>,
+    proof_hex: &str,
+    program_io: &str,
+) -> Result<(), String> {
+    let program_io: ProgramIO = serde_json::from_str(program_io)
+        .map_err(|e| format!("Failed to deserialize program_io: {e}"))?;
+    if !authorizes(&program_io) {
+        return Err("Model output is DENIED (class != 0)".to_string());
+    }
+    let bytes = hex::decode(proof_hex).map_err(|e| format!("Bad proof hex: {e}"))?;
+    let snark = JoltSNARK::<Fr, PCS, KeccakTranscript>::deserialize_compressed(&bytes[..])
+        .map_err(|e| format!("Failed to deserialize proof: {e}"))?;
+    snark
+        .verify(preprocessing, program_io, None)
+        .map_err(|e| format!("Proof verification failed: {e}"))
+}
+
+struct Auditor {
+    preprocessing: JoltVerifierPreprocessing<Fr, PCS>,
+    model_hash: String,
+    signers: Vec<String>,
+    /// SNARK results by (proof hash, program_io), so replays verify once.
+    snarks: HashMap<(String, String), Result<(), String>>,
+    /// Issued nonces by (chain_id, sender, nonce) and the signature they carry.
+    nonces: HashMap<(u64, String, u64), String>,
+}
+
+impl Auditor {
+    fn check_snark(&mut self, envelope: &ProofEnvelope, program_io: &str) -> Result<(), String> {
+        let key = (envelope.proof_hash.clone(), program_io.to_string());
+        if let Some(result) = self.snarks.get(&key) {
+            return result.clone();
+        }
+        let result = verify_snark(&self.preprocessing, &envelope.proof, program_io);
+        self.snarks.insert(key, result.clone());
+        result
+    }
+
+    /// Re-check one approved (or replayed) decision.
+    fn check_approval(
+        &mut self,
+        envelope: &ProofEnvelope,
+        decision: &ArchivedDecision,
+    ) -> Result<(), String> {
+        if decision.model_hash != self.model_hash {
+            return Err(format!(
+                "Approved under model {}, not {}",
+                decision.model_hash, self.model_hash
+            ));
+        }
+        let (Some(nonce), Some(timestamp), Some(signature)) =
+            (decision.nonce, decision.timestamp, &decision.signature)
+        else {
+            return Err("Approval is missing its nonce, timestamp or signature".to_string());
+        };
+        self.check_snark(envelope, envelope.program_io_for(decision))?;
+
+        let tx = TxDetails {
+            from: decision.from.clone(),
+            to: decision.to.clone(),
+            amount: decision.amount.clone(),
+            token: decision.token.clone(),
+            chain_id: decision.chain_id,
+        };
+        if !self.signers.iter().any(|signer| {
+            approval::verify_approval(&tx, nonce, timestamp, signature, signer).is_ok()
+        }) {
+            return Err(format!(
+                "Signature was not made by any of {}",
+                self.signers.join(", ")
+            ));
+        }
+
+        // A replay repeats the original signature; anything else is a reuse
+        let domain = (decision.chain_id, decision.from.to_lowercase(), nonce);
+        match self.nonces.get(&domain) {
+            Some(seen) if seen != signature => Err(format!(
+                "Nonce {nonce} was issued twice for {} on chain {}",
+                decision.from, decision.chain_id
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.nonces.insert(domain, signature.clone());
+                Ok(())
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let files = match envelope_files(&args.inputs) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Failed to list envelopes: {e}");
+            std::process::exit(2);
+        }
+    };
+    let model_hash = match std::fs::read(&args.model) {
+        Ok(bytes) => hex::encode(Sha256::digest(bytes)),
+        Err(e) => {
+            eprintln!("Failed to read model {}: {e}", args.model.display());
+            std::process::exit(2);
+        }
+    };
+
+    eprintln!("Preprocessing model {model_hash}...");
+    let model_path = args.model.clone();
+    let prover_preprocessing = JoltSNARK::<Fr, PCS, KeccakTranscript>::prover_preprocess(
+        || model(&model_path),
+        JOLT_TABLE_SIZE,
+    );
+    let mut auditor = Auditor {
+        preprocessing: (&prover_preprocessing).into(),
+        model_hash,
+        signers: args.signers,
+        snarks: HashMap::new(),
+        nonces: HashMap::new(),
+    };
+
+    let (mut passed, mut failed, mut skipped) = (0usize, 0usize, 0usize);
+    for path in &files {
+        let envelope = match archive::read_envelope(path) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                println!("FAIL {}: {e}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+        for (i, decision) in envelope.decisions.iter().enumerate() {
+            let label = format!(
+                "{} #{i} {} chain {} from {} nonce {}",
+                envelope.proof_hash,
+                decision.outcome,
+                decision.chain_id,
+                decision.from,
+                decision.nonce.map_or("-".to_string(), |n| n.to_string())
+            );
+            if decision.outcome != "approved" && decision.outcome != "replayed" {
+                // Refusals issued nothing, so there is nothing to hold the cosigner to
+                println!("SKIP {label}");
+                skipped += 1;
+                continue;
+            }
+            match auditor.check_approval(&envelope, decision) {
+                Ok(()) => {
+                    println!("PASS {label}");
+                    passed += 1;
+                }
+                Err(e) => {
+                    println!("FAIL {label}: {e}");
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    println!(
+        "{}: {passed} approvals passed, {failed} failed, {skipped} refusals skipped, {} envelopes",
+        if failed == 0 { "OK" } else { "FAIL" },
+        files.len()
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
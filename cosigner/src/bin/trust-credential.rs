@@ -0,0 +1,87 @@
+//! Issue a signed trust credential for the prover and cosigner.
+//!
+//! Usage: trust-credential --subject <0x address> --trust <0-7> --expires-in <secs>
+//!
+//! Signs with one key, loaded the same way as the cosigner's local keys
+//! (`COSIGNER_KEYSTORE` with `COSIGNER_KEYSTORE_PASSWORD_FILE`, or
+//! `COSIGNER_PRIVATE_KEY`), and prints the credential JSON. Its address must
+//! be in the prover's and cosigner's `TRUST_ISSUERS`.
+
+use std::sync::Arc;
+use zkml_cosigner::{
+    credential::{credential_digest, TrustCredential, MAX_TRUST},
+    signer::{self, Signer},
+};
+
+const USAGE: &str =
+    "Usage: trust-credential --subject <0x address> --trust <0-7> --expires-in <secs>";
+
+struct Args {
+    subject: String,
+    trust: u8,
+    expires_in: u64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut subject = None;
+    let mut trust = None;
+    let mut expires_in = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--subject" => subject = args.next().map(|s| s.to_lowercase()),
+            "--trust" => trust = args.next().and_then(|s| s.parse().ok()),
+            "--expires-in" => expires_in = args.next().and_then(|s| s.parse().ok()),
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}")),
+        }
+    }
+    match (subject, trust, expires_in) {
+        (Some(subject), Some(trust), Some(expires_in)) if trust <= MAX_TRUST => Ok(Args {
+            subject,
+            trust,
+            expires_in,
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let signers = signer::local_from_env().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    let [signer] = <[Arc<dyn Signer>; 1]>::try_from(signers).unwrap_or_else(|signers| {
+        eprintln!("Configure exactly one key, found {}", signers.len());
+        std::process::exit(1);
+    });
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let expires_at = now.saturating_add(args.expires_in);
+    let digest = credential_digest(&args.subject, args.trust, expires_at).unwrap_or_else(|e| {
+        eprintln!("Invalid --subject: {e}");
+        std::process::exit(2);
+    });
+    let signature = signer.sign(&digest).unwrap_or_else(|e| {
+        eprintln!("Failed to sign: {e}");
+        std::process::exit(1);
+    });
+    let credential = TrustCredential {
+        subject: args.subject,
+        trust: args.trust,
+        expires_at,
+        issuer: signer.address().to_string(),
+        signature,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&credential).expect("TrustCredential serializes")
+    );
+}
@@ -0,0 +1,192 @@
+//! Client side of two-party ECDSA with the cosigner.
+//!
+//! Usage:
+//!   two-party keygen --cosigner <url> --owner <keystore.json> --out <share.json>
+//!   two-party sign --cosigner <url> --share <share.json> --request <request.json>
+//!
+//! `keygen` generates a key with the cosigner, asking for it with a request
+//! signed by the wallet key in the `--owner` keystore. It saves the client's
+//! share encrypted to `--out` and prints the shared address. `sign` sends a `/verify` request for a transfer from that address,
+//! plus the transaction's `nonce`, fees and gas limit under `transaction`, and
+//! prints the signed raw transaction once the cosigner has approved and
+//! signed its half.
+//!
+//! Passphrases for the share and the owner keystore are read from
+//! `TWO_PARTY_PASSWORD_FILE`, or prompted for. The cosigner URL should be `https://`: the protocols assume a private
+//! channel.
+
+use serde::Deserialize;
+use std::{path::PathBuf, sync::Arc};
+use zeroize::Zeroizing;
+use zkml_cosigner::{
+    approval::TxDetails,
+    keys,
+    signer::KeystoreSigner,
+    two_party::{self, Batch, KeyShare, KeygenRequest, Opened, Party, Round, TransferTx, CLIENT},
+};
+
+const USAGE: &str = "Usage:
+  two-party keygen --cosigner <url> --owner <keystore.json> --out <share.json>
+  two-party sign --cosigner <url> --share <share.json> --request <request.json>";
+
+enum Command {
+    Keygen { owner: PathBuf, out: PathBuf },
+    Sign { share: PathBuf, request: PathBuf },
+}
+
+fn parse_args() -> Result<(String, Command), String> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().ok_or(USAGE)?;
+    let (mut cosigner, mut owner, mut out, mut share, mut request) = (None, None, None, None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cosigner" => cosigner = args.next().map(|u| u.trim_end_matches('/').to_string()),
+            "--owner" => owner = args.next().map(PathBuf::from),
+            "--out" => out = args.next().map(PathBuf::from),
+            "--share" => share = args.next().map(PathBuf::from),
+            "--request" => request = args.next().map(PathBuf::from),
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}")),
+        }
+    }
+    let command = match (command.as_str(), owner, out, share, request) {
+        ("keygen", Some(owner), Some(out), None, None) => Command::Keygen { owner, out },
+        ("sign", None, None, Some(share), Some(request)) => Command::Sign { share, request },
+        _ => return Err(USAGE.to_string()),
+    };
+    Ok((cosigner.ok_or(USAGE)?, command))
+}
+
+/// A `/verify` request with the transaction to sign; the request is passed
+/// to the cosigner as is.
+#[derive(Deserialize)]
+struct SignRequest {
+    tx: TxDetails,
+    transaction: TransferTx,
+}
+
+struct Cosigner {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl Cosigner {
+    fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        token: Option<&str>,
+        body: &impl serde::Serialize,
+    ) -> Result<T, String> {
+        let mut req = self.client.post(format!("{}{path}", self.url)).json(body);
+        if let Some(token) = token {
+            req = req.bearer_auth(token);
+        }
+        let resp = req.send().map_err(|e| format!("{path}: {e}"))?;
+        let status = resp.status();
+        let text = resp.text().map_err(|e| format!("{path}: {e}"))?;
+        if !status.is_success() {
+            return Err(format!("{path}: {status}: {text}"));
+        }
+        serde_json::from_str(&text).map_err(|e| format!("{path}: unexpected response: {e}"))
+    }
+
+    /// Open a session at `path` and run the client's side of it. `start`
+    /// makes the client's party from the opened session, or refuses it
+    /// before any client message is sent.
+    fn run<O: Send + 'static>(
+        &self,
+        path: &str,
+        body: &impl serde::Serialize,
+        start: impl FnOnce(&Opened) -> Result<Party<O>, String>,
+    ) -> Result<O, String> {
+        let opened: Opened = self.post(path, None, body)?;
+        let party = start(&opened)?;
+        let session = format!("/v1/two-party/sessions/{}", opened.session_id);
+        two_party::run_client(
+            party,
+            || Ok(opened.round.clone()),
+            |messages| {
+                self.post::<Round>(&session, Some(&opened.session_token), &Batch { messages })
+            },
+        )
+    }
+}
+
+fn passphrase(path: &std::path::Path) -> Result<Zeroizing<String>, String> {
+    match std::env::var("TWO_PARTY_PASSWORD_FILE") {
+        Ok(file) => keys::read_passphrase_file(std::path::Path::new(&file)),
+        Err(_) => rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))
+            .map(Zeroizing::new)
+            .map_err(|e| keys::KeyError::Passphrase(e.to_string())),
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn keygen(cosigner: &Cosigner, owner: PathBuf, out: PathBuf) -> Result<String, String> {
+    if out.exists() {
+        return Err(format!("{} already exists", out.display()));
+    }
+    let owner = KeystoreSigner::open(&owner, &passphrase(&owner)?).map_err(|e| e.to_string())?;
+    let passphrase = passphrase(&out)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let request = KeygenRequest::sign(&owner, now).map_err(|e| e.to_string())?;
+    eprintln!("Generating a key with the cosigner; this takes a minute or two...");
+    let share: KeyShare = cosigner.run("/v1/two-party/keygen", &request, |opened| {
+        Ok(two_party::keygen(CLIENT, &opened.session_id))
+    })?;
+    two_party::save_share(&out, &share, &passphrase)
+        .map_err(|e| format!("Failed to save {}: {e}", out.display()))?;
+    Ok(two_party::shared_address(&share))
+}
+
+fn sign(cosigner: &Cosigner, share: PathBuf, request: PathBuf) -> Result<String, String> {
+    let body: serde_json::Value = std::fs::read(&request)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Invalid request {}: {e}", request.display()))?;
+    let SignRequest { tx, transaction } = serde_json::from_value(body.clone())
+        .map_err(|e| format!("Invalid request {}: {e}", request.display()))?;
+    let key_share =
+        two_party::load_share(&share, &passphrase(&share)?).map_err(|e| e.to_string())?;
+    let address = two_party::shared_address(&key_share);
+    if !tx.from.eq_ignore_ascii_case(&address) {
+        return Err(format!("tx.from must be the two-party address {address}"));
+    }
+    let hash = transaction.signing_hash(&tx)?;
+    let key_share = Arc::new(key_share);
+    let signature = cosigner.run("/v1/two-party/sessions", &body, |opened| {
+        // Only sign the transaction built here, whatever the cosigner says
+        if opened.signing_hash.as_deref() != Some(hex::encode(hash).as_str()) {
+            return Err("Cosigner is signing a different transaction".to_string());
+        }
+        Ok(two_party::sign(CLIENT, &opened.session_id, key_share, hash))
+    })?;
+    let raw = transaction.encode_signed(&tx, &signature)?;
+    Ok(format!("0x{}", hex::encode(raw)))
+}
+
+fn main() {
+    env_logger::init();
+    let (url, command) = parse_args().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let client = reqwest::blocking::Client::builder()
+        .timeout(two_party::EXCHANGE_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+    let cosigner = Cosigner { url, client };
+    let result = match command {
+        Command::Keygen { owner, out } => keygen(&cosigner, owner, out),
+        Command::Sign { share, request } => sign(&cosigner, share, request),
+    };
+    match result {
+        Ok(output) => println!("{output}"),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Trust credentials: an issuer's signed statement of a sender's `trust`
+//! bucket, so the feature isn't self-reported. The prover only builds an
+//! input from a credential it has checked, and the cosigner checks it again
+//! against the transfer and the proof's decoded input.
+
+use crate::approval::{keccak256, parse_address, signed_by};
+use serde::{Deserialize, Serialize};
+
+/// Domain tag of credential digests; see
+/// [`approval_digest`](crate::approval::approval_digest).
+const CREDENTIAL_TAG: &[u8] = b"zkml-trust-credential-v1";
+
+/// Highest trust bucket in the model's vocab.
+pub const MAX_TRUST: u8 = 7;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TrustCredential {
+    /// Sender address the credential is about.
+    pub subject: String,
+    /// Trust bucket, `0..=MAX_TRUST`.
+    pub trust: u8,
+    /// Unix time after which the credential is no longer accepted.
+    pub expires_at: u64,
+    /// Address of the issuer key.
+    pub issuer: String,
+    /// Hex `r || s` by the issuer over [`credential_digest`], made the same
+    /// way as approval signatures.
+    pub signature: String,
+}
+
+/// Digest an issuer signs:
+/// keccak256(tag || subject || trust || expires_at)
+/// with subject as its 20 address bytes, trust as one byte and expires_at as
+/// an 8-byte big-endian integer.
+// NOTE: Duplicated in prover/src/main.rs; the test vector below pins both.
+pub fn credential_digest(subject: &str, trust: u8, expires_at: u64) -> Result<[u8; 32], String> {
+    let mut msg = Vec::with_capacity(CREDENTIAL_TAG.len() + 20 + 1 + 8);
+    msg.extend_from_slice(CREDENTIAL_TAG);
+    msg.extend_from_slice(&parse_address(subject)?);
+    msg.push(trust);
+    msg.extend_from_slice(&expires_at.to_be_bytes());
+    Ok(keccak256(&msg))
+}
+
+impl TrustCredential {
+    pub fn digest(&self) -> Result<[u8; 32], String> {
+        credential_digest(&self.subject, self.trust, self.expires_at)
+            .map_err(|e| format!("Invalid subject: {e}"))
+    }
+
+    /// Check the credential is signed by one of `issuers` (addresses), in
+    /// range and unexpired at `now`.
+    pub fn verify(&self, issuers: &[String], now: u64) -> Result<(), String> {
+        if self.trust > MAX_TRUST {
+            return Err(format!(
+                "Trust {} is out of range (0..={MAX_TRUST})",
+                self.trust
+            ));
+        }
+        if !issuers.iter().any(|i| i.eq_ignore_ascii_case(&self.issuer)) {
+            return Err(format!("Issuer {} is not trusted", self.issuer));
+        }
+        if !signed_by(&self.digest()?, &self.signature, &self.issuer) {
+            return Err(format!("Signature is not by issuer {}", self.issuer));
+        }
+        if self.expires_at <= now {
+            return Err(format!("Credential expired at {}", self.expires_at));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::address;
+    use k256::ecdsa::{signature::Signer, SigningKey};
+
+    const ALICE: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+
+    fn issue(key: &SigningKey, trust: u8, expires_at: u64) -> TrustCredential {
+        let (sig, _) = key.sign(&credential_digest(ALICE, trust, expires_at).unwrap());
+        TrustCredential {
+            subject: ALICE.to_string(),
+            trust,
+            expires_at,
+            issuer: address(key.verifying_key()),
+            signature: hex::encode(sig.to_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_verify_credential() {
+        let issuer = SigningKey::from_bytes(&[1; 32].into()).unwrap();
+        let other = SigningKey::from_bytes(&[2; 32].into()).unwrap();
+        let issuers = vec![address(issuer.verifying_key()).to_uppercase()];
+
+        let credential = issue(&issuer, 5, 1_000);
+        assert!(credential.verify(&issuers, 999).is_ok());
+        assert!(credential.verify(&issuers, 1_000).is_err());
+        assert!(credential.verify(&[], 999).is_err());
+
+        // Raising the trust breaks the signature
+        let mut raised = credential.clone();
+        raised.trust = 7;
+        assert!(raised.verify(&issuers, 999).is_err());
+
+        // A key outside the set can't issue, even naming a trusted issuer
+        let mut forged = issue(&other, 5, 1_000);
+        forged.issuer = credential.issuer.clone();
+        assert!(forged.verify(&issuers, 999).is_err());
+
+        assert!(issue(&issuer, 8, 1_000).verify(&issuers, 999).is_err());
+
+        let mut unaddressed = credential.clone();
+        unaddressed.subject = "alice".to_string();
+        assert!(unaddressed.verify(&issuers, 999).is_err());
+    }
+
+    #[test]
+    fn test_credential_vector() {
+        // The prover checks credentials with its own copy of this encoding;
+        // its test_credential_vector must agree
+        let issuer = SigningKey::from_bytes(&[1; 32].into()).unwrap();
+        let credential = issue(&issuer, 5, 1_000);
+        assert_eq!(
+            hex::encode(credential.digest().unwrap()),
+            "4225396515ba85cd4e75eb097b9aa7bf0133488d3fdbc8801a40c5e3399b2f91"
+        );
+        assert_eq!(
+            credential.signature,
+            "b11804499a39541c44abf5ae1c879f925ea6f5ac9b163c467033be735545593e\
+             0c42c0a23476811c96e9646ba531c39411544ff866f3dfa69b953201d12df734"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Address denylists (e.g. sanctions lists) screened before a proof is
+//! verified. Each list is a text file with one address per line, the format
+//! published for OFAC's sanctioned digital currency addresses: `#` starts a
+//! comment and blank lines are ignored. A `# version: <v>` line names the
+//! list's version; without one, the version is a hash of the file.
+
+use sha2::{Digest, Sha256};
+use std::{collections::HashSet, path::Path};
+use zkml_cosigner::approval::parse_address;
+
+pub struct AddressList {
+    /// File stem, used to name the list in rejections and the audit log.
+    pub name: String,
+    pub version: String,
+    /// Lowercase addresses.
+    addresses: HashSet<String>,
+}
+
+impl AddressList {
+    /// Parse a list. Any line that isn't an address refuses the whole list,
+    /// so a damaged file can't silently screen against half its entries.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut version = None;
+        let mut addresses = HashSet::new();
+        for (i, line) in text.lines().enumerate() {
+            let (entry, comment) = line.split_once('#').unwrap_or((line, ""));
+            if let Some(v) = comment.trim().strip_prefix("version:") {
+                version = Some(v.trim().to_string());
+            }
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            parse_address(entry).map_err(|e| format!("line {}: {e}", i + 1))?;
+            addresses.insert(entry.to_lowercase());
+        }
+        let version = version.unwrap_or_else(|| {
+            format!(
+                "sha256:{}",
+                &hex::encode(Sha256::digest(text.as_bytes()))[..16]
+            )
+        });
+        Ok(AddressList {
+            name: name.to_string(),
+            version,
+            addresses,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |s| s.to_string_lossy().into_owned(),
+        );
+        Self::parse(&name, &text).map_err(|e| format!("Invalid denylist {}: {e}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.addresses.contains(&address.to_lowercase())
+    }
+
+    /// `name@version`, as recorded in the audit log.
+    pub fn label(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTED: &str = "0x8589427373D6D84E98730D7795D8f6f8731FDA16";
+
+    #[test]
+    fn test_parse_address_list() {
+        let text = format!("# OFAC SDN\n# version: 2026-10-01\n\n{LISTED}\n0x722122dF12D4e14e13Ac3b6895a86e84145b6967 # note\n");
+        let list = AddressList::parse("sdn", &text).unwrap();
+        assert_eq!(list.label(), "sdn@2026-10-01");
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(&LISTED.to_lowercase()));
+        assert!(list.contains("0x722122DF12D4E14E13AC3B6895A86E84145B6967"));
+        assert!(!list.contains("0x0000000000000000000000000000000000000001"));
+
+        // Without a version line, the version follows the contents
+        let a = AddressList::parse("sdn", LISTED).unwrap();
+        let b = AddressList::parse("sdn", &format!("{LISTED}\n")).unwrap();
+        assert!(a.version.starts_with("sha256:"));
+        assert_ne!(a.version, b.version);
+
+        assert!(AddressList::parse("sdn", &format!("{LISTED}\nnot-an-address\n")).is_err());
+    }
+}
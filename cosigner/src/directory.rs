@@ -0,0 +1,192 @@
+//! Merchant directory: a signed list of recipients and their spending
+//! category, so the model's `category` feature reflects who is actually
+//! being paid rather than what the client claims.
+
+use crate::approval::{keccak256, parse_address, signed_by};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// Domain tag of directory digests; see
+/// [`approval_digest`](crate::approval::approval_digest).
+const DIRECTORY_TAG: &[u8] = b"zkml-merchant-directory-v1";
+
+/// Highest category bucket in the model's vocab.
+pub const MAX_CATEGORY: u8 = 3;
+
+/// Directory contents before they are signed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryEntries {
+    /// Free-form version string, logged on load.
+    pub version: String,
+    /// Category of recipients not listed; they are refused when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_category: Option<u8>,
+    /// Recipient address to category.
+    pub merchants: BTreeMap<String, u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerchantDirectory {
+    #[serde(flatten)]
+    pub entries: DirectoryEntries,
+    /// Address of the signing key.
+    pub issuer: String,
+    /// Hex `r || s` by the issuer over [`DirectoryEntries::digest`], made
+    /// the same way as approval signatures.
+    pub signature: String,
+}
+
+impl DirectoryEntries {
+    /// Digest the issuer signs:
+    /// keccak256(tag || len(version) || version || default || merchants)
+    /// with the length as 4 bytes big-endian, the default as one byte (0xff
+    /// when unset) and each merchant as its lowercase address followed by a
+    /// category byte, in address order.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut msg = Vec::new();
+        msg.extend_from_slice(DIRECTORY_TAG);
+        msg.extend_from_slice(&(self.version.len() as u32).to_be_bytes());
+        msg.extend_from_slice(self.version.as_bytes());
+        msg.push(self.default_category.unwrap_or(0xff));
+        let merchants: BTreeMap<String, u8> = self
+            .merchants
+            .iter()
+            .map(|(address, &category)| (address.to_lowercase(), category))
+            .collect();
+        for (address, category) in merchants {
+            msg.extend_from_slice(address.as_bytes());
+            msg.push(category);
+        }
+        keccak256(&msg)
+    }
+
+    /// Check every address and category is well-formed, and lowercase the
+    /// addresses.
+    pub fn normalize(self) -> Result<Self, String> {
+        if let Some(category) = self.default_category.filter(|&c| c > MAX_CATEGORY) {
+            return Err(format!(
+                "Default category {category} is out of range (0..={MAX_CATEGORY})"
+            ));
+        }
+        let mut merchants = BTreeMap::new();
+        for (address, category) in self.merchants {
+            parse_address(&address)?;
+            if category > MAX_CATEGORY {
+                return Err(format!(
+                    "{address} has category {category}, out of range (0..={MAX_CATEGORY})"
+                ));
+            }
+            if merchants.insert(address.to_lowercase(), category).is_some() {
+                return Err(format!("{address} is listed twice"));
+            }
+        }
+        Ok(DirectoryEntries { merchants, ..self })
+    }
+}
+
+impl MerchantDirectory {
+    /// Parse a signed directory and check it is well-formed and signed by
+    /// one of `signers` (addresses).
+    pub fn parse(json: &[u8], signers: &[String]) -> Result<Self, String> {
+        let directory: MerchantDirectory =
+            serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        if !signers
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(&directory.issuer))
+        {
+            return Err(format!("Issuer {} is not trusted", directory.issuer));
+        }
+        if !signed_by(
+            &directory.entries.digest(),
+            &directory.signature,
+            &directory.issuer,
+        ) {
+            return Err(format!("Signature is not by issuer {}", directory.issuer));
+        }
+        Ok(MerchantDirectory {
+            entries: directory.entries.normalize()?,
+            ..directory
+        })
+    }
+
+    pub fn load(path: &Path, signers: &[String]) -> Result<Self, String> {
+        let json = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&json, signers)
+            .map_err(|e| format!("Invalid merchant directory {}: {e}", path.display()))
+    }
+
+    pub fn version(&self) -> &str {
+        &self.entries.version
+    }
+
+    /// Category of `recipient`, or the default when it isn't listed.
+    pub fn category(&self, recipient: &str) -> Option<u8> {
+        self.entries
+            .merchants
+            .get(&recipient.to_lowercase())
+            .copied()
+            .or(self.entries.default_category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::address;
+    use k256::ecdsa::{signature::Signer, SigningKey};
+
+    const SHOP: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+
+    fn sign(key: &SigningKey, entries: &DirectoryEntries) -> Vec<u8> {
+        let (sig, _) = key.sign(&entries.digest());
+        serde_json::to_vec(&MerchantDirectory {
+            entries: entries.clone(),
+            issuer: address(key.verifying_key()),
+            signature: hex::encode(sig.to_bytes()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        let issuer = SigningKey::from_bytes(&[1; 32].into()).unwrap();
+        let other = SigningKey::from_bytes(&[2; 32].into()).unwrap();
+        let signers = vec![address(issuer.verifying_key())];
+        let mut entries = DirectoryEntries {
+            version: "2026-10-01".to_string(),
+            default_category: None,
+            merchants: BTreeMap::from([(SHOP.to_string(), 2)]),
+        };
+
+        let directory = MerchantDirectory::parse(&sign(&issuer, &entries), &signers).unwrap();
+        assert_eq!(directory.version(), "2026-10-01");
+        assert_eq!(
+            directory.category(&SHOP.to_uppercase().replace("0X", "0x")),
+            Some(2)
+        );
+        assert_eq!(
+            directory.category("0x0000000000000000000000000000000000000001"),
+            None
+        );
+        assert!(MerchantDirectory::parse(&sign(&other, &entries), &signers).is_err());
+
+        // Editing a signed directory breaks the signature
+        let mut edited: serde_json::Value =
+            serde_json::from_slice(&sign(&issuer, &entries)).unwrap();
+        edited["default_category"] = 0.into();
+        let edited = serde_json::to_vec(&edited).unwrap();
+        assert!(MerchantDirectory::parse(&edited, &signers).is_err());
+
+        entries.default_category = Some(0);
+        let directory = MerchantDirectory::parse(&sign(&issuer, &entries), &signers).unwrap();
+        assert_eq!(
+            directory.category("0x0000000000000000000000000000000000000001"),
+            Some(0)
+        );
+
+        entries.merchants.insert(SHOP.to_lowercase(), 1);
+        assert!(MerchantDirectory::parse(&sign(&issuer, &entries), &signers).is_err());
+        entries.merchants = BTreeMap::from([(SHOP.to_string(), 4)]);
+        assert!(MerchantDirectory::parse(&sign(&issuer, &entries), &signers).is_err());
+    }
+}
@@ -0,0 +1,646 @@
+//! Decoding the model input in `program_io` back into feature values, and
+//! binding them to the transaction being approved. The prover one-hot encodes
+//! each feature into the slots `models/vocab.json` assigns it, writing 1.0 as
+//! the fixed-point value [`ONE`]; the SNARK proves the model ran on that
+//! input, but not that the input describes the transfer.
+//!
+//! The binding is configured by a JSON file (`INPUT_BINDING_PATH`):
+//!
+//! ```json
+//! {
+//!   "amount_buckets": { "0x<token>": ["100", "1000", "10000"] },
+//!   "utc_offset_minutes": 0,
+//!   "clock_skew_secs": 300,
+//!   "velocity": { "window": "24h", "bounds": [0, 1, 2, 4, 8, 16, 32] },
+//!   "budget": { "window": "7d", "tokens": { "0x<token>": "1000000000" } },
+//!   "tolerance": 1
+//! }
+//! ```
+//!
+//! An amount up to `bounds[0]` is bucket 0, up to `bounds[1]` bucket 1, and
+//! so on; larger amounts fall in bucket `bounds.len()`. The `day` bucket is
+//! the weekday with Monday as 0, and the `time` bucket splits the day into
+//! equal blocks, one per slot (four six-hour blocks for the shipped vocab),
+//! both in local time at `utc_offset_minutes`. Either clock bucket may be
+//! taken from up to `clock_skew_secs` ago, since proving takes a while.
+//!
+//! `velocity` and `budget` are derived from the cosigner's own
+//! [`SpendLedger`] rather than trusted from the client. The velocity bucket
+//! places the sender's approval count over the window in `bounds` like an
+//! amount; the budget bucket is the share of the token's budget the sender
+//! has left over the window, from 0 (spent) to the top bucket (untouched).
+//! Both may differ from the decoded input by up to `tolerance` buckets, since
+//! approvals can land between proving and verification. They are checked
+//! under the ledger lock, just before the approval's spend is recorded, so
+//! concurrent or batched approvals from one sender each see the ones before.
+
+use crate::{
+    ledger::SpendLedger,
+    policy::{parse_amount, parse_window, weekday},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+use zkml_cosigner::approval::TxDetails;
+
+/// 1.0 in the prover's fixed-point encoding (scale 7).
+pub const ONE: i32 = 1 << 7;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Largest `clock_skew_secs` accepted, so the skew stays well under a
+/// `time` block.
+const MAX_CLOCK_SKEW_SECS: u64 = 60 * 60;
+
+/// Largest `tolerance` accepted, in buckets.
+const MAX_TOLERANCE: usize = 3;
+
+#[derive(Deserialize)]
+struct VocabFile {
+    vocab_mapping: HashMap<String, VocabEntry>,
+    feature_mapping: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct VocabEntry {
+    index: usize,
+}
+
+/// Which input slots encode which feature, from `vocab.json`.
+pub struct Vocab {
+    /// Per feature, the slot of each value in order.
+    groups: BTreeMap<String, Vec<usize>>,
+    input_len: usize,
+}
+
+/// Feature values decoded from a well-formed input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Features(BTreeMap<String, usize>);
+
+impl Features {
+    pub fn get(&self, feature: &str) -> Option<usize> {
+        self.0.get(feature).copied()
+    }
+}
+
+impl Vocab {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let file: VocabFile = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        let mut groups = BTreeMap::new();
+        let mut seen = HashMap::new();
+        for (feature, keys) in file.feature_mapping {
+            let mut slots = Vec::new();
+            for key in keys {
+                let index = file
+                    .vocab_mapping
+                    .get(&key)
+                    .ok_or_else(|| format!("{feature} lists {key}, which has no index"))?
+                    .index;
+                if let Some(other) = seen.insert(index, key.clone()) {
+                    return Err(format!("{key} and {other} share slot {index}"));
+                }
+                slots.push(index);
+            }
+            // Features without slots (e.g. an unused output label) aren't inputs
+            if !slots.is_empty() {
+                groups.insert(feature, slots);
+            }
+        }
+        let input_len = seen.keys().max().map_or(0, |&max| max + 1);
+        Ok(Vocab { groups, input_len })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&json).map_err(|e| format!("Invalid vocab {}: {e}", path.display()))
+    }
+
+    /// Number of values `feature` can take.
+    pub fn buckets(&self, feature: &str) -> Option<usize> {
+        self.groups.get(feature).map(Vec::len)
+    }
+
+    /// Decode `input`: every slot must be 0 or [`ONE`], with exactly one hot
+    /// slot per feature.
+    pub fn decode(&self, input: &[i32]) -> Result<Features, String> {
+        if input.len() != self.input_len {
+            return Err(format!(
+                "Input has {} slots; the vocab has {}",
+                input.len(),
+                self.input_len
+            ));
+        }
+        if let Some((i, v)) = input.iter().enumerate().find(|(_, &v)| v != 0 && v != ONE) {
+            return Err(format!("Slot {i} is {v}; one-hot slots must be 0 or {ONE}"));
+        }
+        let mut values = BTreeMap::new();
+        for (feature, slots) in &self.groups {
+            let hot: Vec<usize> = (0..slots.len())
+                .filter(|&value| input[slots[value]] == ONE)
+                .collect();
+            match hot[..] {
+                [value] => {
+                    values.insert(feature.clone(), value);
+                }
+                [] => return Err(format!("Feature {feature} has no hot slot")),
+                _ => {
+                    return Err(format!(
+                        "Feature {feature} has {} hot slots (values {hot:?})",
+                        hot.len()
+                    ))
+                }
+            }
+        }
+        let hot_total = input.iter().filter(|&&v| v == ONE).count();
+        if hot_total != values.len() {
+            return Err("Input has hot slots outside every feature".to_string());
+        }
+        Ok(Features(values))
+    }
+
+    /// One-hot encode `values`, as the prover does.
+    #[cfg(test)]
+    pub fn encode(&self, values: &[(&str, usize)]) -> Vec<i32> {
+        let mut input = vec![0; self.input_len];
+        for &(feature, value) in values {
+            input[self.groups[feature][value]] = ONE;
+        }
+        input
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingFile {
+    #[serde(default)]
+    amount_buckets: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    utc_offset_minutes: i64,
+    #[serde(default = "default_clock_skew")]
+    clock_skew_secs: u64,
+    velocity: Option<VelocityFile>,
+    budget: Option<BudgetFile>,
+    #[serde(default)]
+    tolerance: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VelocityFile {
+    window: String,
+    bounds: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BudgetFile {
+    window: String,
+    tokens: HashMap<String, String>,
+}
+
+fn default_clock_skew() -> u64 {
+    300
+}
+
+/// Approval count buckets for the `velocity` feature.
+struct VelocityBinding {
+    window: u64,
+    label: String,
+    /// Ascending inclusive upper bounds on the count.
+    bounds: Vec<u64>,
+}
+
+/// Per-token budgets for the `budget` feature.
+struct BudgetBinding {
+    window: u64,
+    label: String,
+    /// Per token (lowercase).
+    tokens: HashMap<String, u128>,
+    /// Number of `budget` values in the vocab.
+    buckets: usize,
+}
+
+/// How decoded features must relate to the transaction, the clock and the
+/// sender's approval history.
+pub struct InputBinding {
+    /// Per token (lowercase), ascending inclusive upper bounds.
+    amount_buckets: HashMap<String, Vec<u128>>,
+    utc_offset_secs: i64,
+    clock_skew_secs: u64,
+    time_buckets: u64,
+    velocity: Option<VelocityBinding>,
+    budget: Option<BudgetBinding>,
+    tolerance: usize,
+}
+
+fn window(s: &str) -> Result<u64, String> {
+    parse_window(s).ok_or_else(|| format!("Window must be <n>m, <n>h or <n>d, got {s:?}"))
+}
+
+impl InputBinding {
+    /// Parse a binding for inputs encoded with `vocab`.
+    pub fn parse(json: &[u8], vocab: &Vocab) -> Result<Self, String> {
+        let file: BindingFile = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        for feature in ["amount", "day", "time"] {
+            if vocab.buckets(feature).is_none() {
+                return Err(format!("The vocab has no {feature} feature"));
+            }
+        }
+        let amount_slots = vocab.buckets("amount").expect("checked above");
+        let mut amount_buckets = HashMap::new();
+        for (token, bounds) in file.amount_buckets {
+            let bounds = bounds
+                .iter()
+                .map(|b| {
+                    parse_amount(b).ok_or_else(|| {
+                        format!("amount_buckets bounds must be base-unit integers, got {b:?}")
+                    })
+                })
+                .collect::<Result<Vec<u128>, String>>()?;
+            if bounds.is_empty() || bounds.len() >= amount_slots {
+                return Err(format!(
+                    "amount_buckets for {token} needs 1 to {} bounds, got {}",
+                    amount_slots - 1,
+                    bounds.len()
+                ));
+            }
+            if bounds.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(format!("amount_buckets for {token} must be ascending"));
+            }
+            amount_buckets.insert(token.to_lowercase(), bounds);
+        }
+        if vocab.buckets("day").is_some_and(|n| n < 7) {
+            return Err("The day feature needs a slot per weekday".to_string());
+        }
+        if file.utc_offset_minutes.abs() > 14 * 60 {
+            return Err("utc_offset_minutes must be within ±14 hours".to_string());
+        }
+        if file.clock_skew_secs > MAX_CLOCK_SKEW_SECS {
+            return Err(format!(
+                "clock_skew_secs must be at most {MAX_CLOCK_SKEW_SECS}"
+            ));
+        }
+        let velocity = match file.velocity {
+            Some(v) => {
+                let slots = vocab
+                    .buckets("velocity")
+                    .ok_or("The vocab has no velocity feature")?;
+                if v.bounds.is_empty() || v.bounds.len() >= slots {
+                    return Err(format!(
+                        "velocity needs 1 to {} bounds, got {}",
+                        slots - 1,
+                        v.bounds.len()
+                    ));
+                }
+                if v.bounds.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err("velocity bounds must be ascending".to_string());
+                }
+                Some(VelocityBinding {
+                    window: window(&v.window)?,
+                    label: v.window,
+                    bounds: v.bounds,
+                })
+            }
+            None => None,
+        };
+        let budget = match file.budget {
+            Some(b) => {
+                let buckets = vocab
+                    .buckets("budget")
+                    .ok_or("The vocab has no budget feature")?;
+                let mut tokens = HashMap::new();
+                for (token, amount) in b.tokens {
+                    let amount = parse_amount(&amount).filter(|&a| a > 0).ok_or_else(|| {
+                        format!("budget for {token} must be a positive base-unit integer")
+                    })?;
+                    tokens.insert(token.to_lowercase(), amount);
+                }
+                Some(BudgetBinding {
+                    window: window(&b.window)?,
+                    label: b.window,
+                    tokens,
+                    buckets,
+                })
+            }
+            None => None,
+        };
+        if file.tolerance > MAX_TOLERANCE {
+            return Err(format!("tolerance must be at most {MAX_TOLERANCE}"));
+        }
+        Ok(InputBinding {
+            amount_buckets,
+            utc_offset_secs: file.utc_offset_minutes * 60,
+            clock_skew_secs: file.clock_skew_secs,
+            time_buckets: vocab.buckets("time").expect("checked above") as u64,
+            velocity,
+            budget,
+            tolerance: file.tolerance,
+        })
+    }
+
+    pub fn load(path: &Path, vocab: &Vocab) -> Result<Self, String> {
+        let json = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&json, vocab)
+            .map_err(|e| format!("Invalid input binding {}: {e}", path.display()))
+    }
+
+    /// How much approval history the velocity and budget checks read.
+    pub fn lookback_secs(&self) -> u64 {
+        let velocity = self.velocity.as_ref().map_or(0, |v| v.window);
+        let budget = self.budget.as_ref().map_or(0, |b| b.window);
+        velocity.max(budget)
+    }
+
+    /// The `amount` bucket of `tx`, if its token has buckets configured.
+    fn amount_bucket(&self, tx: &TxDetails) -> Result<usize, String> {
+        let bounds = self
+            .amount_buckets
+            .get(&tx.token.to_lowercase())
+            .ok_or_else(|| format!("No amount buckets are configured for token {}", tx.token))?;
+        let amount = parse_amount(&tx.amount)
+            .ok_or_else(|| format!("Amount {:?} is not a base-unit integer", tx.amount))?;
+        Ok(bounds.partition_point(|&bound| bound < amount))
+    }
+
+    /// The `day` and `time` buckets at unix time `time`.
+    fn clock_buckets(&self, time: u64) -> (usize, usize) {
+        let local = time.saturating_add_signed(self.utc_offset_secs);
+        let block = DAY_SECS / self.time_buckets;
+        let time_bucket = ((local % DAY_SECS) / block).min(self.time_buckets - 1);
+        (weekday(local) as usize, time_bucket as usize)
+    }
+
+    /// Check that `feature` is within the tolerance of `expected`.
+    fn check_derived(
+        &self,
+        features: &Features,
+        feature: &str,
+        expected: usize,
+        basis: String,
+    ) -> Result<(), String> {
+        match features.get(feature) {
+            Some(claimed) if claimed.abs_diff(expected) <= self.tolerance => Ok(()),
+            claimed => Err(format!(
+                "Input {feature} bucket {} is not within {} of the cosigner's {expected} ({basis})",
+                claimed.map_or("missing".to_string(), |c| c.to_string()),
+                self.tolerance
+            )),
+        }
+    }
+
+    /// Check that `features` describe `tx` being sent at `now`. The
+    /// history-derived features are left to [`InputBinding::check_history`].
+    pub fn check(&self, features: &Features, tx: &TxDetails, now: u64) -> Result<(), String> {
+        let amount = self.amount_bucket(tx)?;
+        if features.get("amount") != Some(amount) {
+            return Err(format!(
+                "Input amount bucket {:?} does not match bucket {amount} of amount {}",
+                features.get("amount"),
+                tx.amount
+            ));
+        }
+        let claimed = (
+            features.get("day").unwrap_or(usize::MAX),
+            features.get("time").unwrap_or(usize::MAX),
+        );
+        let earliest = self.clock_buckets(now.saturating_sub(self.clock_skew_secs));
+        let current = self.clock_buckets(now);
+        if claimed != earliest && claimed != current {
+            return Err(format!(
+                "Input day/time buckets {}/{} do not match the cosigner clock ({}/{})",
+                claimed.0, claimed.1, current.0, current.1
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the `velocity` and `budget` features against the sender's
+    /// approvals in `ledger` at `now`.
+    pub fn check_history(
+        &self,
+        features: &Features,
+        tx: &TxDetails,
+        ledger: &SpendLedger,
+        now: u64,
+    ) -> Result<(), String> {
+        let sender = tx.from.to_lowercase();
+        if let Some(velocity) = &self.velocity {
+            let count = ledger.approvals(&sender, now.saturating_sub(velocity.window));
+            let expected = velocity.bounds.partition_point(|&bound| bound < count);
+            self.check_derived(
+                features,
+                "velocity",
+                expected,
+                format!("{count} approvals in the last {}", velocity.label),
+            )?;
+        }
+        if let Some(budget) = &self.budget {
+            let token = tx.token.to_lowercase();
+            let total = *budget
+                .tokens
+                .get(&token)
+                .ok_or_else(|| format!("No budget is configured for token {}", tx.token))?;
+            let spent = ledger.spent(&sender, &token, None, now.saturating_sub(budget.window));
+            let left = total.saturating_sub(spent);
+            let top = (budget.buckets - 1) as u128;
+            let expected = (left.saturating_mul(top) / total) as usize;
+            self.check_derived(
+                features,
+                "budget",
+                expected,
+                format!("{left} of {total} left in the last {}", budget.label),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::SpendEntry;
+
+    const TOKEN: &str = "0x0000000000000000000000000000000000000001";
+    /// Wednesday 2024-05-01 12:00 UTC.
+    const WED_NOON: u64 = 1_714_564_800;
+
+    fn ledger(name: &str) -> SpendLedger {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        SpendLedger::open(&path, 7 * DAY_SECS, 0).unwrap()
+    }
+
+    fn vocab() -> Vocab {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../models/vocab.json");
+        Vocab::load(&path).unwrap()
+    }
+
+    fn features(amount: usize, day: usize, time: usize) -> Vec<(&'static str, usize)> {
+        vec![
+            ("budget", 15),
+            ("trust", 7),
+            ("amount", amount),
+            ("category", 0),
+            ("velocity", 2),
+            ("day", day),
+            ("time", time),
+        ]
+    }
+
+    fn tx(amount: &str) -> TxDetails {
+        TxDetails {
+            from: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            to: "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC".to_string(),
+            amount: amount.to_string(),
+            token: TOKEN.to_string(),
+            chain_id: 9745,
+        }
+    }
+
+    #[test]
+    fn test_decode_one_hot() {
+        let vocab = vocab();
+        assert_eq!(vocab.input_len, 64);
+        let decoded = vocab.decode(&vocab.encode(&features(8, 1, 1))).unwrap();
+        assert_eq!(decoded.get("amount"), Some(8));
+        assert_eq!(decoded.get("budget"), Some(15));
+        assert_eq!(decoded.get("risk"), None);
+
+        let mut missing = vocab.encode(&features(8, 1, 1));
+        missing[vocab.groups["amount"][8]] = 0;
+        assert!(vocab.decode(&missing).unwrap_err().contains("amount"));
+
+        let mut doubled = vocab.encode(&features(8, 1, 1));
+        doubled[vocab.groups["amount"][2]] = ONE;
+        assert!(vocab.decode(&doubled).unwrap_err().contains("2 hot slots"));
+
+        let mut scaled = vocab.encode(&features(8, 1, 1));
+        scaled[vocab.groups["time"][1]] = ONE / 2;
+        assert!(vocab.decode(&scaled).is_err());
+
+        assert!(vocab.decode(&[0; 63]).is_err());
+    }
+
+    #[test]
+    fn test_binding_amount_and_clock() {
+        let vocab = vocab();
+        let binding = InputBinding::parse(
+            format!(
+                r#"{{"amount_buckets": {{"{TOKEN}": ["100", "1000", "10000"]}},
+                    "clock_skew_secs": 600}}"#
+            )
+            .as_bytes(),
+            &vocab,
+        )
+        .unwrap();
+        let check = |amount: usize, day: usize, time: usize, tx_amount: &str, now: u64| {
+            let decoded = vocab
+                .decode(&vocab.encode(&features(amount, day, time)))
+                .unwrap();
+            binding.check(&decoded, &tx(tx_amount), now)
+        };
+
+        // Wednesday is day 2; noon opens the third six-hour block
+        assert!(check(0, 2, 2, "100", WED_NOON).is_ok());
+        assert!(check(1, 2, 2, "101", WED_NOON).is_ok());
+        assert!(check(3, 2, 2, "10001", WED_NOON).is_ok());
+        assert!(check(0, 2, 2, "10001", WED_NOON)
+            .unwrap_err()
+            .contains("amount bucket"));
+
+        // A proof made just before noon is still accepted, but not one for tomorrow
+        assert!(check(0, 2, 1, "1", WED_NOON).is_ok());
+        assert!(check(0, 2, 1, "1", WED_NOON + 601).is_err());
+        assert!(check(0, 3, 2, "1", WED_NOON)
+            .unwrap_err()
+            .contains("cosigner clock"));
+
+        let mut other = tx("1");
+        other.token = "0x0000000000000000000000000000000000000002".to_string();
+        let decoded = vocab.decode(&vocab.encode(&features(0, 2, 2))).unwrap();
+        assert!(binding.check(&decoded, &other, WED_NOON).is_err());
+
+        // Local time: noon UTC is Thursday 00:30 in UTC+12:30
+        let east = InputBinding::parse(
+            format!(r#"{{"amount_buckets": {{"{TOKEN}": ["100"]}}, "utc_offset_minutes": 750}}"#)
+                .as_bytes(),
+            &vocab,
+        )
+        .unwrap();
+        let decoded = vocab.decode(&vocab.encode(&features(0, 3, 0))).unwrap();
+        assert!(east.check(&decoded, &tx("1"), WED_NOON).is_ok());
+    }
+
+    #[test]
+    fn test_velocity_and_budget_from_ledger() {
+        let vocab = vocab();
+        let mut ledger = ledger("test_binding_history.jsonl");
+        let binding = InputBinding::parse(
+            format!(
+                r#"{{"amount_buckets": {{"{TOKEN}": ["100"]}},
+                    "velocity": {{"window": "24h", "bounds": [0, 1, 2, 4]}},
+                    "budget": {{"window": "7d", "tokens": {{"{TOKEN}": "1500"}}}},
+                    "tolerance": 1}}"#
+            )
+            .as_bytes(),
+            &vocab,
+        )
+        .unwrap();
+        assert_eq!(binding.lookback_secs(), 7 * DAY_SECS);
+        let check = |velocity: usize, budget: usize, ledger: &SpendLedger| {
+            let mut values = features(0, 2, 2);
+            values.retain(|&(f, _)| f != "velocity" && f != "budget");
+            values.extend([("velocity", velocity), ("budget", budget)]);
+            let decoded = vocab.decode(&vocab.encode(&values)).unwrap();
+            binding.check_history(&decoded, &tx("1"), ledger, WED_NOON)
+        };
+
+        // No history: velocity 0 and the whole budget left
+        assert!(check(0, 15, &ledger).is_ok());
+        assert!(check(1, 14, &ledger).is_ok());
+        let err = check(2, 15, &ledger).unwrap_err();
+        assert!(err.contains("velocity bucket 2"), "{err}");
+        assert!(err.contains("0 approvals in the last 24h"), "{err}");
+
+        // Two approvals today, and 600 of 1500 spent this week
+        let sender = tx("1");
+        for (ago, amount) in [(3 * DAY_SECS, 300), (2 * 3600, 200), (3, 100)] {
+            let entry = SpendEntry::new(&sender, amount, WED_NOON - ago);
+            ledger.append(&[entry]).unwrap();
+        }
+        assert!(check(2, 9, &ledger).is_ok());
+        assert!(check(3, 8, &ledger).is_ok());
+        assert!(check(0, 9, &ledger).is_err());
+        let err = check(2, 15, &ledger).unwrap_err();
+        assert!(err.contains("900 of 1500 left in the last 7d"), "{err}");
+    }
+
+    #[test]
+    fn test_invalid_bindings() {
+        let vocab = vocab();
+        for json in [
+            format!(r#"{{"amount_buckets": {{"{TOKEN}": []}}}}"#),
+            format!(r#"{{"amount_buckets": {{"{TOKEN}": ["10", "5"]}}}}"#),
+            format!(r#"{{"amount_buckets": {{"{TOKEN}": ["1e6"]}}}}"#),
+            format!(
+                r#"{{"amount_buckets": {{"{TOKEN}": {:?}}}}}"#,
+                (1..=16).map(|b| b.to_string()).collect::<Vec<_>>()
+            ),
+            r#"{"clock_skew_secs": 7200}"#.to_string(),
+            r#"{"utc_offset_minutes": 900}"#.to_string(),
+            r#"{"day_buckets": 7}"#.to_string(),
+            r#"{"tolerance": 4}"#.to_string(),
+            r#"{"velocity": {"window": "1w", "bounds": [1]}}"#.to_string(),
+            r#"{"velocity": {"window": "1h", "bounds": [2, 1]}}"#.to_string(),
+            r#"{"velocity": {"window": "1h", "bounds": [1, 2, 3, 4, 5, 6, 7, 8]}}"#.to_string(),
+            format!(r#"{{"budget": {{"window": "1d", "tokens": {{"{TOKEN}": "0"}}}}}}"#),
+        ] {
+            assert!(
+                InputBinding::parse(json.as_bytes(), &vocab).is_err(),
+                "{json}"
+            );
+        }
+    }
+}
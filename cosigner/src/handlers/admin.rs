@@ -0,0 +1,62 @@
+//! The signing key ring and its admin operations.
+
+use super::{authorize_bearer, not_found};
+use crate::{unix_now, AppState};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+
+/// Every key in the ring. Retired keys stay listed so approvals they
+/// signed can still be checked.
+pub async fn list_keys(data: web::Data<AppState>) -> HttpResponse {
+    let ring = data.key_ring.read().unwrap_or_else(|e| e.into_inner());
+    HttpResponse::Ok().json(serde_json::json!({
+        "primary": ring.primary().0,
+        "keys": ring.entries(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct PromoteKeyRequest {
+    key_id: String,
+}
+
+/// Make a standby key the primary and retire the current one.
+pub async fn promote_key(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<PromoteKeyRequest>,
+) -> HttpResponse {
+    if let Err(resp) = authorize_bearer(
+        data.admin_api_token.as_deref(),
+        "ADMIN_API_TOKEN",
+        &http_req,
+    ) {
+        return resp;
+    }
+    let now = unix_now();
+    let mut ring = data.key_ring.write().unwrap_or_else(|e| e.into_inner());
+    let retired = match ring.promote(&body.key_id, now) {
+        Ok(retired) => retired,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return not_found(&e.to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            return HttpResponse::Conflict().json(serde_json::json!({"error": e.to_string()}))
+        }
+        Err(e) => {
+            log::error!("Failed to persist key ring: {e}");
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "failed to persist key ring"}));
+        }
+    };
+    let (primary_id, primary) = ring.primary();
+    let primary_address = primary.address();
+    let reason = format!(
+        "Promoted key {primary_id} ({primary_address}); retired key {} ({})",
+        retired.key_id, retired.address
+    );
+    log::warn!("{reason}");
+    data.record_admin("key_promoted", reason, Some(primary_id.to_string()));
+    HttpResponse::Ok().json(serde_json::json!({
+        "primary": primary_id,
+        "retired": retired,
+    }))
+}
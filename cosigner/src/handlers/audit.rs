@@ -0,0 +1,175 @@
+//! The audit API: paging, export and archived proofs, behind
+//! `AUDIT_API_TOKEN`.
+
+use super::{authorize_bearer, not_found};
+use crate::AppState;
+use actix_web::{http::header::CONTENT_DISPOSITION, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use zkml_cosigner::audit::{self, AuditEntry, AuditQuery};
+
+/// Pagination for `GET /v1/audit/decisions`: entries after sequence number
+/// `after`, at most `limit` per page.
+#[derive(Deserialize)]
+pub struct AuditPage {
+    after: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct AuditExportFormat {
+    format: Option<String>,
+}
+
+pub const AUDIT_PAGE_DEFAULT: usize = 100;
+const AUDIT_PAGE_MAX: usize = 1000;
+
+/// Require `Authorization: Bearer <AUDIT_API_TOKEN>` on the audit API.
+pub fn authorize_audit(data: &AppState, req: &HttpRequest) -> Result<(), HttpResponse> {
+    authorize_bearer(data.audit_api_token.as_deref(), "AUDIT_API_TOKEN", req)
+}
+
+/// Up to `limit` audit entries after `after` that match `query`. The log is
+/// read (and its chain checked) on a blocking thread, from entry `after + 1`
+/// up to the last complete entry, stopping once `limit` have matched.
+pub async fn query_audit(
+    data: &AppState,
+    query: AuditQuery,
+    after: u64,
+    limit: Option<usize>,
+) -> Result<Vec<AuditEntry>, HttpResponse> {
+    let (path, start, last_seq) = {
+        let audit = data.audit.lock().unwrap_or_else(|e| e.into_inner());
+        let first = after.saturating_add(1);
+        (
+            audit.path().to_path_buf(),
+            audit.offset(first),
+            audit.head().seq,
+        )
+    };
+    let Some(offset) = start else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.unwrap_or(usize::MAX);
+    let entries = web::block(move || {
+        let mut entries = Vec::new();
+        audit::scan(&path, offset, after + 1, last_seq, |entry| {
+            if query.matches(&entry.record) {
+                entries.push(entry);
+            }
+            entries.len() < limit
+        })
+        .map(|()| entries)
+    })
+    .await;
+    match entries {
+        Ok(Ok(entries)) => Ok(entries),
+        Ok(Err(e)) => {
+            log::error!("Failed to read audit log: {e}");
+            Err(HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("failed to read audit log: {e}")})))
+        }
+        Err(e) => {
+            log::error!("Audit query failed: {e}");
+            Err(HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "audit query failed"})))
+        }
+    }
+}
+
+/// Page through audit entries matching the query-string filters.
+pub async fn list_audit(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    page: web::Query<AuditPage>,
+) -> HttpResponse {
+    if let Err(resp) = authorize_audit(&data, &http_req) {
+        return resp;
+    }
+    let limit = page
+        .limit
+        .unwrap_or(AUDIT_PAGE_DEFAULT)
+        .clamp(1, AUDIT_PAGE_MAX);
+    let after = page.after.unwrap_or(0);
+    let mut entries = match query_audit(&data, query.into_inner(), after, Some(limit + 1)).await {
+        Ok(entries) => entries,
+        Err(resp) => return resp,
+    };
+    let next_after = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|e| e.record.seq)
+    } else {
+        None
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "entries": entries,
+        "next_after": next_after,
+    }))
+}
+
+/// Download every matching audit entry as JSONL (default) or CSV.
+pub async fn export_audit(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    format: web::Query<AuditExportFormat>,
+) -> HttpResponse {
+    if let Err(resp) = authorize_audit(&data, &http_req) {
+        return resp;
+    }
+    let format = format.format.as_deref().unwrap_or("jsonl");
+    if format != "jsonl" && format != "csv" {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "format must be jsonl or csv"}));
+    }
+    let entries = match query_audit(&data, query.into_inner(), 0, None).await {
+        Ok(entries) => entries,
+        Err(resp) => return resp,
+    };
+    let mut body = Vec::new();
+    let content_type = if format == "csv" {
+        audit::write_csv(&entries, &mut body).expect("writing to a Vec cannot fail");
+        "text/csv"
+    } else {
+        for entry in &entries {
+            serde_json::to_writer(&mut body, entry).expect("AuditEntry serializes");
+            body.push(b'\n');
+        }
+        "application/x-ndjson"
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"audit.{format}\""),
+        ))
+        .body(body)
+}
+
+/// An archived proof with its `program_io` and every decision made on it.
+pub async fn get_proof(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    hash: web::Path<String>,
+) -> HttpResponse {
+    if let Err(resp) = authorize_audit(&data, &http_req) {
+        return resp;
+    }
+    let hash = hash.into_inner();
+    let data = data.into_inner();
+    let found = web::block(move || data.archive.get(&hash)).await;
+    match found {
+        Ok(Ok(Some(envelope))) => HttpResponse::Ok().json(envelope),
+        Ok(Ok(None)) => not_found("no archived proof with that hash"),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()}))
+        }
+        Ok(Err(e)) => {
+            log::error!("Failed to read proof archive: {e}");
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "proof archive unreadable"}))
+        }
+        Err(_) => HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error": "proof archive unavailable"})),
+    }
+}
@@ -0,0 +1,278 @@
+//! `POST /verify/batch`: several proofs in one call, with the approved
+//! items' nonces issued in a single store write.
+
+use super::verify::{
+    check_request, check_spend, ready_verifier, record_all, record_spend, refund_spend,
+    sign_approval, snark_verdict, CheckedRequest,
+};
+use crate::{
+    features::Features, idempotency, ledger::SpendEntry, nonce::NonceDomain, unix_now,
+    verify_pool::PoolError, AppState, Verdict, VerifyRequest,
+};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use zkml_cosigner::{approval::TxDetails, credential::TrustCredential};
+
+/// Body of `POST /verify/batch`: several proofs against one model.
+#[derive(Deserialize)]
+pub struct BatchVerifyRequest {
+    model_hash: String,
+    items: Vec<BatchItem>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchItem {
+    proof: String,
+    program_io: String,
+    tx: TxDetails,
+    trust_credential: Option<TrustCredential>,
+}
+
+/// Per-item result: the status `/verify` would have returned plus its body.
+#[derive(Serialize)]
+pub struct BatchItemResult {
+    status: u16,
+    #[serde(flatten)]
+    response: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct BatchVerifyResponse {
+    results: Vec<BatchItemResult>,
+}
+
+/// Verify several proofs in one call. Items are checked in parallel on the
+/// verification pool, then every approved item gets its nonce from a single
+/// store write, so a failure can't leave part of a batch signed.
+pub async fn verify_batch(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<BatchVerifyRequest>,
+) -> HttpResponse {
+    if http_req
+        .headers()
+        .contains_key(idempotency::IDEMPOTENCY_HEADER)
+    {
+        let verdict = Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            format!(
+                "{} is not supported on /verify/batch",
+                idempotency::IDEMPOTENCY_HEADER
+            ),
+        );
+        data.observe("batch", &verdict);
+        return verdict.into_response();
+    }
+    let BatchVerifyRequest { model_hash, items } = req.into_inner();
+    let reqs: Vec<VerifyRequest> = items
+        .into_iter()
+        .map(|item| VerifyRequest {
+            proof: item.proof,
+            program_io: item.program_io,
+            tx: item.tx,
+            model_hash: model_hash.clone(),
+            trust_credential: item.trust_credential,
+        })
+        .collect();
+    match evaluate_batch(&data, &reqs).await {
+        Ok(verdicts) => {
+            let results = record_all(&data, "batch", reqs.into_iter().zip(verdicts).collect())
+                .await
+                .into_iter()
+                .map(|verdict| BatchItemResult {
+                    status: verdict.status.as_u16(),
+                    response: serde_json::from_str(&verdict.body)
+                        .expect("Verdict body is a VerifyResponse"),
+                })
+                .collect();
+            HttpResponse::Ok().json(BatchVerifyResponse { results })
+        }
+        Err(verdict) => {
+            // Refused as a whole before any proof was looked at: metrics only
+            data.observe("batch", &verdict);
+            verdict.into_response()
+        }
+    }
+}
+
+/// Per-item verdicts for a batch, or one verdict refusing the whole batch.
+pub async fn evaluate_batch(
+    data: &AppState,
+    reqs: &[VerifyRequest],
+) -> Result<Vec<Verdict>, Verdict> {
+    if reqs.is_empty() || reqs.len() > data.batch_max_items {
+        return Err(Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            format!("Batch must contain 1-{} items", data.batch_max_items),
+        ));
+    }
+
+    let verifier = ready_verifier(data)?;
+
+    // Queue every SNARK check before awaiting any, so they run in parallel
+    let mut pending = Vec::with_capacity(reqs.len());
+    for req in reqs {
+        let queued = check_request(data, req).and_then(|checked| {
+            let verifier = Arc::clone(&verifier);
+            let CheckedRequest {
+                domain,
+                proof_bytes,
+                program_io,
+                features,
+            } = checked;
+            let metrics = Arc::clone(&data.metrics);
+            let in_flight = metrics.track_in_flight();
+            data.verify_pool
+                .submit(move || {
+                    let _in_flight = in_flight;
+                    verifier.check(&proof_bytes, program_io, &metrics)
+                })
+                .map(|rx| (domain, features, rx))
+                .map_err(|e| snark_verdict(data, Err(e)).unwrap_err())
+        });
+        pending.push(queued);
+    }
+
+    let mut verdicts: Vec<Option<Verdict>> = Vec::with_capacity(pending.len());
+    let mut approved = Vec::new();
+    for (i, queued) in pending.into_iter().enumerate() {
+        let outcome = match queued {
+            Ok((domain, features, rx)) => {
+                let outcome = rx.await.map_err(|_| PoolError::WorkerPanicked);
+                snark_verdict(data, outcome).map(|()| (domain, features))
+            }
+            Err(verdict) => Err(verdict),
+        };
+        match outcome {
+            Ok((domain, features)) => {
+                approved.push((i, domain, features));
+                verdicts.push(None);
+            }
+            Err(verdict) => verdicts.push(Some(verdict)),
+        }
+    }
+
+    let timestamp = unix_now();
+    let policy_version = data.policy.as_ref().map(|p| p.version());
+    for (i, nonce, spend) in issue_batch(data, reqs, approved, &mut verdicts) {
+        let signed = sign_approval(data, &reqs[i].tx, nonce, timestamp, policy_version).await;
+        verdicts[i] = Some(match signed {
+            Ok(body) => Verdict::approved(body),
+            Err(verdict) => {
+                refund_spend(data, spend.as_slice());
+                verdict
+            }
+        });
+    }
+
+    Ok(verdicts
+        .into_iter()
+        .map(|verdict| verdict.expect("every batch item has a verdict"))
+        .collect())
+}
+
+/// Record the spend of each verified batch item and issue nonces to those
+/// within the caps, under the locks. Returns the items to sign with their
+/// nonces and recorded spend; every other item gets its verdict.
+pub fn issue_batch(
+    data: &AppState,
+    reqs: &[VerifyRequest],
+    mut approved: Vec<(usize, NonceDomain, Features)>,
+    verdicts: &mut [Option<Verdict>],
+) -> Vec<(usize, u64, Option<SpendEntry>)> {
+    let mut state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+    let mut ledger = data.lock_ledger();
+    let now = unix_now();
+    // Items are recorded as they pass, so a batch can't split a transfer
+    // that is over a cap into pieces that each fit, and each item's velocity
+    // and budget inputs are checked against the items before it. Their spend
+    // is refunded if issuing the nonces then fails.
+    let mut spends = Vec::new();
+    approved.retain(|(i, _, features)| {
+        let (i, tx) = (*i, &reqs[*i].tx);
+        let recorded = check_spend(data, &ledger, tx, features, now)
+            .and_then(|amount| record_spend(&mut ledger, tx, amount, now));
+        match recorded {
+            Ok(spend) => {
+                spends.push(spend);
+                true
+            }
+            Err(verdict) => {
+                verdicts[i] = Some(verdict);
+                false
+            }
+        }
+    });
+    drop(ledger);
+
+    if approved.is_empty() {
+        return Vec::new();
+    }
+    let domains: Vec<NonceDomain> = approved.iter().map(|(_, d, _)| d.clone()).collect();
+    match state.next_nonces(&domains) {
+        Ok(nonces) => {
+            data.metrics.nonces_issued.inc_by(nonces.len() as u64);
+            approved
+                .into_iter()
+                .zip(nonces)
+                .zip(spends)
+                .map(|(((i, _, _), nonce), spend)| (i, nonce, spend))
+                .collect()
+        }
+        Err(e) => {
+            log::error!("Failed to persist nonce state: {e}");
+            let spends: Vec<SpendEntry> = spends.into_iter().flatten().collect();
+            refund_spend(data, &spends);
+            for (i, _, _) in approved {
+                verdicts[i] = Some(Verdict::rejected(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "nonce_store",
+                    format!("Internal error: failed to persist nonce: {e}"),
+                ));
+            }
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::testing::{self, StubVerifier, TOKEN},
+        policy::Policy,
+    };
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn test_nonces_go_to_the_approved_items() {
+        let dir = testing::test_dir("test_batch_nonces");
+        let verifier = Arc::new(StubVerifier::default());
+        let mut state = testing::state(&dir, verifier.clone());
+        let policy =
+            format!(r#"{{"version": "v1", "tokens": {{"{TOKEN}": {{"max_transfer": "100"}}}}}}"#);
+        state.policy = Some(Policy::parse(policy.as_bytes()).unwrap());
+        let data = web::Data::new(state);
+        let features = testing::features(&data.vocab);
+
+        let items: Vec<_> = ["1", "101", "2"]
+            .into_iter()
+            .map(|amount| testing::request(&data.vocab, testing::tx(amount), &features))
+            .collect();
+        let body = serde_json::json!({ "model_hash": testing::MODEL_HASH, "items": items });
+        let req = TestRequest::post().uri("/verify/batch").set_json(body);
+        let (status, body) = testing::call(&data, "/verify/batch", verify_batch, req).await;
+        assert_eq!(status, StatusCode::OK);
+        let results = body["results"].as_array().unwrap();
+        let statuses: Vec<_> = results.iter().map(|r| r["status"].clone()).collect();
+        assert_eq!(statuses, [200, 403, 200]);
+        assert_eq!(results[0]["nonce"], 1);
+        assert_eq!(results[1]["policy_rule"], "max_transfer");
+        assert!(results[1].get("nonce").is_none());
+        assert_eq!(results[2]["nonce"], 2);
+        assert_eq!(data.lock_ledger().len(), 2);
+    }
+}
@@ -0,0 +1,197 @@
+//! `POST /v1/cosign`, through which an M-of-N coordinator collects approvals
+//! of the same digest from independent cosigners.
+
+use super::{
+    authorize_bearer,
+    verify::{check_spend, record, record_spend, refund_spend, sign_approval, verify_request},
+};
+use crate::{
+    features::Features, ledger::SpendEntry, nonce::NonceDomain, unix_now, AppState, Verdict,
+    VerifyRequest,
+};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use zkml_cosigner::multisig::CosignRequest;
+
+/// How far a coordinator's timestamp may be from the cosigner's clock.
+pub const MAX_COSIGN_SKEW_SECS: u64 = 60;
+
+/// How far past the last issued nonce a coordinator may claim, so a bad
+/// coordinator can't exhaust a sender's nonces.
+pub const MAX_COSIGN_NONCE_GAP: u64 = 1_000;
+
+/// Check a coordinator request as `/verify` would, then sign it under the
+/// coordinator's nonce and timestamp if that nonce is still unused here.
+pub async fn evaluate_cosign(
+    data: &AppState,
+    req: &VerifyRequest,
+    nonce: u64,
+    timestamp: u64,
+) -> Verdict {
+    let now = unix_now();
+    if timestamp.abs_diff(now) > MAX_COSIGN_SKEW_SECS {
+        return Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "invalid_timestamp",
+            format!("Timestamp {timestamp} is more than {MAX_COSIGN_SKEW_SECS}s from cosigner time {now}"),
+        );
+    }
+    let (domain, features) = match verify_request(data, req).await {
+        Ok(verified) => verified,
+        Err(verdict) => return verdict,
+    };
+    let spend = match claim_cosign(data, req, &domain, &features, nonce, now) {
+        Ok(spend) => spend,
+        Err(verdict) => return verdict,
+    };
+
+    let policy_version = data.policy.as_ref().map(|p| p.version());
+    match sign_approval(data, &req.tx, nonce, timestamp, policy_version).await {
+        Ok(body) => Verdict::approved(body),
+        Err(verdict) => {
+            // The claimed nonce can't be signed again
+            refund_spend(data, spend.as_slice());
+            verdict
+        }
+    }
+}
+
+/// Record the spend and then claim the coordinator's `nonce`, under the
+/// locks, returning the spend recorded. The nonce is checked first, so a
+/// conflict records nothing; the spend is refunded if the claim then fails.
+pub fn claim_cosign(
+    data: &AppState,
+    req: &VerifyRequest,
+    domain: &NonceDomain,
+    features: &Features,
+    nonce: u64,
+    now: u64,
+) -> Result<Option<SpendEntry>, Verdict> {
+    let mut state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+    let mut ledger = data.lock_ledger();
+    let amount = check_spend(data, &ledger, &req.tx, features, now)?;
+    let nonce_store_failed = |e: std::io::Error| {
+        log::error!("Failed to persist nonce state: {e}");
+        Verdict::rejected(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "nonce_store",
+            format!("Internal error: failed to persist nonce: {e}"),
+        )
+    };
+    let current = state.current(domain).map_err(nonce_store_failed)?;
+    let conflict = || {
+        Verdict::rejected(
+            StatusCode::CONFLICT,
+            "nonce_conflict",
+            format!(
+                "Nonce {nonce} is already used or too far ahead; next is {}",
+                current + 1
+            ),
+        )
+    };
+    if nonce <= current || nonce > current.saturating_add(MAX_COSIGN_NONCE_GAP) {
+        return Err(conflict());
+    }
+    let spend = record_spend(&mut ledger, &req.tx, amount, now)?;
+    drop(ledger);
+    let refused = match state.claim_nonce(domain, nonce) {
+        Ok(true) => {
+            data.metrics.nonces_issued.inc();
+            return Ok(spend);
+        }
+        Ok(false) => conflict(),
+        Err(e) => nonce_store_failed(e),
+    };
+    refund_spend(data, spend.as_slice());
+    Err(refused)
+}
+
+/// `POST /v1/cosign`: `/verify` for an M-of-N coordinator, which picks the
+/// nonce and timestamp so every cosigner signs the same approval digest.
+pub async fn cosign(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CosignRequest>,
+) -> HttpResponse {
+    if let Err(resp) = authorize_bearer(
+        data.cosign_api_token.as_deref(),
+        "COSIGN_API_TOKEN",
+        &http_req,
+    ) {
+        return resp;
+    }
+    let CosignRequest {
+        proof,
+        program_io,
+        tx,
+        model_hash,
+        trust_credential,
+        nonce,
+        timestamp,
+    } = req.into_inner();
+    let req = VerifyRequest {
+        proof,
+        program_io,
+        tx,
+        model_hash,
+        trust_credential,
+    };
+    let verdict = evaluate_cosign(&data, &req, nonce, timestamp).await;
+    record(&data, "cosign", req, verdict).await.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::testing::{self, StubVerifier};
+    use actix_web::test::TestRequest;
+    use std::sync::Arc;
+
+    fn post(req: &VerifyRequest, nonce: u64, timestamp: u64) -> TestRequest {
+        let mut body = serde_json::to_value(req).unwrap();
+        body["nonce"] = nonce.into();
+        body["timestamp"] = timestamp.into();
+        TestRequest::post()
+            .uri("/v1/cosign")
+            .insert_header(("Authorization", "Bearer coordinator"))
+            .set_json(body)
+    }
+
+    #[actix_web::test]
+    async fn test_cosign_claims_the_coordinator_nonce() {
+        let dir = testing::test_dir("test_cosign_claims");
+        let mut state = testing::state(&dir, Arc::new(StubVerifier::default()));
+        state.cosign_api_token = Some("coordinator".to_string());
+        let data = web::Data::new(state);
+        let features = testing::features(&data.vocab);
+        let req = testing::request(&data.vocab, testing::tx("1"), &features);
+        let now = unix_now();
+
+        let unauthorized = post(&req, 1, now).insert_header(("Authorization", "Bearer other"));
+        let (status, _) = testing::call(&data, "/v1/cosign", cosign, unauthorized).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let skewed = post(&req, 1, now - MAX_COSIGN_SKEW_SECS - 5);
+        let (status, _) = testing::call(&data, "/v1/cosign", cosign, skewed).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            testing::last_reason_code(&data).as_deref(),
+            Some("invalid_timestamp")
+        );
+
+        // Nonces may skip ahead, but not reuse or run too far past the last
+        let (status, body) = testing::call(&data, "/v1/cosign", cosign, post(&req, 3, now)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["nonce"], 3);
+        assert_eq!(body["timestamp"], now);
+        for nonce in [3, 2, 4 + MAX_COSIGN_NONCE_GAP] {
+            let (status, _) =
+                testing::call(&data, "/v1/cosign", cosign, post(&req, nonce, now)).await;
+            assert_eq!(status, StatusCode::CONFLICT, "nonce {nonce}");
+            assert_eq!(
+                testing::last_reason_code(&data).as_deref(),
+                Some("nonce_conflict")
+            );
+        }
+        assert_eq!(data.lock_ledger().len(), 1);
+    }
+}
@@ -0,0 +1,103 @@
+//! Asynchronous verification jobs under `/v1/verifications`.
+
+use super::verify::{evaluate, record, IdempotencyClaim};
+use crate::{idempotency, jobs::JobView, unix_now, AppState, Verdict, VerifyRequest};
+use actix_web::{
+    http::{
+        header::{LOCATION, RETRY_AFTER},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use std::time::Duration;
+
+/// Queue a verification job and return 202 with its id; the result is polled
+/// from `GET /v1/verifications/{id}`. For callers whose proxies time out
+/// before a synchronous `/verify` finishes.
+pub async fn create_verification(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<VerifyRequest>,
+) -> HttpResponse {
+    let key = match idempotency::key_from_request(&http_req) {
+        Ok(key) => key,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    };
+    let now = unix_now();
+    if let Err(e) = data
+        .jobs
+        .prune(now.saturating_sub(data.jobs_retention_secs))
+    {
+        log::warn!("Failed to prune verification jobs: {e}");
+    }
+    let request = serde_json::to_value(&*req).expect("VerifyRequest serializes");
+    let job = match data.jobs.create(request, key, data.jobs_max_pending, now) {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            return HttpResponse::ServiceUnavailable()
+                .insert_header((RETRY_AFTER, data.verify_retry_after_secs.to_string()))
+                .json(serde_json::json!({"error": "too many pending verification jobs"}))
+        }
+        Err(e) => {
+            log::error!("Failed to persist verification job: {e}");
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "failed to persist verification job"}));
+        }
+    };
+    actix_web::rt::spawn(run_job(data.clone(), job.id.clone()));
+    HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/v1/verifications/{}", job.id)))
+        .json(JobView::from(&job))
+}
+
+pub async fn get_verification(data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    match data.jobs.get(&id) {
+        Some(job) => HttpResponse::Ok().json(JobView::from(&job)),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error": "unknown job"})),
+    }
+}
+
+/// Evaluate a queued job to completion. A full verification queue is waited
+/// out rather than reported, since nobody is holding a connection open.
+pub async fn run_job(data: web::Data<AppState>, id: String) {
+    let Some(job) = data.jobs.get(&id) else {
+        return;
+    };
+    let request = job
+        .request
+        .map(serde_json::from_value::<VerifyRequest>)
+        .and_then(Result::ok);
+    let verdict = match request {
+        Some(req) => {
+            if let Err(e) = data.jobs.mark_running(&id, unix_now()) {
+                log::warn!("Failed to mark job {id} running: {e}");
+            }
+            let claim = IdempotencyClaim::new(job.idempotency_key, &req);
+            let verdict = loop {
+                let verdict = evaluate(&data, &req, Some(claim.clone())).await;
+                match verdict.retry_after {
+                    Some(secs) => actix_web::rt::time::sleep(Duration::from_secs(secs)).await,
+                    None => break verdict,
+                }
+            };
+            record(&data, "job", req, verdict).await
+        }
+        None => {
+            let verdict = Verdict::rejected(
+                StatusCode::BAD_REQUEST,
+                "unreadable_job",
+                "Stored job request is unreadable",
+            );
+            data.observe("job", &verdict);
+            verdict
+        }
+    };
+    let result =
+        serde_json::from_str(&verdict.body).unwrap_or(serde_json::Value::String(verdict.body));
+    if let Err(e) = data
+        .jobs
+        .complete(&id, verdict.status.as_u16(), result, unix_now())
+    {
+        log::error!("Failed to record result of job {id}: {e}");
+    }
+}
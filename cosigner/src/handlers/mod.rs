@@ -0,0 +1,79 @@
+//! HTTP handlers, grouped by API area. The verification pipeline they share
+//! lives in [`verify`].
+
+use actix_web::{
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    HttpRequest, HttpResponse,
+};
+use subtle::ConstantTimeEq;
+
+pub mod admin;
+pub mod audit;
+pub mod batch;
+pub mod cosign;
+pub mod jobs;
+pub mod probes;
+#[cfg(test)]
+pub mod testing;
+pub mod transparency;
+pub mod two_party;
+pub mod verify;
+
+/// Require `Authorization: Bearer <token>`; 404 when `token` is unset.
+pub fn authorize_bearer(
+    token: Option<&str>,
+    env_var: &str,
+    req: &HttpRequest,
+) -> Result<(), HttpResponse> {
+    let Some(expected) = token else {
+        return Err(HttpResponse::NotFound()
+            .json(serde_json::json!({"error": format!("API is disabled; set {env_var}")})));
+    };
+    if bearer_matches(req, expected) {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+            .json(serde_json::json!({"error": "missing or invalid bearer token"})))
+    }
+}
+
+/// Whether `req` carries `Authorization: Bearer <expected>`, compared in
+/// constant time.
+pub fn bearer_matches(req: &HttpRequest, expected: &str) -> bool {
+    bearer_token(req).is_some_and(|token| bool::from(token.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+/// The token in `req`'s `Authorization: Bearer` header, if any.
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+pub fn not_found(msg: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({"error": msg}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_matches() {
+        use actix_web::test::TestRequest;
+        let req = |value: &str| {
+            TestRequest::default()
+                .insert_header((AUTHORIZATION, value))
+                .to_http_request()
+        };
+        assert!(bearer_matches(&req("Bearer s3cret"), "s3cret"));
+        assert!(!bearer_matches(&req("Bearer s3cre"), "s3cret"));
+        assert!(!bearer_matches(&req("s3cret"), "s3cret"));
+        assert!(!bearer_matches(
+            &TestRequest::default().to_http_request(),
+            "s3cret"
+        ));
+    }
+}
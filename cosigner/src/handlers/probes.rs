@@ -0,0 +1,110 @@
+//! Liveness, readiness, status and metrics endpoints.
+
+use crate::{AppState, JOLT_TABLE_SIZE};
+use actix_web::{web, HttpResponse};
+
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+}
+
+pub async fn metrics_endpoint(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(data.metrics.render())
+}
+
+/// Liveness: the process is up and serving HTTP.
+pub async fn livez() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"status": "alive"}))
+}
+
+/// Readiness: preprocessing has finished and the nonce store is readable, so
+/// `/verify` can be served.
+pub async fn readyz(data: web::Data<AppState>) -> HttpResponse {
+    let verifier = data.verifier.get().is_some();
+    let nonce_store = {
+        let state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+        state.domain_count().is_ok()
+    };
+    let body = serde_json::json!({
+        "ready": verifier && nonce_store,
+        "checks": {"verifier": verifier, "nonce_store": nonce_store},
+    });
+    if verifier && nonce_store {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Deployment details for dashboards and on-call.
+pub async fn status(data: web::Data<AppState>) -> HttpResponse {
+    let nonces = {
+        let state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .domain_count()
+            .and_then(|domains| Ok((domains, state.total_issued()?)))
+    };
+    let nonces = match nonces {
+        Ok((domains, issued)) => serde_json::json!({"domains": domains, "issued": issued}),
+        Err(e) => {
+            log::error!("Failed to read nonce state: {e}");
+            serde_json::Value::Null
+        }
+    };
+    let (key_id, signer_address) = {
+        let ring = data.key_ring.read().unwrap_or_else(|e| e.into_inner());
+        let (key_id, signer) = ring.primary();
+        (key_id.to_string(), signer.address().to_string())
+    };
+    let policy_version = data.policy.as_ref().map(|p| p.version());
+    let verifier = data.verifier.get();
+    HttpResponse::Ok().json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": option_env!("GIT_COMMIT"),
+        "ready": verifier.is_some(),
+        "model_hash": data.model_hash,
+        "jolt_table_size": JOLT_TABLE_SIZE,
+        "signer_address": signer_address,
+        "key_id": key_id,
+        "policy_version": policy_version,
+        "nonces": nonces,
+        "preprocessing_secs": verifier.map(|v| v.prepared_in.as_secs_f64()),
+        "uptime_secs": data.started_at.elapsed().as_secs(),
+        "verify_pool": {
+            "workers": data.verify_pool.workers(),
+            "queue_depth": data.verify_pool.queue_depth(),
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_health_endpoint() {
+        use actix_web::test;
+
+        let app =
+            test::init_service(actix_web::App::new().route("/health", web::get().to(health))).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 200);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body, serde_json::json!({"status": "ok"}));
+    }
+
+    #[actix_web::test]
+    async fn test_livez_endpoint() {
+        use actix_web::test;
+        let app =
+            test::init_service(actix_web::App::new().route("/livez", web::get().to(livez))).await;
+        let req = test::TestRequest::get().uri("/livez").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+}
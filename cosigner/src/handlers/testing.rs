@@ -0,0 +1,194 @@
+//! A cosigner over temporary files with a stand-in proof verifier, for
+//! handler tests.
+
+use super::verify::{ProofRejection, ProofVerifier};
+use crate::{
+    features::Vocab, idempotency, jobs::JobStore, ledger::SpendLedger, metrics::Metrics,
+    nonce::FileNonceStore, policy, unix_now, verify_pool::VerifyPool, AppState, Verifier,
+    VerifyRequest,
+};
+use actix_web::{http::StatusCode, test, web, App, FromRequest, Handler, Responder};
+use k256::ecdsa::SigningKey;
+use onnx_tracer::{tensor::Tensor, ProgramIO};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use zkml_cosigner::{
+    approval::TxDetails,
+    archive::ProofArchive,
+    audit::{self, AuditLog},
+    keyring::KeyRing,
+    signer::{LocalSigner, Signer},
+    transparency::TransparencyLog,
+};
+
+pub const FROM: &str = "0x70997970c51812dc3a010c7d01b50e0d17dc79c8";
+pub const TO: &str = "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc";
+pub const TOKEN: &str = "0x0000000000000000000000000000000000000001";
+pub const MODEL_HASH: &str = "test-model";
+
+/// Accepts every proof and counts the checks, so a test can tell whether a
+/// request got as far as the SNARK.
+#[derive(Default)]
+pub struct StubVerifier {
+    checks: AtomicUsize,
+}
+
+impl StubVerifier {
+    pub fn checks(&self) -> usize {
+        self.checks.load(Ordering::SeqCst)
+    }
+}
+
+impl ProofVerifier for StubVerifier {
+    fn check(&self, _: &[u8], _: ProgramIO, _: &Metrics) -> Result<(), ProofRejection> {
+        self.checks.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// The directory test `name` keeps its files in, emptied.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write `contents` to `file` in `dir`.
+pub fn write(dir: &Path, file: &str, contents: &str) -> PathBuf {
+    let path = dir.join(file);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// A cosigner keeping its files in `dir`, with every optional check off and
+/// `verifier` checking proofs.
+pub fn state(dir: &Path, verifier: Arc<StubVerifier>) -> AppState {
+    let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+    let signer: Arc<dyn Signer> = Arc::new(LocalSigner::new(key));
+    let vocab =
+        Vocab::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../models/vocab.json")).unwrap();
+    let verifier_ready = OnceLock::new();
+    let _ = verifier_ready.set(Verifier {
+        snark: verifier,
+        prepared_in: Duration::ZERO,
+    });
+    AppState {
+        verifier: verifier_ready,
+        started_at: Instant::now(),
+        verify_pool: VerifyPool::new(2, 16),
+        verify_retry_after_secs: 5,
+        key_ring: RwLock::new(
+            KeyRing::open(dir.join("key_ring.json"), vec![signer], unix_now()).unwrap(),
+        ),
+        admin_api_token: None,
+        cosign_api_token: None,
+        nonce_state: Mutex::new(Box::new(
+            FileNonceStore::open(dir.join("nonce_state.json")).unwrap(),
+        )),
+        model_hash: MODEL_HASH.to_string(),
+        idempotency_ttl_secs: idempotency::DEFAULT_TTL_SECS,
+        jobs: JobStore::open(dir.join("jobs")).unwrap(),
+        jobs_max_pending: 16,
+        jobs_retention_secs: 3600,
+        batch_max_items: 16,
+        metrics: Arc::new(Metrics::new()),
+        audit: Mutex::new(AuditLog::open(dir.join("audit_log.jsonl")).unwrap()),
+        audit_api_token: None,
+        transparency: Mutex::new(
+            TransparencyLog::open(dir.join("transparency_log.jsonl")).unwrap(),
+        ),
+        archive: ProofArchive::open(dir.join("proof_archive")).unwrap(),
+        archive_retention_secs: 3600,
+        vocab,
+        input_binding: None,
+        trust_issuers: None,
+        merchant_directory: None,
+        denylists: Vec::new(),
+        policy: None,
+        spend_ledger: Mutex::new(
+            SpendLedger::open(dir.join("spend_ledger.jsonl"), 7 * 24 * 60 * 60, unix_now())
+                .unwrap(),
+        ),
+        two_party: None,
+    }
+}
+
+pub fn tx(amount: &str) -> TxDetails {
+    TxDetails {
+        from: FROM.to_string(),
+        to: TO.to_string(),
+        amount: amount.to_string(),
+        token: TOKEN.to_string(),
+        chain_id: 9745,
+    }
+}
+
+/// Feature values for an input proved now: the smallest amount, no history,
+/// and the UTC clock buckets.
+pub fn features(vocab: &Vocab) -> Vec<(&'static str, usize)> {
+    let now = unix_now();
+    let block = 24 * 60 * 60 / vocab.buckets("time").unwrap() as u64;
+    vec![
+        ("budget", vocab.buckets("budget").unwrap() - 1),
+        ("trust", 7),
+        ("amount", 0),
+        ("category", 0),
+        ("velocity", 0),
+        ("day", policy::weekday(now) as usize),
+        ("time", (now % (24 * 60 * 60) / block) as usize),
+    ]
+}
+
+/// A request for `tx` whose proof the model authorized on an input encoding
+/// `features`.
+pub fn request(vocab: &Vocab, tx: TxDetails, features: &[(&str, usize)]) -> VerifyRequest {
+    let input = vocab.encode(features);
+    let program_io = ProgramIO {
+        input: Tensor::new(Some(&input), &[1, input.len()]).unwrap(),
+        output: Tensor::new(Some(&[100, 0]), &[1, 2]).unwrap(),
+    };
+    VerifyRequest {
+        proof: "00".to_string(),
+        program_io: serde_json::to_string(&program_io).unwrap(),
+        tx,
+        model_hash: MODEL_HASH.to_string(),
+        trust_credential: None,
+    }
+}
+
+/// Send `req` to `handler` mounted at `pattern`; returns the status and the
+/// JSON body.
+pub async fn call<F, Args>(
+    data: &web::Data<AppState>,
+    pattern: &str,
+    handler: F,
+    req: test::TestRequest,
+) -> (StatusCode, serde_json::Value)
+where
+    F: Handler<Args>,
+    Args: FromRequest + 'static,
+    F::Output: Responder + 'static,
+{
+    let app = test::init_service(
+        App::new()
+            .app_data(data.clone())
+            .route(pattern, web::route().to(handler)),
+    )
+    .await;
+    let resp = test::call_service(&app, req.to_request()).await;
+    let status = resp.status();
+    (status, test::read_body_json(resp).await)
+}
+
+/// Reason code of the last decision in the audit log.
+pub fn last_reason_code(data: &AppState) -> Option<String> {
+    let path = data.audit.lock().unwrap().path().to_path_buf();
+    audit::read_log(&path).unwrap().pop()?.record.reason_code
+}
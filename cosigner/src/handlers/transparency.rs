@@ -0,0 +1,93 @@
+//! Signed transparency roots and the proofs checked against them.
+
+use super::not_found;
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct InclusionQuery {
+    chain_id: u64,
+    sender: String,
+    nonce: u64,
+    /// Published tree size to prove against; defaults to the latest root.
+    tree_size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ConsistencyQuery {
+    first: u64,
+    /// Defaults to the latest published tree size.
+    second: Option<u64>,
+}
+
+/// The latest signed transparency root.
+pub async fn transparency_root(data: web::Data<AppState>) -> HttpResponse {
+    let log = data.transparency.lock().unwrap_or_else(|e| e.into_inner());
+    match log.latest_root() {
+        Some(root) => HttpResponse::Ok().json(root),
+        None => not_found("no root published yet"),
+    }
+}
+
+/// The signed root published for a given tree size.
+pub async fn transparency_root_at(data: web::Data<AppState>, size: web::Path<u64>) -> HttpResponse {
+    let log = data.transparency.lock().unwrap_or_else(|e| e.into_inner());
+    match log.root_at(*size) {
+        Some(root) => HttpResponse::Ok().json(root),
+        None => not_found("no root published at that tree size"),
+    }
+}
+
+/// Inclusion proof for the approval of `sender`'s `nonce` in a published tree.
+pub async fn transparency_inclusion(
+    data: web::Data<AppState>,
+    query: web::Query<InclusionQuery>,
+) -> HttpResponse {
+    let log = data.transparency.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(index) = log.find(query.chain_id, &query.sender, query.nonce) else {
+        return not_found("no approval for that chain, sender and nonce");
+    };
+    let root = match query.tree_size {
+        Some(size) => log.root_at(size),
+        None => log.latest_root(),
+    };
+    let Some(root) = root else {
+        return not_found("no root published at that tree size");
+    };
+    if index as u64 >= root.tree_size {
+        return not_found("approval is not in a published root yet");
+    }
+    let proof = log.inclusion_proof(index, root.tree_size as usize);
+    HttpResponse::Ok().json(serde_json::json!({
+        "leaf_index": index,
+        "leaf_hash": hex::encode(log.leaf(index).expect("indexed leaf exists")),
+        "root": root,
+        "audit_path": proof.iter().map(hex::encode).collect::<Vec<_>>(),
+    }))
+}
+
+/// Consistency proof between two published roots.
+pub async fn transparency_consistency(
+    data: web::Data<AppState>,
+    query: web::Query<ConsistencyQuery>,
+) -> HttpResponse {
+    let log = data.transparency.lock().unwrap_or_else(|e| e.into_inner());
+    let second = match query.second {
+        Some(size) => log.root_at(size),
+        None => log.latest_root(),
+    };
+    let (Some(first), Some(second)) = (log.root_at(query.first), second) else {
+        return not_found("both tree sizes must have published roots");
+    };
+    if first.tree_size > second.tree_size {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "first must not exceed second"}));
+    }
+    let proof = log.consistency_proof(first.tree_size as usize, second.tree_size as usize);
+    HttpResponse::Ok().json(serde_json::json!({
+        "first": first,
+        "second": second,
+        "proof": proof.iter().map(hex::encode).collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,520 @@
+//! Two-party ECDSA sessions: key generation and signing of transfers from
+//! the shared address, after the same checks as `/verify`.
+
+use super::{
+    bearer_token, not_found,
+    verify::{check_spend, record, record_spend, refund_spend, verify_request},
+};
+use crate::{
+    features::Features,
+    ledger::SpendEntry,
+    sessions::{self, Protocol, Ticket, TwoParty},
+    unix_now, AppState, Verdict, VerifyRequest, VerifyResponse,
+};
+use actix_web::{
+    http::{header::WWW_AUTHENTICATE, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use serde::Deserialize;
+use zkml_cosigner::{
+    approval::TxDetails,
+    credential::TrustCredential,
+    two_party::{self, Batch, KeyShare, KeygenRequest, Opened, Round, TransferTx, WireMessage},
+};
+
+/// How far a key generation request's timestamp may be from the cosigner's
+/// clock, so a signed request can't be replayed later.
+pub const MAX_KEYGEN_SKEW_SECS: u64 = 60;
+
+/// Largest batch of client messages. Key generation messages carry Paillier
+/// proofs.
+pub const MAX_BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// Body of `POST /v1/two-party/sessions`: a `/verify` request for a transfer
+/// from the two-party address, and the transaction to sign it in.
+#[derive(Deserialize)]
+pub struct TwoPartyRequest {
+    proof: String,
+    program_io: String,
+    tx: TxDetails,
+    model_hash: String,
+    #[serde(default)]
+    trust_credential: Option<TrustCredential>,
+    transaction: TransferTx,
+}
+
+pub fn two_party_disabled() -> HttpResponse {
+    not_found("Two-party signing is disabled; set TWO_PARTY_KEY_DIR")
+}
+
+pub fn too_many_sessions(data: &AppState) -> Verdict {
+    Verdict {
+        retry_after: Some(data.verify_retry_after_secs),
+        ..Verdict::rejected(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "too_many_sessions",
+            "Too many two-party sessions in progress, retry later",
+        )
+    }
+}
+
+/// Run one exchange of a two-party session on a blocking thread, since a
+/// round can take a minute. A session is dropped once it finishes or fails;
+/// a finished key generation installs the cosigner's new share.
+pub async fn exchange_session(
+    data: &web::Data<AppState>,
+    id: String,
+    messages: Vec<WireMessage>,
+) -> Result<Round, HttpResponse> {
+    let state = data.clone();
+    let exchanged = web::block(move || state.exchange_session_blocking(id, messages)).await;
+    let (status, error) = match exchanged {
+        Ok(Ok(round)) => return Ok(round),
+        Ok(Err(e)) => e,
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Internal error: {e}"),
+        ),
+    };
+    Err(HttpResponse::build(status).json(serde_json::json!({"error": error})))
+}
+
+/// `POST /v1/two-party/keygen`: open a session generating a two-party key for
+/// the wallet that signed the request. The cosigner saves its share under the
+/// new key's address in `TWO_PARTY_KEY_DIR` when the session finishes; the
+/// wallet keeps the other.
+pub async fn open_two_party_keygen(
+    data: web::Data<AppState>,
+    req: web::Json<KeygenRequest>,
+) -> HttpResponse {
+    let Some(two_party) = &data.two_party else {
+        return two_party_disabled();
+    };
+    let now = unix_now();
+    let refused = if req.timestamp.abs_diff(now) > MAX_KEYGEN_SKEW_SECS {
+        Some(format!(
+            "Timestamp {} is more than {MAX_KEYGEN_SKEW_SECS}s from cosigner time {now}",
+            req.timestamp
+        ))
+    } else {
+        req.verify().err()
+    };
+    if let Some(error) = refused {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": error}));
+    }
+    let owner = req.into_inner().owner.to_lowercase();
+    let opened = two_party.sessions.open(|id| Protocol::Keygen {
+        owner,
+        party: two_party::keygen(two_party::COSIGNER, id),
+    });
+    let Some(ticket) = opened else {
+        return too_many_sessions(&data).into_response();
+    };
+    match exchange_session(&data, ticket.id.clone(), Vec::new()).await {
+        Ok(round) => HttpResponse::Ok().json(Opened {
+            session_id: ticket.id,
+            session_token: ticket.token,
+            signing_hash: None,
+            round,
+        }),
+        Err(resp) => resp,
+    }
+}
+
+/// `POST /v1/two-party/sessions`: check a transfer from the two-party address
+/// as `/verify` would and log the approval, then start signing its
+/// transaction. The cosigner sends no signing message before the approval
+/// is in the audit log. Its spend is refunded if the session fails before
+/// the cosigner's first message, and stays counted after: from the
+/// cosigner's last messages the client can finish the signature alone.
+pub async fn open_two_party_session(
+    data: web::Data<AppState>,
+    req: web::Json<TwoPartyRequest>,
+) -> HttpResponse {
+    let Some(two_party) = &data.two_party else {
+        return two_party_disabled();
+    };
+    let TwoPartyRequest {
+        proof,
+        program_io,
+        tx,
+        model_hash,
+        trust_credential,
+        transaction,
+    } = req.into_inner();
+    let req = VerifyRequest {
+        proof,
+        program_io,
+        tx,
+        model_hash,
+        trust_credential,
+    };
+    let mut session = None;
+    let mut spend = None;
+    let verdict = match open_signing_session(&data, two_party, &req.tx, &transaction) {
+        Ok(opened) => {
+            session = Some(opened);
+            let (verdict, recorded) = evaluate_two_party(&data, &req, &transaction).await;
+            spend = recorded;
+            verdict
+        }
+        Err(verdict) => verdict,
+    };
+    let verdict = record(&data, "two_party", req, verdict).await;
+    let Some((ticket, signing_hash)) = session else {
+        return verdict.into_response();
+    };
+    if verdict.status != StatusCode::OK {
+        two_party.sessions.take(&ticket.id);
+        refund_spend(&data, spend.as_slice());
+        return verdict.into_response();
+    }
+    match exchange_session(&data, ticket.id.clone(), Vec::new()).await {
+        Ok(round) => HttpResponse::Ok().json(Opened {
+            session_id: ticket.id,
+            session_token: ticket.token,
+            signing_hash: Some(hex::encode(signing_hash)),
+            round,
+        }),
+        Err(resp) => {
+            refund_spend(&data, spend.as_slice());
+            resp
+        }
+    }
+}
+
+/// Open a session signing `transaction` with the cosigner's share. It sends
+/// nothing until its first exchange.
+pub fn open_signing_session(
+    data: &AppState,
+    two_party: &TwoParty,
+    tx: &TxDetails,
+    transaction: &TransferTx,
+) -> Result<(Ticket, [u8; 32]), Verdict> {
+    let Some(share) = two_party.share(&tx.from) else {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "wrong_sender",
+            format!("Sender {} is not a two-party address", tx.from),
+        ));
+    };
+    let hash = transaction
+        .signing_hash(tx)
+        .map_err(|e| Verdict::rejected(StatusCode::BAD_REQUEST, "invalid_tx", e))?;
+    two_party
+        .sessions
+        .open(|id| Protocol::Signing(two_party::sign(two_party::COSIGNER, id, share, hash)))
+        .map(|ticket| (ticket, hash))
+        .ok_or_else(|| too_many_sessions(data))
+}
+
+/// Run every `/verify` check on a two-party transfer and record its spend,
+/// returning the verdict and the spend recorded. No cosigner nonce is
+/// issued: the transaction's account nonce stops the signature being
+/// replayed, and the response carries that instead.
+pub async fn evaluate_two_party(
+    data: &AppState,
+    req: &VerifyRequest,
+    transaction: &TransferTx,
+) -> (Verdict, Option<SpendEntry>) {
+    let features = match verify_request(data, req).await {
+        Ok((_, features)) => features,
+        Err(verdict) => return (verdict, None),
+    };
+    let now = unix_now();
+    let spend = match record_two_party_spend(data, &req.tx, &features, now) {
+        Ok(spend) => spend,
+        Err(verdict) => return (verdict, None),
+    };
+    let verdict = Verdict::approved(
+        serde_json::to_string(&VerifyResponse {
+            approved: true,
+            nonce: Some(transaction.nonce),
+            timestamp: Some(now),
+            policy_version: data.policy.as_ref().map(|p| p.version().to_string()),
+            ..VerifyResponse::default()
+        })
+        .expect("VerifyResponse serializes"),
+    );
+    (verdict, spend)
+}
+
+/// Check and record the spend of a two-party transfer, under the ledger lock.
+pub fn record_two_party_spend(
+    data: &AppState,
+    tx: &TxDetails,
+    features: &Features,
+    now: u64,
+) -> Result<Option<SpendEntry>, Verdict> {
+    let mut ledger = data.lock_ledger();
+    let amount = check_spend(data, &ledger, tx, features, now)?;
+    record_spend(&mut ledger, tx, amount, now)
+}
+
+/// `POST /v1/two-party/sessions/{id}`: the client's next messages in a
+/// session, answered with the cosigner's. Needs the session's token as a
+/// bearer token, checked before the body is read.
+pub async fn continue_two_party_session(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    http_req: HttpRequest,
+    payload: web::Payload,
+) -> HttpResponse {
+    let Some(two_party) = &data.two_party else {
+        return two_party_disabled();
+    };
+    if !bearer_token(&http_req).is_some_and(|token| two_party.sessions.authorized(&id, token)) {
+        return HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+            .json(serde_json::json!({"error": "Unknown, expired or busy session, or a wrong session token"}));
+    }
+    let body = match payload.to_bytes_limited(MAX_BATCH_BYTES).await {
+        Ok(Ok(body)) => body,
+        Ok(Err(e)) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": format!("Failed to read body: {e}")}))
+        }
+        Err(_) => {
+            return HttpResponse::PayloadTooLarge().json(
+                serde_json::json!({"error": format!("Batch is over {MAX_BATCH_BYTES} bytes")}),
+            )
+        }
+    };
+    let batch: Batch = match serde_json::from_slice(&body) {
+        Ok(batch) => batch,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": format!("Invalid batch: {e}")}))
+        }
+    };
+    match exchange_session(&data, id.into_inner(), batch.messages).await {
+        Ok(round) => HttpResponse::Ok().json(round),
+        Err(resp) => resp,
+    }
+}
+
+impl AppState {
+    /// Hand two-party session `id` the client's `messages` and wait for the
+    /// cosigner's answer; see [`exchange_session`].
+    fn exchange_session_blocking(
+        &self,
+        id: String,
+        messages: Vec<WireMessage>,
+    ) -> Result<Round, (StatusCode, String)> {
+        let two_party = self.two_party.as_ref().ok_or((
+            StatusCode::NOT_FOUND,
+            "Two-party signing is disabled".to_string(),
+        ))?;
+        let session = two_party.sessions.take(&id).ok_or((
+            StatusCode::NOT_FOUND,
+            "Unknown, expired or busy session".to_string(),
+        ))?;
+        let failed = |e: String| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Two-party protocol failed: {e}"),
+            )
+        };
+        let round = match &session.protocol {
+            Protocol::Signing(party) => party.exchange(messages).map_err(failed)?.round(),
+            Protocol::Keygen { owner, party } => {
+                let answer = party.exchange(messages).map_err(failed)?;
+                let round = answer.round();
+                if let Some(share) = answer.output {
+                    self.install_share(two_party, owner, share)?;
+                }
+                round
+            }
+        };
+        if !round.finished {
+            two_party.sessions.put_back(id, session);
+        }
+        Ok(round)
+    }
+
+    /// Save the cosigner's share from a finished key generation.
+    fn install_share(
+        &self,
+        two_party: &TwoParty,
+        owner: &str,
+        share: KeyShare,
+    ) -> Result<(), (StatusCode, String)> {
+        let address = two_party.install(share).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                return (StatusCode::CONFLICT, e.to_string());
+            }
+            log::error!("Failed to save two-party key share: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal error: failed to save the key share".to_string(),
+            )
+        })?;
+        let reason = format!(
+            "Generated two-party key {address} for {owner}; cosigner share saved to {}",
+            sessions::share_path(&two_party.dir, &address).display()
+        );
+        log::warn!("{reason}");
+        self.record_admin("two_party_keygen", reason, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::testing::{self, StubVerifier},
+        sessions::Sessions,
+    };
+    use actix_web::test::TestRequest;
+    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+    use std::{path::Path, sync::mpsc, sync::Arc, time::Duration};
+    use zeroize::Zeroizing;
+    use zkml_cosigner::two_party::RecoverableSignature;
+
+    /// Address of the key whose shares are in `testdata/two-party`, saved
+    /// under `PASSPHRASE`.
+    const SHARED: &str = "0xf7a9a82f3a15fac28d142a1f23a5a82f9aece645";
+    const PASSPHRASE: &str = "correct horse";
+
+    fn testdata(party: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/two-party")
+            .join(format!("{party}.json"))
+    }
+
+    /// A cosigner holding the fixture key's cosigner share.
+    fn state(name: &str) -> web::Data<AppState> {
+        let dir = testing::test_dir(name);
+        let mut state = testing::state(&dir, Arc::new(StubVerifier::default()));
+        let shares = dir.join("two-party");
+        std::fs::create_dir_all(&shares).unwrap();
+        std::fs::copy(testdata("cosigner"), sessions::share_path(&shares, SHARED)).unwrap();
+        let sessions = Sessions::new(4, Duration::from_secs(120));
+        state.two_party =
+            Some(TwoParty::open(shares, Zeroizing::new(PASSPHRASE.to_string()), sessions).unwrap());
+        web::Data::new(state)
+    }
+
+    fn transaction() -> TransferTx {
+        TransferTx {
+            nonce: 7,
+            max_priority_fee_per_gas: "1000000000".to_string(),
+            max_fee_per_gas: "30000000000".to_string(),
+            gas_limit: 65_000,
+        }
+    }
+
+    async fn open(data: &web::Data<AppState>, tx: TxDetails) -> (StatusCode, serde_json::Value) {
+        let req = testing::request(&data.vocab, tx, &testing::features(&data.vocab));
+        let mut body = serde_json::to_value(&req).unwrap();
+        body["transaction"] = serde_json::to_value(transaction()).unwrap();
+        let req = TestRequest::post()
+            .uri("/v1/two-party/sessions")
+            .set_json(body);
+        testing::call(data, "/v1/two-party/sessions", open_two_party_session, req).await
+    }
+
+    /// Sign `hash` as the client in session `opened`, sending its messages
+    /// through [`continue_two_party_session`].
+    async fn run_session(
+        data: &web::Data<AppState>,
+        opened: Opened,
+        hash: [u8; 32],
+    ) -> Result<RecoverableSignature, String> {
+        let share = Arc::new(two_party::load_share(&testdata("client"), PASSPHRASE).unwrap());
+        let party = two_party::sign(two_party::CLIENT, &opened.session_id, share, hash);
+        let (to_cosigner, batches) = mpsc::channel();
+        let (answers, from_cosigner) = mpsc::channel();
+        let first = opened.round;
+        let client = std::thread::spawn(move || {
+            two_party::run_client(
+                party,
+                || Ok(first),
+                |messages| {
+                    to_cosigner.send(messages).map_err(|e| e.to_string())?;
+                    from_cosigner.recv().map_err(|e| e.to_string())?
+                },
+            )
+        });
+        // Blocks this thread only while the client works on its next batch
+        while let Ok(messages) = batches.recv() {
+            let req = TestRequest::post()
+                .uri(&format!("/v1/two-party/sessions/{}", opened.session_id))
+                .insert_header(("Authorization", format!("Bearer {}", opened.session_token)))
+                .set_json(Batch { messages });
+            let (status, body) = testing::call(
+                data,
+                "/v1/two-party/sessions/{id}",
+                continue_two_party_session,
+                req,
+            )
+            .await;
+            let answer = match status {
+                StatusCode::OK => Ok(serde_json::from_value(body).unwrap()),
+                _ => Err(format!("{status}: {}", body["error"])),
+            };
+            let _ = answers.send(answer);
+        }
+        client.join().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_wrong_sender() {
+        let data = state("test_two_party_wrong_sender");
+        let (status, _) = open(&data, testing::tx("1")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            testing::last_reason_code(&data).as_deref(),
+            Some("wrong_sender")
+        );
+        assert_eq!(data.lock_ledger().len(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_signing_session() {
+        let data = state("test_two_party_signing");
+        let mut tx = testing::tx("1");
+        tx.from = SHARED.to_string();
+        let hash = transaction().signing_hash(&tx).unwrap();
+
+        let (status, body) = open(&data, tx.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let opened: Opened = serde_json::from_value(body).unwrap();
+        assert_eq!(opened.signing_hash, Some(hex::encode(hash)));
+
+        // Only the session's token can continue it
+        let stranger = TestRequest::post()
+            .uri(&format!("/v1/two-party/sessions/{}", opened.session_id))
+            .insert_header(("Authorization", "Bearer 00"))
+            .set_json(Batch {
+                messages: Vec::new(),
+            });
+        let (status, _) = testing::call(
+            &data,
+            "/v1/two-party/sessions/{id}",
+            continue_two_party_session,
+            stranger,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let signature = run_session(&data, opened, hash).await.unwrap();
+        let mut bytes = signature.r.to_vec();
+        bytes.extend_from_slice(&signature.s);
+        let recovered = VerifyingKey::recover_from_prehash(
+            &hash,
+            &Signature::from_slice(&bytes).unwrap(),
+            RecoveryId::from_byte(signature.y_parity).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(zkml_cosigner::approval::address(&recovered), SHARED);
+        assert!(transaction().encode_signed(&tx, &signature).is_ok());
+
+        // A client signing anything but the approved transaction gets nothing
+        let (status, body) = open(&data, tx).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let opened: Opened = serde_json::from_value(body).unwrap();
+        assert!(run_session(&data, opened, [1u8; 32]).await.is_err());
+    }
+}
@@ -0,0 +1,1112 @@
+//! `/verify` and the pipeline every approval goes through: the cheap request
+//! checks, SNARK verification on the pool, the spend ledger and policy, nonce
+//! issuance and signing, and recording the decision.
+
+use crate::{
+    denylist::AddressList,
+    features::Features,
+    idempotency::{self, IdempotencyRecord, Reservation},
+    ledger::{SpendEntry, SpendLedger},
+    metrics::Metrics,
+    nonce::{NonceDomain, NonceStore},
+    policy,
+    reload::Reloadable,
+    unix_now,
+    verify_pool::PoolError,
+    AppState, Verdict, VerifyRequest, VerifyResponse, PCS,
+};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use ark_bn254::Fr;
+use ark_serialize::CanonicalDeserialize;
+use jolt_core::transcripts::KeccakTranscript;
+use onnx_tracer::ProgramIO;
+use std::sync::Arc;
+use zkml_cosigner::{
+    approval::{approval_digest, TxDetails},
+    directory::MerchantDirectory,
+};
+use zkml_jolt_core::jolt::{JoltSNARK, JoltVerifierPreprocessing};
+
+/// Why a proof was refused by [`ProofVerifier::check`].
+pub enum ProofRejection {
+    /// The proof bytes don't decode to a SNARK (client error).
+    Malformed(String),
+    /// The SNARK decodes but does not verify.
+    Invalid(String),
+}
+
+/// Checks SNARK proofs: the Jolt verifier preprocessing, or a stand-in in
+/// handler tests.
+pub trait ProofVerifier: Send + Sync {
+    /// Deserialize and verify a SNARK proof. CPU-heavy: run it on the
+    /// verification pool, not on an actix worker.
+    fn check(
+        &self,
+        proof_bytes: &[u8],
+        program_io: ProgramIO,
+        metrics: &Metrics,
+    ) -> Result<(), ProofRejection>;
+}
+
+impl ProofVerifier for JoltVerifierPreprocessing<Fr, PCS> {
+    fn check(
+        &self,
+        proof_bytes: &[u8],
+        program_io: ProgramIO,
+        metrics: &Metrics,
+    ) -> Result<(), ProofRejection> {
+        let timer = metrics.deserialize_seconds.start_timer();
+        let snark = JoltSNARK::<Fr, PCS, KeccakTranscript>::deserialize_compressed(proof_bytes)
+            .map_err(|e| ProofRejection::Malformed(format!("Failed to deserialize proof: {e}")))?;
+        timer.observe_duration();
+        let _timer = metrics.verify_seconds.start_timer();
+        snark
+            .verify(self, program_io, None)
+            .map_err(|e| ProofRejection::Invalid(format!("Proof verification failed: {e}")))
+    }
+}
+
+/// What an idempotency key is already recorded as.
+pub enum Recorded {
+    /// The response to replay, or a conflict.
+    Verdict(Verdict),
+    /// An approval whose nonce was issued but whose response wasn't
+    /// recorded; it is signed again under the same nonce and timestamp.
+    Reserved(Reservation),
+}
+
+pub fn recorded_verdict(
+    store: &dyn NonceStore,
+    key: &str,
+    request_digest: &str,
+    now: u64,
+    ttl_secs: u64,
+) -> std::io::Result<Option<Recorded>> {
+    let record = match store.idempotent_response(key)? {
+        Some(r) if !r.is_expired(now, ttl_secs) => r,
+        _ => return Ok(None),
+    };
+    if record.request_digest != request_digest {
+        return Ok(Some(Recorded::Verdict(Verdict::rejected(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "idempotency_conflict",
+            format!(
+                "{} was already used with a different request body",
+                idempotency::IDEMPOTENCY_HEADER
+            ),
+        ))));
+    }
+    if let Some(reservation) = record.reserved {
+        return Ok(Some(Recorded::Reserved(reservation)));
+    }
+    Ok(Some(Recorded::Verdict(Verdict {
+        status: StatusCode::from_u16(record.status).unwrap_or(StatusCode::OK),
+        body: record.body,
+        retry_after: None,
+        replayed: true,
+        reason_code: None,
+    })))
+}
+
+pub fn idempotency_read_failed(e: std::io::Error) -> Verdict {
+    log::error!("Failed to read idempotency record: {e}");
+    Verdict::rejected(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "idempotency_store",
+        format!("Internal error: failed to read idempotency record: {e}"),
+    )
+}
+
+/// An idempotency key paired with the digest of the request it was sent with.
+#[derive(Clone)]
+pub struct IdempotencyClaim {
+    key: String,
+    request_digest: String,
+}
+
+impl IdempotencyClaim {
+    pub fn new(key: String, req: &VerifyRequest) -> Self {
+        let body = serde_json::to_vec(req).expect("VerifyRequest serializes");
+        IdempotencyClaim {
+            key,
+            request_digest: idempotency::request_digest(&body),
+        }
+    }
+}
+
+/// A request that passed the cheap checks and is ready for SNARK verification.
+pub struct CheckedRequest {
+    pub domain: NonceDomain,
+    pub proof_bytes: Vec<u8>,
+    pub program_io: ProgramIO,
+    /// The decoded model input, for the history checks in [`check_spend`].
+    pub features: Features,
+}
+
+/// Steps that don't need the SNARK verifier: model hash, nonce domain, proof
+/// and program_io decoding, the model's output class, and the model input.
+pub fn check_request(data: &AppState, req: &VerifyRequest) -> Result<CheckedRequest, Verdict> {
+    // 0. Check model hash matches
+    if req.model_hash != data.model_hash {
+        return Err(Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "model_hash_mismatch",
+            format!(
+                "Model hash mismatch: prover={}, cosigner={}",
+                req.model_hash, data.model_hash
+            ),
+        ));
+    }
+
+    // Resolve the nonce domain up front so malformed senders are rejected cheaply
+    let domain = match NonceDomain::new(req.tx.chain_id, &req.tx.from) {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(Verdict::rejected(
+                StatusCode::BAD_REQUEST,
+                "invalid_sender",
+                e,
+            ))
+        }
+    };
+
+    // The approval digest needs well-formed addresses and amount
+    req.tx
+        .validate()
+        .map_err(|e| Verdict::rejected(StatusCode::BAD_REQUEST, "invalid_tx", e))?;
+
+    // Screen the parties before any expensive work
+    screen(&data.denylists, &req.tx)?;
+
+    // 1. Decode proof (deserialized on the verification pool in step 4)
+    let proof_bytes = match hex::decode(&req.proof) {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(Verdict::rejected(
+                StatusCode::BAD_REQUEST,
+                "invalid_proof_hex",
+                format!("Invalid proof hex: {e}"),
+            ))
+        }
+    };
+
+    // 2. Deserialize program_io
+    let program_io: ProgramIO = match serde_json::from_str(&req.program_io) {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(Verdict::rejected(
+                StatusCode::BAD_REQUEST,
+                "invalid_program_io",
+                format!("Failed to deserialize program_io: {e}"),
+            ))
+        }
+    };
+
+    // 3. Check output indicates AUTHORIZED (class 0 has highest value)
+    // NOTE: ProgramIO contains fixed-point i32 values after Jolt circuit execution,
+    // so integer cmp is correct here. The ONNX model originally outputs floats, but
+    // those are converted to fixed-point integers during circuit execution.
+    let output_data: Vec<i32> = program_io.output.iter().cloned().collect();
+    if output_data.is_empty() {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "empty_output",
+            "Empty model output",
+        ));
+    }
+    let (pred_idx, _) = output_data
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.cmp(b.1))
+        .unwrap(); // safe: checked non-empty above
+    if pred_idx != 0 {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "denied",
+            "Model output is DENIED (class != 0)",
+        ));
+    }
+
+    // 4. Check the input is a well-formed encoding of this transfer
+    let input: Vec<i32> = program_io.input.iter().cloned().collect();
+    let features = data.vocab.decode(&input).map_err(|e| {
+        Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "malformed_input",
+            format!("Malformed model input: {e}"),
+        )
+    })?;
+    if let Some(binding) = &data.input_binding {
+        binding
+            .check(&features, &req.tx, unix_now())
+            .map_err(|e| Verdict::rejected(StatusCode::FORBIDDEN, "input_mismatch", e))?;
+    }
+    if let Some(issuers) = &data.trust_issuers {
+        check_credential(issuers, req, features.get("trust"))?;
+    }
+    if let Some(directory) = &data.merchant_directory {
+        check_category(&directory.current(), &req.tx, features.get("category"))?;
+    }
+
+    Ok(CheckedRequest {
+        domain,
+        proof_bytes,
+        program_io,
+        features,
+    })
+}
+
+/// Check the request's trust credential is from one of `issuers`, about the
+/// sender, and states the proof's decoded `trust` bucket.
+pub fn check_credential(
+    issuers: &[String],
+    req: &VerifyRequest,
+    trust: Option<usize>,
+) -> Result<(), Verdict> {
+    let Some(credential) = &req.trust_credential else {
+        return Err(Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "missing_credential",
+            "A trust credential is required",
+        ));
+    };
+    let invalid = |reason: String| {
+        Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "invalid_credential",
+            format!("Invalid trust credential: {reason}"),
+        )
+    };
+    credential.verify(issuers, unix_now()).map_err(invalid)?;
+    if !credential.subject.eq_ignore_ascii_case(&req.tx.from) {
+        return Err(invalid(format!(
+            "it is for {}, not the sender {}",
+            credential.subject, req.tx.from
+        )));
+    }
+    if trust != Some(usize::from(credential.trust)) {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "trust_mismatch",
+            format!(
+                "Input trust bucket {} does not match the credential's {}",
+                trust.map_or("missing".to_string(), |t| t.to_string()),
+                credential.trust
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Refuse the transfer if its sender or recipient is on any of `lists`.
+pub fn screen(lists: &[Reloadable<AddressList>], tx: &TxDetails) -> Result<(), Verdict> {
+    for list in lists {
+        let list = list.current();
+        for (role, address) in [("sender", &tx.from), ("recipient", &tx.to)] {
+            if list.contains(address) {
+                return Err(Verdict::denylisted(&list, role, address));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the proof's decoded `category` is the directory's category for the
+/// recipient.
+pub fn check_category(
+    directory: &MerchantDirectory,
+    tx: &TxDetails,
+    category: Option<usize>,
+) -> Result<(), Verdict> {
+    let Some(expected) = directory.category(&tx.to) else {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "unknown_recipient",
+            format!(
+                "Recipient {} is not in merchant directory {}",
+                tx.to,
+                directory.version()
+            ),
+        ));
+    };
+    if category != Some(usize::from(expected)) {
+        return Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "category_mismatch",
+            format!(
+                "Input category bucket {} does not match {} for {} in merchant directory {}",
+                category.map_or("missing".to_string(), |c| c.to_string()),
+                expected,
+                tx.to,
+                directory.version()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The proof verifier, or a 503 while startup preprocessing runs.
+pub fn ready_verifier(data: &AppState) -> Result<Arc<dyn ProofVerifier>, Verdict> {
+    match data.verifier.get() {
+        Some(v) => Ok(Arc::clone(&v.snark)),
+        None => Err(Verdict {
+            retry_after: Some(data.verify_retry_after_secs),
+            ..Verdict::rejected(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "not_ready",
+                "Verifier is still preprocessing, retry later",
+            )
+        }),
+    }
+}
+
+/// Map the result of a pool SNARK check to a rejection, if any.
+pub fn snark_verdict(
+    data: &AppState,
+    outcome: Result<Result<(), ProofRejection>, PoolError>,
+) -> Result<(), Verdict> {
+    match outcome {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(ProofRejection::Malformed(reason))) => Err(Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "malformed_proof",
+            reason,
+        )),
+        Ok(Err(ProofRejection::Invalid(reason))) => Err(Verdict::rejected(
+            StatusCode::FORBIDDEN,
+            "invalid_proof",
+            reason,
+        )),
+        Err(PoolError::QueueFull) => Err(Verdict {
+            retry_after: Some(data.verify_retry_after_secs),
+            ..Verdict::rejected(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "queue_full",
+                "Verification queue is full, retry later",
+            )
+        }),
+        Err(e) => {
+            log::error!("Proof verification job failed: {e}");
+            Err(Verdict::rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                format!("Internal error: {e}"),
+            ))
+        }
+    }
+}
+
+/// Check the history-derived input `features` and then `tx` against the
+/// spending policy, if any, with the ledger locked. Returns the amount to
+/// record in the spend ledger before a nonce is issued; without a policy,
+/// amounts that don't parse are not recorded.
+pub fn check_spend(
+    data: &AppState,
+    ledger: &SpendLedger,
+    tx: &TxDetails,
+    features: &Features,
+    now: u64,
+) -> Result<Option<u128>, Verdict> {
+    if let Some(binding) = &data.input_binding {
+        binding
+            .check_history(features, tx, ledger, now)
+            .map_err(|e| Verdict::rejected(StatusCode::FORBIDDEN, "input_mismatch", e))?;
+    }
+    match &data.policy {
+        Some(policy) => policy
+            .check(tx, ledger, now)
+            .map(Some)
+            .map_err(Verdict::policy_refused),
+        None => Ok(policy::parse_amount(&tx.amount)),
+    }
+}
+
+/// Durably record the approved `amount` of `tx`, if any, and return the
+/// entry to refund if the approval is never signed. A failure refuses the
+/// approval, since unrecorded spend would escape the caps.
+pub fn record_spend(
+    ledger: &mut SpendLedger,
+    tx: &TxDetails,
+    amount: Option<u128>,
+    now: u64,
+) -> Result<Option<SpendEntry>, Verdict> {
+    let Some(amount) = amount else {
+        return Ok(None);
+    };
+    let entry = SpendEntry::new(tx, amount, now);
+    ledger
+        .append(std::slice::from_ref(&entry))
+        .map(|()| Some(entry))
+        .map_err(|e| {
+            log::error!("Failed to record spend: {e}");
+            Verdict::rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "spend_ledger",
+                format!("Internal error: failed to record spend: {e}"),
+            )
+        })
+}
+
+/// Cancel the recorded `spends` of approvals that were never signed. Takes
+/// the ledger lock, so the caller must not hold it. A failure only logs: the
+/// spend then stays counted until it leaves the window.
+pub fn refund_spend(data: &AppState, spends: &[SpendEntry]) {
+    if spends.is_empty() {
+        return;
+    }
+    let refunds: Vec<SpendEntry> = spends.iter().map(SpendEntry::refund).collect();
+    if let Err(e) = data.lock_ledger().append(&refunds) {
+        log::error!("Failed to refund spend of an unsigned approval: {e}");
+    }
+}
+
+/// Sign an approval of `tx` under `nonce` and serialize the `VerifyResponse`.
+/// The nonce stays spent if the signer fails; callers that can't sign it
+/// again refund its spend.
+pub async fn sign_approval(
+    data: &AppState,
+    tx: &TxDetails,
+    nonce: u64,
+    timestamp: u64,
+    policy_version: Option<&str>,
+) -> Result<String, Verdict> {
+    let hash = approval_digest(tx, nonce, timestamp)
+        .map_err(|e| Verdict::rejected(StatusCode::BAD_REQUEST, "invalid_tx", e))?;
+    let (key_id, signer) = {
+        let ring = data.key_ring.read().unwrap_or_else(|e| e.into_inner());
+        let (key_id, signer) = ring.primary();
+        (key_id.to_string(), Arc::clone(signer))
+    };
+    // A remote signer is a network round trip; keep it off the workers
+    let signed = web::block(move || signer.sign(&hash).map_err(|e| e.to_string())).await;
+    let signature = match signed {
+        Ok(Ok(signature)) => signature,
+        Ok(Err(e)) => return Err(signer_unavailable(e)),
+        Err(e) => return Err(signer_unavailable(e.to_string())),
+    };
+    Ok(serde_json::to_string(&VerifyResponse {
+        approved: true,
+        signature: Some(signature),
+        nonce: Some(nonce),
+        timestamp: Some(timestamp),
+        key_id: Some(key_id),
+        policy_version: policy_version.map(str::to_string),
+        ..VerifyResponse::default()
+    })
+    .expect("VerifyResponse serializes"))
+}
+
+pub fn signer_unavailable(e: String) -> Verdict {
+    log::error!("Failed to sign approval: {e}");
+    Verdict::rejected(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "signer_unavailable",
+        "Signer unavailable; retry later",
+    )
+}
+
+/// Run every check on `req` up to and including SNARK verification, and
+/// return its nonce domain and decoded input.
+pub async fn verify_request(
+    data: &AppState,
+    req: &VerifyRequest,
+) -> Result<(NonceDomain, Features), Verdict> {
+    let CheckedRequest {
+        domain,
+        proof_bytes,
+        program_io,
+        features,
+    } = check_request(data, req)?;
+
+    // 4. Deserialize and verify the SNARK proof off the actix workers
+    log::info!("Verifying SNARK proof...");
+    let verify_start = std::time::Instant::now();
+    let verifier = ready_verifier(data)?;
+    let metrics = Arc::clone(&data.metrics);
+    let in_flight = metrics.track_in_flight();
+    let outcome = data
+        .verify_pool
+        .run(move || {
+            let _in_flight = in_flight;
+            verifier.check(&proof_bytes, program_io, &metrics)
+        })
+        .await;
+    snark_verdict(data, outcome)?;
+    log::info!("Proof verified in {:?}", verify_start.elapsed());
+    Ok((domain, features))
+}
+
+/// Run the full verification pipeline for one request: model hash, proof
+/// decoding, output class, SNARK verification, then nonce issuance and signing.
+pub async fn evaluate(
+    data: &AppState,
+    req: &VerifyRequest,
+    idempotency: Option<IdempotencyClaim>,
+) -> Verdict {
+    // Retries carrying an Idempotency-Key get the recorded approval back
+    // instead of burning a new nonce
+    if let Some(claim) = &idempotency {
+        let recorded = {
+            let state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+            recorded_verdict(
+                state.as_ref(),
+                &claim.key,
+                &claim.request_digest,
+                unix_now(),
+                data.idempotency_ttl_secs,
+            )
+        };
+        match recorded {
+            Ok(Some(Recorded::Verdict(verdict))) => return verdict,
+            Ok(Some(Recorded::Reserved(reservation))) => {
+                return finish_reserved(data, &req.tx, claim, reservation, true).await
+            }
+            Ok(None) => {}
+            Err(e) => return idempotency_read_failed(e),
+        }
+    }
+
+    let (domain, features) = match verify_request(data, req).await {
+        Ok(verified) => verified,
+        Err(verdict) => return verdict,
+    };
+
+    // 5. Generate nonce and sign approval
+    let (reservation, replayed, spend) =
+        match reserve_nonce(data, req, &domain, &features, idempotency.as_ref()) {
+            Ok(reserved) => reserved,
+            Err(verdict) => return verdict,
+        };
+    match &idempotency {
+        // A retry with the key signs the reservation, so its spend stays
+        Some(claim) => finish_reserved(data, &req.tx, claim, reservation, replayed).await,
+        None => {
+            let Reservation { nonce, timestamp } = reservation;
+            let policy_version = data.policy.as_ref().map(|p| p.version());
+            match sign_approval(data, &req.tx, nonce, timestamp, policy_version).await {
+                Ok(body) => Verdict::approved(body),
+                Err(verdict) => {
+                    refund_spend(data, spend.as_slice());
+                    verdict
+                }
+            }
+        }
+    }
+}
+
+/// Record `req`'s spend and then issue its nonce, under the locks; the spend
+/// is refunded if the nonce can't be issued. With an idempotency key, the
+/// nonce is issued in the same store write as a reservation under the key,
+/// so a concurrent or later retry signs this approval rather than issuing
+/// another. Returns the reservation, whether it was an earlier one found
+/// under the key, and the spend recorded for a new one.
+pub fn reserve_nonce(
+    data: &AppState,
+    req: &VerifyRequest,
+    domain: &NonceDomain,
+    features: &Features,
+    idempotency: Option<&IdempotencyClaim>,
+) -> Result<(Reservation, bool, Option<SpendEntry>), Verdict> {
+    let mut state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(claim) = idempotency {
+        match recorded_verdict(
+            state.as_ref(),
+            &claim.key,
+            &claim.request_digest,
+            unix_now(),
+            data.idempotency_ttl_secs,
+        ) {
+            Ok(Some(Recorded::Verdict(verdict))) => return Err(verdict),
+            Ok(Some(Recorded::Reserved(reservation))) => return Ok((reservation, true, None)),
+            Ok(None) => {}
+            Err(e) => return Err(idempotency_read_failed(e)),
+        }
+    }
+    let now = unix_now();
+    let mut ledger = data.lock_ledger();
+    let amount = check_spend(data, &ledger, &req.tx, features, now)?;
+    let spend = record_spend(&mut ledger, &req.tx, amount, now)?;
+    drop(ledger);
+    // Include timestamp for signature expiration
+    // NOTE: The client/contract should reject signatures older than a configurable
+    // threshold (e.g., 300 seconds) to prevent replay of stale approvals.
+    let timestamp = now;
+    let issued = match idempotency {
+        Some(claim) => state.next_nonce_with_record(
+            domain,
+            &claim.key,
+            &claim.request_digest,
+            timestamp,
+            timestamp.saturating_sub(data.idempotency_ttl_secs),
+        ),
+        None => state.next_nonce(domain),
+    };
+    let nonce = match issued {
+        Ok(n) => {
+            data.metrics.nonces_issued.inc();
+            n
+        }
+        Err(e) => {
+            log::error!("Failed to persist nonce state: {e}");
+            refund_spend(data, spend.as_slice());
+            return Err(Verdict::rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "nonce_store",
+                format!("Internal error: failed to persist nonce: {e}"),
+            ));
+        }
+    };
+    Ok((Reservation { nonce, timestamp }, false, spend))
+}
+
+/// Sign the approval reserved under `claim` and record the response, so
+/// every retry with the key gets this approval. If the record can't be
+/// written the response is withheld; a retry signs the reservation again.
+pub async fn finish_reserved(
+    data: &AppState,
+    tx: &TxDetails,
+    claim: &IdempotencyClaim,
+    reservation: Reservation,
+    replayed: bool,
+) -> Verdict {
+    let Reservation { nonce, timestamp } = reservation;
+    let policy_version = data.policy.as_ref().map(|p| p.version());
+    let body = match sign_approval(data, tx, nonce, timestamp, policy_version).await {
+        Ok(body) => body,
+        Err(verdict) => return verdict,
+    };
+    let record = IdempotencyRecord {
+        request_digest: claim.request_digest.clone(),
+        status: StatusCode::OK.as_u16(),
+        body: body.clone(),
+        created_at: timestamp,
+        reserved: None,
+    };
+    let expire_before = unix_now().saturating_sub(data.idempotency_ttl_secs);
+    let recorded = data
+        .nonce_state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record_response(&claim.key, &record, expire_before);
+    if let Err(e) = recorded {
+        log::error!("Failed to record idempotency key: {e}");
+        return Verdict::rejected(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "idempotency_store",
+            format!("Internal error: failed to record idempotency key: {e}"),
+        );
+    }
+    Verdict {
+        replayed,
+        ..Verdict::approved(body)
+    }
+}
+
+/// Record one decision off the workers; see [`AppState::record_blocking`].
+pub async fn record(
+    data: &web::Data<AppState>,
+    endpoint: &'static str,
+    req: VerifyRequest,
+    verdict: Verdict,
+) -> Verdict {
+    record_all(data, endpoint, vec![(req, verdict)])
+        .await
+        .pop()
+        .expect("one verdict per decision")
+}
+
+/// Record decisions in order on a blocking thread and return the verdicts
+/// to send.
+pub async fn record_all(
+    data: &web::Data<AppState>,
+    endpoint: &'static str,
+    decisions: Vec<(VerifyRequest, Verdict)>,
+) -> Vec<Verdict> {
+    let count = decisions.len();
+    let state = data.clone();
+    let recorded = web::block(move || {
+        decisions
+            .into_iter()
+            .map(|(req, verdict)| state.record_blocking(endpoint, &req, verdict))
+            .collect()
+    })
+    .await;
+    recorded.unwrap_or_else(|e| {
+        // Nothing is known to be logged, so nothing may be released
+        log::error!("Failed to record decisions: {e}");
+        (0..count)
+            .map(|_| {
+                let withheld = Verdict::rejected(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "audit_log",
+                    "Internal error: failed to record the decision",
+                );
+                data.observe(endpoint, &withheld);
+                withheld
+            })
+            .collect()
+    })
+}
+
+pub async fn verify_proof(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<VerifyRequest>,
+) -> HttpResponse {
+    let idempotency = match idempotency::key_from_request(&http_req) {
+        Ok(key) => Ok(key.map(|k| IdempotencyClaim::new(k, &req))),
+        Err(e) => Err(Verdict::rejected(
+            StatusCode::BAD_REQUEST,
+            "invalid_idempotency_key",
+            e,
+        )),
+    };
+    let verdict = match idempotency {
+        Ok(idempotency) => evaluate(&data, &req, idempotency).await,
+        Err(verdict) => verdict,
+    };
+    record(&data, "verify", req.into_inner(), verdict)
+        .await
+        .into_response()
+}
+
+/// Next nonce the cosigner will issue for `sender` on `chain_id`.
+pub async fn next_nonce(data: web::Data<AppState>, path: web::Path<(u64, String)>) -> HttpResponse {
+    let (chain_id, sender) = path.into_inner();
+    let domain = match NonceDomain::new(chain_id, &sender) {
+        Ok(d) => d,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    };
+    let current = {
+        let state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
+        state.current(&domain)
+    };
+    let current = match current {
+        Ok(n) => n,
+        Err(e) => {
+            log::error!("Failed to read nonce state: {e}");
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "failed to read nonce state"}));
+        }
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "chain_id": domain.chain_id,
+        "sender": domain.sender,
+        "next_nonce": current + 1,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        features::InputBinding,
+        handlers::testing::{self, StubVerifier, TOKEN},
+        nonce::FileNonceStore,
+        policy::Policy,
+    };
+    use actix_web::test::TestRequest;
+    use k256::ecdsa::SigningKey;
+    use std::{
+        collections::BTreeMap,
+        path::Path,
+        sync::{Mutex, RwLock},
+    };
+    use zkml_cosigner::{
+        directory::DirectoryEntries,
+        keyring::KeyRing,
+        signer::{LocalSigner, Signer, SignerError},
+    };
+
+    const SHOP: &str = "0x90f79bf6eb2c4f870365e785982e1f101e93b906";
+
+    fn post(req: &VerifyRequest) -> TestRequest {
+        TestRequest::post().uri("/verify").set_json(req)
+    }
+
+    /// Nonces issued to the fixture's sender.
+    fn issued(data: &AppState) -> u64 {
+        let domain = NonceDomain::new(9745, testing::FROM).unwrap();
+        data.nonce_state.lock().unwrap().current(&domain).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_policy_refusal_issues_no_nonce() {
+        let dir = testing::test_dir("test_verify_policy");
+        let mut state = testing::state(&dir, Arc::new(StubVerifier::default()));
+        let policy =
+            format!(r#"{{"version": "v1", "tokens": {{"{TOKEN}": {{"max_transfer": "100"}}}}}}"#);
+        state.policy = Some(Policy::parse(policy.as_bytes()).unwrap());
+        let data = web::Data::new(state);
+        let features = testing::features(&data.vocab);
+
+        let over = testing::request(&data.vocab, testing::tx("101"), &features);
+        let (status, body) = testing::call(&data, "/verify", verify_proof, post(&over)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["approved"], false);
+        assert_eq!(body["policy_version"], "v1");
+        assert_eq!(body["policy_rule"], "max_transfer");
+        assert_eq!(testing::last_reason_code(&data).as_deref(), Some("policy"));
+        assert_eq!(issued(&data), 0);
+        assert_eq!(data.lock_ledger().len(), 0);
+
+        let within = testing::request(&data.vocab, testing::tx("100"), &features);
+        let (status, body) = testing::call(&data, "/verify", verify_proof, post(&within)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["nonce"], 1);
+        assert_eq!(body["policy_version"], "v1");
+        assert_eq!(issued(&data), 1);
+        assert_eq!(data.lock_ledger().len(), 1);
+    }
+
+    /// A signer whose key can't be reached.
+    struct Unreachable(LocalSigner);
+
+    impl Signer for Unreachable {
+        fn address(&self) -> &str {
+            self.0.address()
+        }
+
+        fn sign(&self, _: &[u8]) -> Result<String, SignerError> {
+            Err(SignerError("connection refused".to_string()))
+        }
+
+        fn describe(&self) -> String {
+            "unreachable".to_string()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unsigned_approvals_are_refunded() {
+        let dir = testing::test_dir("test_verify_refund");
+        let mut state = testing::state(&dir, Arc::new(StubVerifier::default()));
+        let signer: Arc<dyn Signer> = Arc::new(Unreachable(LocalSigner::new(
+            SigningKey::from_slice(&[7u8; 32]).unwrap(),
+        )));
+        state.key_ring = RwLock::new(
+            KeyRing::open(dir.join("unreachable_ring.json"), vec![signer], unix_now()).unwrap(),
+        );
+        let nonces = dir.join("nonces");
+        std::fs::create_dir_all(&nonces).unwrap();
+        state.nonce_state = Mutex::new(Box::new(
+            FileNonceStore::open(nonces.join("nonce_state.json")).unwrap(),
+        ));
+        let data = web::Data::new(state);
+        let spent = |data: &AppState| data.lock_ledger().spent(testing::FROM, TOKEN, None, 0);
+
+        let features = testing::features(&data.vocab);
+        let req = testing::request(&data.vocab, testing::tx("5"), &features);
+        let (status, _) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(issued(&data), 1);
+        assert_eq!(spent(&data), 0);
+
+        // The nonce can't be written once its directory is gone
+        std::fs::remove_dir_all(&nonces).unwrap();
+        let (status, _) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            testing::last_reason_code(&data).as_deref(),
+            Some("nonce_store")
+        );
+        assert_eq!(spent(&data), 0);
+        assert_eq!(data.lock_ledger().approvals(testing::FROM, 0), 0);
+
+        // Refunds survive a restart
+        let ledger = SpendLedger::open(dir.join("spend_ledger.jsonl"), 60, unix_now()).unwrap();
+        assert_eq!(ledger.spent(testing::FROM, TOKEN, None, 0), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_input_binding_mismatches() {
+        let dir = testing::test_dir("test_verify_binding");
+        let verifier = Arc::new(StubVerifier::default());
+        let mut state = testing::state(&dir, verifier.clone());
+        let binding = format!(
+            r#"{{"amount_buckets": {{"{TOKEN}": ["100", "1000"]}},
+                "velocity": {{"window": "24h", "bounds": [0, 1, 2, 4]}}}}"#
+        );
+        state.input_binding = Some(InputBinding::parse(binding.as_bytes(), &state.vocab).unwrap());
+        let data = web::Data::new(state);
+        let with = |feature: &str, value: usize| {
+            let mut features = testing::features(&data.vocab);
+            features.iter_mut().find(|(f, _)| *f == feature).unwrap().1 = value;
+            features
+        };
+        let refusal = |amount: &str, features: Vec<(&str, usize)>| {
+            let req = testing::request(&data.vocab, testing::tx(amount), &features);
+            let data = data.clone();
+            async move {
+                let (status, body) =
+                    testing::call(&data, "/verify", verify_proof, post(&req)).await;
+                assert_eq!(status, StatusCode::FORBIDDEN, "{body}");
+                assert_eq!(
+                    testing::last_reason_code(&data).as_deref(),
+                    Some("input_mismatch")
+                );
+                body["reason"].as_str().unwrap().to_string()
+            }
+        };
+
+        // The amount and clock are checked before the SNARK
+        let reason = refusal("1000", with("amount", 0)).await;
+        assert!(reason.contains("amount bucket"), "{reason}");
+        let tomorrow = (testing::features(&data.vocab)[5].1 + 1) % 7;
+        let reason = refusal("1", with("day", tomorrow)).await;
+        assert!(reason.contains("cosigner clock"), "{reason}");
+        assert_eq!(verifier.checks(), 0);
+
+        // Velocity comes from the cosigner's own ledger
+        let reason = refusal("1", with("velocity", 2)).await;
+        assert!(reason.contains("velocity bucket 2"), "{reason}");
+        assert!(reason.contains("0 approvals in the last 24h"), "{reason}");
+        let req = testing::request(&data.vocab, testing::tx("1"), &with("velocity", 0));
+        let (status, _) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::OK);
+        let reason = refusal("1", with("velocity", 0)).await;
+        assert!(reason.contains("1 approvals in the last 24h"), "{reason}");
+        assert_eq!(verifier.checks(), 3);
+        assert_eq!(issued(&data), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_denylist_refuses_before_the_snark() {
+        let dir = testing::test_dir("test_verify_denylist");
+        let verifier = Arc::new(StubVerifier::default());
+        let mut state = testing::state(&dir, verifier.clone());
+        let list = testing::write(
+            &dir,
+            "blocked.txt",
+            &format!("# version: v1\n{}\n", testing::TO),
+        );
+        state.denylists = vec![Reloadable::open(list, AddressList::load).unwrap()];
+        let data = web::Data::new(state);
+
+        let features = testing::features(&data.vocab);
+        let req = testing::request(&data.vocab, testing::tx("1"), &features);
+        let (status, body) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["denylist"], "blocked@v1");
+        assert_eq!(
+            testing::last_reason_code(&data).as_deref(),
+            Some("denylisted")
+        );
+        assert_eq!(verifier.checks(), 0);
+        assert_eq!(issued(&data), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_recipient() {
+        let dir = testing::test_dir("test_verify_directory");
+        let verifier = Arc::new(StubVerifier::default());
+        let mut state = testing::state(&dir, verifier.clone());
+        let issuer = LocalSigner::new(SigningKey::from_slice(&[9u8; 32]).unwrap());
+        let entries = DirectoryEntries {
+            version: "d1".to_string(),
+            default_category: None,
+            merchants: BTreeMap::from([(SHOP.to_string(), 1)]),
+        };
+        let directory = MerchantDirectory {
+            signature: issuer.sign(&entries.digest()).unwrap(),
+            issuer: issuer.address().to_string(),
+            entries,
+        };
+        let path = testing::write(
+            &dir,
+            "directory.json",
+            &serde_json::to_string(&directory).unwrap(),
+        );
+        let signers = vec![issuer.address().to_string()];
+        state.merchant_directory = Some(
+            Reloadable::open(path, move |p: &Path| MerchantDirectory::load(p, &signers)).unwrap(),
+        );
+        let data = web::Data::new(state);
+
+        let features = testing::features(&data.vocab);
+        let req = testing::request(&data.vocab, testing::tx("1"), &features);
+        let (status, body) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body["reason"]
+            .as_str()
+            .unwrap()
+            .contains("is not in merchant directory d1"));
+        assert_eq!(
+            testing::last_reason_code(&data).as_deref(),
+            Some("unknown_recipient")
+        );
+        assert_eq!(verifier.checks(), 0);
+
+        let mut tx = testing::tx("1");
+        tx.to = SHOP.to_string();
+        let mut features = features;
+        features
+            .iter_mut()
+            .find(|(f, _)| *f == "category")
+            .unwrap()
+            .1 = 1;
+        let req = testing::request(&data.vocab, tx, &features);
+        let (status, _) = testing::call(&data, "/verify", verify_proof, post(&req)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(verifier.checks(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_recorded_verdict_replays_or_rejects() {
+        use actix_web::body::to_bytes;
+
+        let path = std::env::temp_dir().join("test_recorded_verdict.json");
+        let _ = std::fs::remove_file(&path);
+        let mut store = crate::nonce::FileNonceStore::open(&path).unwrap();
+
+        let body = r#"{"approved":true,"signature":"ab","nonce":1,"timestamp":1000}"#;
+        let digest = idempotency::request_digest(b"request");
+        let record = IdempotencyRecord {
+            request_digest: digest.clone(),
+            status: 200,
+            body: body.to_string(),
+            created_at: 1000,
+            reserved: None,
+        };
+        store.record_response("retry-1", &record, 0).unwrap();
+        let replayed = |key, digest, now| match recorded_verdict(&store, key, digest, now, 60) {
+            Ok(Some(Recorded::Verdict(verdict))) => Some(verdict),
+            _ => None,
+        };
+
+        // Unknown key: nothing recorded
+        assert!(recorded_verdict(&store, "retry-2", &digest, 1000, 60)
+            .unwrap()
+            .is_none());
+
+        // Same key and body: original response byte-for-byte
+        let resp = replayed("retry-1", &digest, 1000).unwrap().into_response();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get(idempotency::REPLAYED_HEADER).unwrap(),
+            "true"
+        );
+        assert_eq!(to_bytes(resp.into_body()).await.unwrap(), body.as_bytes());
+
+        // Same key, different body: rejected
+        let other = idempotency::request_digest(b"other request");
+        let verdict = replayed("retry-1", &other, 1000).unwrap();
+        assert_eq!(verdict.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Expired records are ignored
+        assert!(recorded_verdict(&store, "retry-1", &digest, 1061, 60)
+            .unwrap()
+            .is_none());
+
+        // An issued but unrecorded approval is signed again, not reissued
+        let domain = NonceDomain::new(9745, "0x70997970c51812dc3a010c7d01b50e0d17dc79c8").unwrap();
+        let nonce = store
+            .next_nonce_with_record(&domain, "retry-3", &digest, 1000, 0)
+            .unwrap();
+        assert!(matches!(
+            recorded_verdict(&store, "retry-3", &digest, 1000, 60),
+            Ok(Some(Recorded::Reserved(Reservation { nonce: n, timestamp: 1000 }))) if n == nonce
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,122 @@
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Request header carrying a client-chosen retry key for `/verify`.
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// Response header set when a stored response is replayed.
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// How long a recorded response can be replayed (default 24 hours).
+pub const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+
+const MAX_KEY_LEN: usize = 255;
+
+/// A `/verify` response recorded under an idempotency key, persisted in the
+/// nonce store so a retry after a dropped connection gets the original
+/// signature instead of burning a new nonce.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IdempotencyRecord {
+    /// SHA-256 (hex) of the request the key was first used with.
+    pub request_digest: String,
+    pub status: u16,
+    /// Serialized response body, replayed byte-for-byte.
+    pub body: String,
+    /// Unix seconds when the response was recorded.
+    pub created_at: u64,
+    /// Set while the approval is still being signed: its nonce was issued
+    /// in the same write as this record, so a retry signs it again instead
+    /// of issuing another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved: Option<Reservation>,
+}
+
+/// Nonce and timestamp an approval was issued under.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reservation {
+    pub nonce: u64,
+    pub timestamp: u64,
+}
+
+impl IdempotencyRecord {
+    /// Record for an approval issued under `reservation` but not yet signed.
+    pub fn reserved(request_digest: &str, reservation: Reservation) -> Self {
+        IdempotencyRecord {
+            request_digest: request_digest.to_string(),
+            status: 0,
+            body: String::new(),
+            created_at: reservation.timestamp,
+            reserved: Some(reservation),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64, ttl_secs: u64) -> bool {
+        now.saturating_sub(self.created_at) > ttl_secs
+    }
+}
+
+/// Read and validate the `Idempotency-Key` header, if present.
+/// Keys must be 1-255 visible ASCII characters.
+pub fn key_from_request(req: &HttpRequest) -> Result<Option<String>, String> {
+    let Some(value) = req.headers().get(IDEMPOTENCY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .map_err(|_| format!("{IDEMPOTENCY_HEADER} must be visible ASCII"))?;
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !key.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(format!(
+            "{IDEMPOTENCY_HEADER} must be 1-{MAX_KEY_LEN} visible ASCII characters"
+        ));
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Digest identifying a request body for idempotency comparisons.
+pub fn request_digest(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_key_from_request() {
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(key_from_request(&req).unwrap(), None);
+
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_HEADER, "retry-7f3a"))
+            .to_http_request();
+        assert_eq!(
+            key_from_request(&req).unwrap(),
+            Some("retry-7f3a".to_string())
+        );
+
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_HEADER, "has space"))
+            .to_http_request();
+        assert!(key_from_request(&req).is_err());
+
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_HEADER, "k".repeat(MAX_KEY_LEN + 1)))
+            .to_http_request();
+        assert!(key_from_request(&req).is_err());
+    }
+
+    #[test]
+    fn test_record_expiry() {
+        let record = IdempotencyRecord {
+            request_digest: request_digest(b"{}"),
+            status: 200,
+            body: "{}".to_string(),
+            created_at: 1_000,
+            reserved: None,
+        };
+        assert!(!record.is_expired(1_000 + DEFAULT_TTL_SECS, DEFAULT_TTL_SECS));
+        assert!(record.is_expired(1_001 + DEFAULT_TTL_SECS, DEFAULT_TTL_SECS));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::persist::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Default directory holding one JSON file per verification job.
+pub const DEFAULT_JOBS_DIR: &str = "./verification_jobs";
+
+/// How long finished jobs stay pollable (default 24 hours).
+pub const DEFAULT_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+}
+
+/// An asynchronous `/v1/verifications` job as persisted on disk.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Idempotency key the job runs under: the caller's `Idempotency-Key`, or
+    /// one derived from the job id. Re-running a job after a restart replays
+    /// the recorded approval instead of issuing a second nonce.
+    pub idempotency_key: String,
+    /// The original verify request, kept until the job completes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<serde_json::Value>,
+    /// HTTP status `/verify` would have returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    /// The final `VerifyResponse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+/// What `GET /v1/verifications/{id}` returns: the job without its request.
+#[derive(Serialize, Debug)]
+pub struct JobView {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+impl From<&Job> for JobView {
+    fn from(job: &Job) -> Self {
+        JobView {
+            id: job.id.clone(),
+            status: job.status,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+            http_status: job.http_status,
+            result: job.result.clone(),
+        }
+    }
+}
+
+/// Verification jobs, mirrored in memory and written through to `dir` on
+/// every state change so they survive a restart.
+pub struct JobStore {
+    dir: PathBuf,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobStore {
+    /// Load every job under `dir`, creating it if needed. Unreadable job files
+    /// fail the open rather than silently dropping a caller's job.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut jobs = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let job: Job = serde_json::from_slice(&fs::read(&path)?).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Corrupt job file {}: {e}", path.display()),
+                )
+            })?;
+            jobs.insert(job.id.clone(), job);
+        }
+        Ok(JobStore {
+            dir,
+            jobs: Mutex::new(jobs),
+        })
+    }
+
+    fn path_for(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    fn persist(&self, job: &Job) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(job).map_err(io::Error::other)?;
+        write_atomic(&self.path_for(&job.id), &bytes)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queue a new job for `request`, or return `Ok(None)` when `max_pending`
+    /// jobs are already queued or running.
+    pub fn create(
+        &self,
+        request: serde_json::Value,
+        idempotency_key: Option<String>,
+        max_pending: usize,
+        now: u64,
+    ) -> io::Result<Option<Job>> {
+        let mut jobs = self.lock();
+        if jobs
+            .values()
+            .filter(|j| j.status != JobStatus::Completed)
+            .count()
+            >= max_pending
+        {
+            return Ok(None);
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let job = Job {
+            idempotency_key: idempotency_key.unwrap_or_else(|| format!("job:{id}")),
+            id: id.clone(),
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+            request: Some(request),
+            http_status: None,
+            result: None,
+        };
+        self.persist(&job)?;
+        jobs.insert(id, job.clone());
+        Ok(Some(job))
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.lock().get(id).cloned()
+    }
+
+    /// Ids of jobs that were queued or running, oldest first. Called at
+    /// startup to resume work interrupted by a restart.
+    pub fn pending(&self) -> Vec<String> {
+        let jobs = self.lock();
+        let mut pending: Vec<&Job> = jobs
+            .values()
+            .filter(|j| j.status != JobStatus::Completed)
+            .collect();
+        pending.sort_by_key(|j| j.created_at);
+        pending.into_iter().map(|j| j.id.clone()).collect()
+    }
+
+    pub fn mark_running(&self, id: &str, now: u64) -> io::Result<()> {
+        self.update(id, |job| {
+            job.status = JobStatus::Running;
+            job.updated_at = now;
+        })
+    }
+
+    /// Record the final response and drop the stored request.
+    pub fn complete(
+        &self,
+        id: &str,
+        http_status: u16,
+        result: serde_json::Value,
+        now: u64,
+    ) -> io::Result<()> {
+        self.update(id, |job| {
+            job.status = JobStatus::Completed;
+            job.updated_at = now;
+            job.request = None;
+            job.http_status = Some(http_status);
+            job.result = Some(result);
+        })
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) -> io::Result<()> {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get(id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unknown job {id}"),
+            ));
+        };
+        let mut next = job.clone();
+        f(&mut next);
+        self.persist(&next)?;
+        jobs.insert(id.to_string(), next);
+        Ok(())
+    }
+
+    /// Delete completed jobs last updated before `expire_before`.
+    pub fn prune(&self, expire_before: u64) -> io::Result<usize> {
+        let mut jobs = self.lock();
+        let expired: Vec<String> = jobs
+            .values()
+            .filter(|j| j.status == JobStatus::Completed && j.updated_at < expire_before)
+            .map(|j| j.id.clone())
+            .collect();
+        for id in &expired {
+            match fs::remove_file(self.path_for(id)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            jobs.remove(id);
+        }
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_jobs_survive_reopen() {
+        let dir = temp_dir("test_jobs_survive_reopen");
+        let store = JobStore::open(&dir).unwrap();
+        let job = store
+            .create(serde_json::json!({"proof": "ab"}), None, 8, 100)
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.idempotency_key, format!("job:{}", job.id));
+        store.mark_running(&job.id, 101).unwrap();
+        drop(store);
+
+        // A job interrupted mid-run is pending again after restart
+        let store = JobStore::open(&dir).unwrap();
+        assert_eq!(store.pending(), vec![job.id.clone()]);
+        assert_eq!(store.get(&job.id).unwrap().status, JobStatus::Running);
+
+        store
+            .complete(&job.id, 200, serde_json::json!({"approved": true}), 102)
+            .unwrap();
+        drop(store);
+
+        let store = JobStore::open(&dir).unwrap();
+        let done = store.get(&job.id).unwrap();
+        assert_eq!(done.status, JobStatus::Completed);
+        assert_eq!(done.http_status, Some(200));
+        assert!(done.request.is_none());
+        assert!(store.pending().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pending_limit_and_prune() {
+        let dir = temp_dir("test_jobs_pending_limit");
+        let store = JobStore::open(&dir).unwrap();
+        let first = store
+            .create(serde_json::json!({}), Some("retry-1".into()), 1, 100)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.idempotency_key, "retry-1");
+        assert!(store
+            .create(serde_json::json!({}), None, 1, 100)
+            .unwrap()
+            .is_none());
+
+        store
+            .complete(&first.id, 403, serde_json::json!({"approved": false}), 100)
+            .unwrap();
+        assert_eq!(store.prune(100).unwrap(), 0);
+        assert_eq!(store.prune(101).unwrap(), 1);
+        assert!(store.get(&first.id).is_none());
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,227 @@
+//! The cosigner's signing keys: one primary key that signs new approvals,
+//! standby keys ready to be promoted, and retired keys kept (address only)
+//! so approvals they signed can still be checked.
+
+use crate::{persist::write_atomic, signer::Signer};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Default key ring state file.
+pub const DEFAULT_KEY_RING_PATH: &str = "./key_ring.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    /// Signs new approvals and transparency roots.
+    Primary,
+    /// Loaded and ready to be promoted.
+    Standby,
+    /// Verification only: its approvals stay valid but it signs nothing new.
+    Retired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeyEntry {
+    pub key_id: String,
+    pub address: String,
+    pub status: KeyStatus,
+    pub added_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promoted_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<u64>,
+}
+
+/// Short id of a key: the first 4 bytes of its address, in hex.
+pub fn key_id(address: &str) -> String {
+    address.trim_start_matches("0x")[..8].to_lowercase()
+}
+
+/// Key ring state persisted at `path`, plus the signers loaded for it.
+pub struct KeyRing {
+    path: PathBuf,
+    entries: Vec<KeyEntry>,
+    signers: HashMap<String, Arc<dyn Signer>>,
+}
+
+impl KeyRing {
+    /// Reconcile the persisted ring with the signers configured at startup. New keys
+    /// join as standby (the first one becomes primary in a fresh ring);
+    /// retired keys are dropped. Fails if the primary key was not loaded.
+    pub fn open(
+        path: impl AsRef<Path>,
+        signers: Vec<Arc<dyn Signer>>,
+        now: u64,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries: Vec<KeyEntry> = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Corrupt key ring {}: {e}", path.display()),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut loaded = HashMap::new();
+        for signer in signers {
+            let id = key_id(signer.address());
+            match entries.iter().find(|e| e.key_id == id) {
+                Some(entry) if entry.status == KeyStatus::Retired => {
+                    log::warn!("Key {id} is retired and will not sign; remove it from the config");
+                    continue;
+                }
+                Some(_) => {}
+                None => {
+                    let status = if entries.iter().any(|e| e.status == KeyStatus::Primary) {
+                        KeyStatus::Standby
+                    } else {
+                        KeyStatus::Primary
+                    };
+                    entries.push(KeyEntry {
+                        key_id: id.clone(),
+                        address: signer.address().to_string(),
+                        status,
+                        added_at: now,
+                        promoted_at: (status == KeyStatus::Primary).then_some(now),
+                        retired_at: None,
+                    });
+                }
+            }
+            log::info!("Key {id} ({}) is a {}", signer.address(), signer.describe());
+            loaded.insert(id, signer);
+        }
+
+        let Some(primary) = entries.iter().find(|e| e.status == KeyStatus::Primary) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No usable signing key configured",
+            ));
+        };
+        if !loaded.contains_key(&primary.key_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Primary key {} ({}) is not among the configured keys",
+                    primary.key_id, primary.address
+                ),
+            ));
+        }
+
+        let ring = KeyRing {
+            path,
+            entries,
+            signers: loaded,
+        };
+        ring.persist()?;
+        Ok(ring)
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.entries).map_err(io::Error::other)?;
+        write_atomic(&self.path, &json)
+    }
+
+    pub fn entries(&self) -> &[KeyEntry] {
+        &self.entries
+    }
+
+    /// The primary key's id and signer.
+    pub fn primary(&self) -> (&str, &Arc<dyn Signer>) {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.status == KeyStatus::Primary)
+            .expect("key ring always has a primary");
+        (&entry.key_id, &self.signers[&entry.key_id])
+    }
+
+    /// Make standby key `key_id` the primary and retire the current primary,
+    /// dropping its signer. Returns the retired entry.
+    pub fn promote(&mut self, key_id: &str, now: u64) -> io::Result<KeyEntry> {
+        let Some(index) = self.entries.iter().position(|e| e.key_id == key_id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unknown key {key_id}"),
+            ));
+        };
+        if self.entries[index].status != KeyStatus::Standby {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Key {key_id} is not a standby key"),
+            ));
+        }
+        let old = self
+            .entries
+            .iter()
+            .position(|e| e.status == KeyStatus::Primary)
+            .expect("key ring always has a primary");
+
+        let mut entries = self.entries.clone();
+        entries[old].status = KeyStatus::Retired;
+        entries[old].retired_at = Some(now);
+        entries[index].status = KeyStatus::Primary;
+        entries[index].promoted_at = Some(now);
+        let json = serde_json::to_vec_pretty(&entries).map_err(io::Error::other)?;
+        write_atomic(&self.path, &json)?;
+
+        self.entries = entries;
+        let retired = self.entries[old].clone();
+        self.signers.remove(&retired.key_id);
+        Ok(retired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::LocalSigner;
+    use k256::ecdsa::SigningKey;
+
+    fn key(byte: u8) -> Arc<dyn Signer> {
+        Arc::new(LocalSigner::new(
+            SigningKey::from_bytes((&[byte; 32]).into()).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_promote_and_reopen() {
+        let path = std::env::temp_dir().join("test_key_ring.json");
+        let _ = fs::remove_file(&path);
+
+        let ring = KeyRing::open(&path, vec![key(1), key(2)], 100).unwrap();
+        let (first, second) = (key_id(key(1).address()), key_id(key(2).address()));
+        assert_eq!(ring.primary().0, first);
+        assert_eq!(ring.entries()[1].status, KeyStatus::Standby);
+        drop(ring);
+
+        let mut ring = KeyRing::open(&path, vec![key(1), key(2)], 200).unwrap();
+        assert!(ring.promote(&first, 300).is_err());
+        assert!(ring.promote("nope", 300).is_err());
+        let retired = ring.promote(&second, 300).unwrap();
+        assert_eq!(retired.key_id, first);
+        assert_eq!(retired.retired_at, Some(300));
+        assert_eq!(ring.primary().0, second);
+        // Retired keys can't come back
+        assert!(ring.promote(&first, 400).is_err());
+        drop(ring);
+
+        // The old key is still configured, but stays retired
+        let ring = KeyRing::open(&path, vec![key(1), key(2)], 500).unwrap();
+        assert_eq!(ring.primary().0, second);
+        assert_eq!(ring.entries()[0].status, KeyStatus::Retired);
+        drop(ring);
+
+        // Refuse to start without the primary key
+        assert!(KeyRing::open(&path, vec![key(1)], 600).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+}
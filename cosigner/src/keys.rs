@@ -0,0 +1,143 @@
+//! Loading secp256k1 signing keys for local signers.
+//!
+//! Keys come from encrypted Web3 Secret Storage (keystore v3) files, unlocked
+//! with a passphrase from a file or an interactive prompt. A raw hex key in
+//! `COSIGNER_PRIVATE_KEY` is still accepted for development. Every
+//! intermediate copy of the key and passphrase is zeroized.
+
+use k256::ecdsa::SigningKey;
+use std::{fmt, path::Path};
+use zeroize::Zeroizing;
+
+/// Why the signing key could not be loaded.
+#[derive(Debug)]
+pub enum KeyError {
+    /// No keystore, plaintext key or remote signer is configured.
+    NotConfigured,
+    Passphrase(String),
+    Keystore(String),
+    InvalidKey(String),
+    Remote(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::NotConfigured => {
+                write!(
+                    f,
+                    "Set COSIGNER_KEYSTORE or COSIGNER_REMOTE_SIGNER (or COSIGNER_PRIVATE_KEY for development)"
+                )
+            }
+            KeyError::Passphrase(e) => write!(f, "Failed to read keystore passphrase: {e}"),
+            KeyError::Keystore(e) => write!(f, "Failed to decrypt keystore: {e}"),
+            KeyError::InvalidKey(e) => write!(f, "Invalid secp256k1 private key: {e}"),
+            KeyError::Remote(e) => write!(f, "Remote signer unavailable: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+fn signing_key_from_bytes(bytes: &[u8]) -> Result<SigningKey, KeyError> {
+    if bytes.len() != 32 {
+        return Err(KeyError::InvalidKey(format!(
+            "expected 32 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    SigningKey::from_bytes(bytes.into()).map_err(|e| KeyError::InvalidKey(e.to_string()))
+}
+
+/// Parse a hex private key, with or without a `0x` prefix.
+pub fn key_from_hex(hex_key: &str) -> Result<SigningKey, KeyError> {
+    let hex_key = hex_key.trim();
+    let hex_key = hex_key.strip_prefix("0x").unwrap_or(hex_key);
+    let bytes = Zeroizing::new(
+        hex::decode(hex_key).map_err(|e| KeyError::InvalidKey(format!("bad hex: {e}")))?,
+    );
+    signing_key_from_bytes(&bytes)
+}
+
+/// Decrypt a keystore v3 file.
+pub fn key_from_keystore(path: &Path, passphrase: &str) -> Result<SigningKey, KeyError> {
+    let bytes = Zeroizing::new(
+        eth_keystore::decrypt_key(path, passphrase)
+            .map_err(|e| KeyError::Keystore(format!("{}: {e}", path.display())))?,
+    );
+    signing_key_from_bytes(&bytes)
+}
+
+/// Read a passphrase file, dropping one trailing newline.
+pub fn read_passphrase_file(path: &Path) -> Result<Zeroizing<String>, KeyError> {
+    let mut passphrase = Zeroizing::new(
+        std::fs::read_to_string(path)
+            .map_err(|e| KeyError::Passphrase(format!("{}: {e}", path.display())))?,
+    );
+    if passphrase.ends_with('\n') {
+        passphrase.pop();
+        if passphrase.ends_with('\r') {
+            passphrase.pop();
+        }
+    }
+    Ok(passphrase)
+}
+
+/// Passphrase for `keystores`: the contents of
+/// `COSIGNER_KEYSTORE_PASSWORD_FILE`, or prompted for on the terminal.
+pub fn keystore_passphrase(keystores: &str) -> Result<Zeroizing<String>, KeyError> {
+    match std::env::var("COSIGNER_KEYSTORE_PASSWORD_FILE") {
+        Ok(file) => read_passphrase_file(Path::new(&file)),
+        Err(_) => Ok(Zeroizing::new(
+            rpassword::prompt_password(format!("Passphrase for {keystores}: "))
+                .map_err(|e| KeyError::Passphrase(e.to_string()))?,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::elliptic_curve::rand_core::OsRng;
+
+    const HARDHAT_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[test]
+    fn test_key_from_hex() {
+        let key = key_from_hex(HARDHAT_KEY).unwrap();
+        assert_eq!(key_from_hex(&format!("0x{HARDHAT_KEY}\n")).unwrap(), key);
+        assert!(matches!(key_from_hex("zz"), Err(KeyError::InvalidKey(_))));
+        assert!(matches!(key_from_hex("abcd"), Err(KeyError::InvalidKey(_))));
+        // Zero is not a valid scalar
+        assert!(matches!(
+            key_from_hex(&"00".repeat(32)),
+            Err(KeyError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let dir = std::env::temp_dir().join("test_cosigner_keystore");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret = hex::decode(HARDHAT_KEY).unwrap();
+        eth_keystore::encrypt_key(&dir, &mut OsRng, &secret, "correct horse", Some("key.json"))
+            .unwrap();
+        let password_file = dir.join("password");
+        std::fs::write(&password_file, "correct horse\n").unwrap();
+
+        let passphrase = read_passphrase_file(&password_file).unwrap();
+        let key = key_from_keystore(&dir.join("key.json"), &passphrase).unwrap();
+        assert_eq!(key, key_from_hex(HARDHAT_KEY).unwrap());
+        assert!(matches!(
+            key_from_keystore(&dir.join("key.json"), "wrong"),
+            Err(KeyError::Keystore(_))
+        ));
+        assert!(matches!(
+            key_from_keystore(&dir.join("missing.json"), "correct horse"),
+            Err(KeyError::Keystore(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
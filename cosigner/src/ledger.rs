@@ -0,0 +1,320 @@
+//! Durable ledger of approved spend, one JSON entry per line. Every approval
+//! is appended and fsynced before its nonce is issued or claimed, so
+//! rolling-window limits survive restarts; an approval that is then never
+//! signed is cancelled by a refund entry. Entries older than the retention
+//! window are dropped when the file is compacted.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use zkml_cosigner::{approval::TxDetails, persist::write_atomic};
+
+pub const DEFAULT_SPEND_LEDGER_PATH: &str = "./spend_ledger.jsonl";
+
+/// Compact once the file holds this many more lines than live entries.
+const COMPACT_SLACK: usize = 1024;
+
+/// One approved transfer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SpendEntry {
+    pub time: u64,
+    /// Lowercase addresses.
+    pub sender: String,
+    pub recipient: String,
+    pub token: String,
+    /// Base units, written as a string like `TxDetails::amount`.
+    #[serde(with = "amount_string")]
+    pub amount: u128,
+    /// Cancels an earlier entry with the same fields, whose approval was
+    /// never signed. It keeps that entry's time so both leave the window
+    /// together.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refund: bool,
+}
+
+impl SpendEntry {
+    pub fn new(tx: &TxDetails, amount: u128, time: u64) -> Self {
+        SpendEntry {
+            time,
+            sender: tx.from.to_lowercase(),
+            recipient: tx.to.to_lowercase(),
+            token: tx.token.to_lowercase(),
+            amount,
+            refund: false,
+        }
+    }
+
+    /// The entry cancelling this one.
+    pub fn refund(&self) -> Self {
+        SpendEntry {
+            refund: true,
+            ..self.clone()
+        }
+    }
+}
+
+mod amount_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &u128, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u128, D::Error> {
+        let s = String::deserialize(d)?;
+        crate::policy::parse_amount(&s)
+            .ok_or_else(|| D::Error::custom(format!("invalid amount {s:?}")))
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub struct SpendLedger {
+    path: PathBuf,
+    file: File,
+    /// Entries inside the retention window, oldest first but for refunds.
+    entries: VecDeque<SpendEntry>,
+    retention_secs: u64,
+    /// Lines in the file, including expired ones not yet compacted away.
+    lines: usize,
+}
+
+impl SpendLedger {
+    /// Open the ledger at `path`, keeping entries from the last
+    /// `retention_secs`. A torn last line (a crash mid-append) is dropped;
+    /// any other unreadable line refuses the ledger.
+    pub fn open(path: impl AsRef<Path>, retention_secs: u64, now: u64) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut lines = Vec::new();
+        match File::open(path) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    lines.push(line?);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let mut entries = VecDeque::new();
+        let mut torn = false;
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<SpendEntry>(line) {
+                Ok(entry) => entries.push_back(entry),
+                Err(e) if i + 1 == lines.len() => {
+                    log::warn!("Dropping torn last line of {}: {e}", path.display());
+                    torn = true;
+                }
+                Err(e) => return Err(invalid(format!("line {}: unreadable entry: {e}", i + 1))),
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut ledger = SpendLedger {
+            path: path.to_path_buf(),
+            file,
+            entries,
+            retention_secs,
+            lines: lines.len(),
+        };
+        ledger.expire(now);
+        if torn || ledger.lines > ledger.entries.len() {
+            ledger.compact()?;
+        }
+        Ok(ledger)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn expire(&mut self, now: u64) {
+        let since = now.saturating_sub(self.retention_secs);
+        // Refunds sit behind newer entries, so this can't stop at the first
+        // live one
+        self.entries.retain(|e| e.time >= since);
+    }
+
+    /// Rewrite the file with only the live entries.
+    fn compact(&mut self) -> io::Result<()> {
+        let mut contents = Vec::new();
+        for entry in &self.entries {
+            serde_json::to_writer(&mut contents, entry).map_err(io::Error::other)?;
+            contents.push(b'\n');
+        }
+        write_atomic(&self.path, &contents)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.lines = self.entries.len();
+        Ok(())
+    }
+
+    /// Durably append `entries` (in time order, but for refunds) with a
+    /// single fsync.
+    pub fn append(&mut self, entries: &[SpendEntry]) -> io::Result<()> {
+        let Some(last) = entries.last() else {
+            return Ok(());
+        };
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry).map_err(io::Error::other)?;
+            lines.push(b'\n');
+        }
+        self.file.write_all(&lines)?;
+        self.file.sync_data()?;
+        self.entries.extend(entries.iter().cloned());
+        self.lines += entries.len();
+
+        self.expire(last.time);
+        if self.lines > self.entries.len() + COMPACT_SLACK {
+            // The appends are already durable; a failed compaction only
+            // leaves expired lines behind
+            if let Err(e) = self.compact() {
+                log::warn!("Failed to compact {}: {e}", self.path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of approvals for `sender` (lowercase) since `since`, in any
+    /// token, less those refunded.
+    pub fn approvals(&self, sender: &str, since: u64) -> u64 {
+        let (approved, refunded) = self
+            .entries
+            .iter()
+            .filter(|e| e.time >= since && e.sender == sender)
+            .fold((0u64, 0u64), |(approved, refunded), e| {
+                if e.refund {
+                    (approved, refunded + 1)
+                } else {
+                    (approved + 1, refunded)
+                }
+            });
+        approved.saturating_sub(refunded)
+    }
+
+    /// Approved amount of `token` sent by `sender` since `since`, optionally
+    /// only to `recipient`, less refunds. Addresses must be lowercase.
+    pub fn spent(&self, sender: &str, token: &str, recipient: Option<&str>, since: u64) -> u128 {
+        let (approved, refunded) = self
+            .entries
+            .iter()
+            .filter(|e| {
+                e.time >= since
+                    && e.sender == sender
+                    && e.token == token
+                    && recipient.is_none_or(|r| e.recipient == r)
+            })
+            .fold((0u128, 0u128), |(approved, refunded), e| {
+                if e.refund {
+                    (approved, refunded.saturating_add(e.amount))
+                } else {
+                    (approved.saturating_add(e.amount), refunded)
+                }
+            });
+        approved.saturating_sub(refunded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn temp_ledger(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn entry(time: u64, sender: &str, amount: u128) -> SpendEntry {
+        SpendEntry {
+            time,
+            sender: sender.to_string(),
+            recipient: "0xshop".to_string(),
+            token: "0xtoken".to_string(),
+            amount,
+            refund: false,
+        }
+    }
+
+    #[test]
+    fn test_spent_survives_reopen() {
+        let path = temp_ledger("test_spend_ledger_reopen.jsonl");
+        let now = 10 * DAY;
+
+        let mut ledger = SpendLedger::open(&path, 7 * DAY, now).unwrap();
+        ledger
+            .append(&[
+                entry(now - 8 * DAY, "0xa", 1),
+                entry(now - 2 * DAY, "0xa", 20),
+            ])
+            .unwrap();
+        ledger
+            .append(&[entry(now - 60, "0xa", 300), entry(now, "0xb", 4000)])
+            .unwrap();
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, now - DAY), 300);
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, now - 7 * DAY), 320);
+        assert_eq!(ledger.spent("0xa", "0xother", None, 0), 0);
+        assert_eq!(ledger.spent("0xb", "0xtoken", Some("0xshop"), 0), 4000);
+        assert_eq!(ledger.spent("0xb", "0xtoken", Some("0xelse"), 0), 0);
+        drop(ledger);
+
+        // Expired entries are compacted away on open
+        let ledger = SpendLedger::open(&path, 7 * DAY, now).unwrap();
+        assert_eq!(ledger.len(), 3);
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, 0), 320);
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 3);
+    }
+
+    #[test]
+    fn test_torn_and_corrupt_lines() {
+        let path = temp_ledger("test_spend_ledger_torn.jsonl");
+        let good = serde_json::to_string(&entry(100, "0xa", 5)).unwrap();
+
+        std::fs::write(&path, format!("{good}\n{{\"time\": 10")).unwrap();
+        let mut ledger = SpendLedger::open(&path, DAY, 100).unwrap();
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, 0), 5);
+        ledger.append(&[entry(200, "0xa", 6)]).unwrap();
+        drop(ledger);
+        assert_eq!(
+            SpendLedger::open(&path, DAY, 200)
+                .unwrap()
+                .spent("0xa", "0xtoken", None, 0),
+            11
+        );
+
+        std::fs::write(&path, format!("garbage\n{good}\n")).unwrap();
+        assert!(SpendLedger::open(&path, DAY, 100).is_err());
+    }
+
+    #[test]
+    fn test_refunds_cancel_entries() {
+        let path = temp_ledger("test_spend_ledger_refund.jsonl");
+        let now = 10 * DAY;
+
+        let mut ledger = SpendLedger::open(&path, 7 * DAY, now).unwrap();
+        let first = entry(now - 2 * DAY, "0xa", 20);
+        let second = entry(now - 60, "0xa", 300);
+        ledger.append(&[first.clone(), second]).unwrap();
+        ledger.append(&[first.refund()]).unwrap();
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, 0), 300);
+        assert_eq!(ledger.approvals("0xa", 0), 1);
+        // The refund leaves the window with the entry it cancels
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, now - DAY), 300);
+        assert_eq!(ledger.approvals("0xa", now - DAY), 1);
+        drop(ledger);
+
+        let ledger = SpendLedger::open(&path, 7 * DAY, now).unwrap();
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, 0), 300);
+        let ledger = SpendLedger::open(&path, 7 * DAY, now + 6 * DAY).unwrap();
+        assert_eq!(ledger.spent("0xa", "0xtoken", None, 0), 300);
+        assert_eq!(ledger.approvals("0xa", 0), 1);
+    }
+}
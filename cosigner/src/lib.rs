@@ -0,0 +1,14 @@
+//! Modules shared by the cosigner service and its offline tools.
+
+pub mod approval;
+pub mod archive;
+pub mod audit;
+pub mod credential;
+pub mod directory;
+pub mod keyring;
+pub mod keys;
+pub mod multisig;
+pub mod persist;
+pub mod signer;
+pub mod transparency;
+pub mod two_party;
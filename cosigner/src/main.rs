@@ -1,44 +1,76 @@
 use actix_governor::{Governor, GovernorConfigBuilder};
-use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::{
+    dev::Service,
+    http::{header::RETRY_AFTER, StatusCode},
+    web, App, HttpResponse, HttpServer,
+};
 use ark_bn254::Fr;
-use ark_serialize::CanonicalDeserialize;
+use denylist::AddressList;
+use features::{InputBinding, Vocab};
+use handlers::verify::ProofVerifier;
+use jobs::JobStore;
 use jolt_core::{poly::commitment::dory::DoryCommitmentScheme, transcripts::KeccakTranscript};
-use k256::ecdsa::{signature::Signer, SigningKey};
-use onnx_tracer::{model, ProgramIO};
+use ledger::{SpendEntry, SpendLedger};
+use metrics::Metrics;
+use nonce::{NonceBackend, NonceStore};
+use onnx_tracer::model;
+use policy::{Policy, Violation};
+use reload::Reloadable;
 use serde::{Deserialize, Serialize};
+use sessions::{Sessions, TwoParty};
 use sha2::{Digest, Sha256};
 use std::{
     fs::File,
-    io::{Read, Write},
+    io::Read,
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+use verify_pool::VerifyPool;
+use zkml_cosigner::{
+    approval::TxDetails,
+    archive::{self, ArchivedDecision, ProofArchive},
+    audit::{self, AuditLog, AuditRecord},
+    credential::TrustCredential,
+    directory::MerchantDirectory,
+    keyring::{self, KeyRing},
+    keys, persist, signer,
+    transparency::{self, ApprovalLeaf, TransparencyLog},
 };
 
+mod denylist;
+mod features;
+mod handlers;
+mod idempotency;
+mod jobs;
+mod ledger;
+mod metrics;
+mod nonce;
+mod policy;
+mod reload;
+mod sessions;
+mod verify_pool;
+
 /// Jolt lookup table size (2^14 = 16384 entries).
 /// Must match the prover's table size for proof compatibility.
 const JOLT_TABLE_SIZE: usize = 1 << 14;
-use tiny_keccak::{Hasher, Keccak};
 use zkml_jolt_core::jolt::{JoltSNARK, JoltVerifierPreprocessing};
 
 #[allow(clippy::upper_case_acronyms)]
 type PCS = DoryCommitmentScheme;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct VerifyRequest {
     proof: String,      // hex-encoded serialized proof
     program_io: String, // JSON-serialized ProgramIO
     tx: TxDetails,
     model_hash: String, // SHA256 of the ONNX model used by prover
+    /// Issuer-signed trust bucket; required when `TRUST_ISSUERS` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trust_credential: Option<TrustCredential>,
 }
 
-#[derive(Deserialize, Clone)]
-struct TxDetails {
-    to: String,
-    amount: String,
-    token: String,
-}
-
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Default)]
 struct VerifyResponse {
     approved: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -47,64 +79,292 @@ struct VerifyResponse {
     nonce: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     timestamp: Option<u64>,
+    /// Key ring id of the key that made `signature`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    /// Version of the spending policy the decision was made under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy_version: Option<String>,
+    /// Policy rule that refused the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy_rule: Option<String>,
+    /// Denylist (`name@version`) that matched the sender or recipient.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    denylist: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     reason: Option<String>,
 }
 
-/// Persistent nonce state backed by a JSON file.
-/// Uses a simple counter - any nonce <= counter is implicitly "used".
-#[derive(Serialize, Deserialize)]
-struct NonceState {
-    counter: u64,
-    #[serde(skip)]
-    path: String,
+impl VerifyResponse {
+    /// A refusal for `reason`; every other field is unset.
+    fn refused(reason: impl Into<String>) -> Self {
+        VerifyResponse {
+            reason: Some(reason.into()),
+            ..VerifyResponse::default()
+        }
+    }
+}
+
+/// Verifier preprocessing, filled in by a background thread after startup so
+/// the server can answer probes while it runs.
+struct Verifier {
+    snark: Arc<dyn ProofVerifier>,
+    prepared_in: Duration,
 }
 
-impl NonceState {
-    fn load(path: &str) -> Self {
-        if let Ok(mut f) = File::open(path) {
-            let mut contents = String::new();
-            if f.read_to_string(&mut contents).is_ok() {
-                if let Ok(mut state) = serde_json::from_str::<NonceState>(&contents) {
-                    state.path = path.to_string();
-                    return state;
-                }
+struct AppState {
+    verifier: OnceLock<Verifier>,
+    started_at: Instant,
+    verify_pool: VerifyPool,
+    verify_retry_after_secs: u64,
+    /// Primary, standby and retired signing keys.
+    key_ring: RwLock<KeyRing>,
+    /// Bearer token for admin operations; they are disabled when unset.
+    admin_api_token: Option<String>,
+    /// Bearer token for M-of-N coordinators; `/v1/cosign` is disabled when unset.
+    cosign_api_token: Option<String>,
+    nonce_state: Mutex<Box<dyn NonceStore>>,
+    model_hash: String,
+    idempotency_ttl_secs: u64,
+    jobs: JobStore,
+    jobs_max_pending: usize,
+    jobs_retention_secs: u64,
+    batch_max_items: usize,
+    metrics: Arc<Metrics>,
+    audit: Mutex<AuditLog>,
+    /// Bearer token for the audit API; the API is disabled when unset.
+    audit_api_token: Option<String>,
+    transparency: Mutex<TransparencyLog>,
+    /// Submitted proofs and the decisions made on them, by proof hash.
+    archive: ProofArchive,
+    archive_retention_secs: u64,
+    /// Which input slots encode which feature.
+    vocab: Vocab,
+    /// How the decoded input must match the transaction, if configured.
+    input_binding: Option<InputBinding>,
+    /// Addresses whose trust credentials are accepted; when set, every
+    /// request must carry one.
+    trust_issuers: Option<Vec<String>>,
+    /// Recipient categories the decoded `category` must match, if configured.
+    merchant_directory: Option<Reloadable<MerchantDirectory>>,
+    /// Address lists the sender and recipient are screened against.
+    denylists: Vec<Reloadable<AddressList>>,
+    /// Spending policy applied after proof verification, if configured.
+    policy: Option<Policy>,
+    /// Every approved transfer, which the policy caps are measured against.
+    spend_ledger: Mutex<SpendLedger>,
+    /// Two-party ECDSA; disabled unless `TWO_PARTY_KEY_DIR` is set.
+    two_party: Option<TwoParty>,
+}
+
+impl AppState {
+    /// Reload config files that changed on disk, keeping the current version
+    /// of any that fail to load.
+    fn reload_config(&self) {
+        if let Some(directory) = &self.merchant_directory {
+            match directory.refresh() {
+                Ok(None) => {}
+                Ok(Some(d)) => log::info!(
+                    "Reloaded merchant directory {} ({} merchants)",
+                    d.version(),
+                    d.entries.merchants.len()
+                ),
+                Err(e) => log::warn!("Keeping the previous merchant directory: {e}"),
             }
         }
-        NonceState {
-            counter: 0,
-            path: path.to_string(),
+        for list in &self.denylists {
+            match list.refresh() {
+                Ok(None) => {}
+                Ok(Some(l)) => {
+                    log::info!("Reloaded denylist {} ({} addresses)", l.label(), l.len())
+                }
+                Err(e) => log::warn!("Keeping the previous denylist: {e}"),
+            }
         }
     }
 
-    fn save(&self) -> std::io::Result<()> {
-        let json = serde_json::to_string(self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        let mut f = File::create(&self.path)?;
-        f.write_all(json.as_bytes())?;
-        Ok(())
+    /// The locked spend ledger. Lock the nonce store first when holding both.
+    fn lock_ledger(&self) -> MutexGuard<'_, SpendLedger> {
+        self.spend_ledger.lock().unwrap_or_else(|e| e.into_inner())
     }
 
-    fn next_nonce(&mut self) -> Result<u64, std::io::Error> {
-        self.counter += 1;
-        self.save()?;
-        Ok(self.counter)
+    /// Count a final verdict in the request metrics.
+    fn observe(&self, endpoint: &str, verdict: &Verdict) {
+        let (outcome, reason) = verdict.outcome();
+        self.metrics.observe_request(endpoint, outcome, reason);
     }
-}
 
-struct AppState {
-    verifier_preprocessing: JoltVerifierPreprocessing<Fr, PCS>,
-    signing_key: SigningKey,
-    nonce_state: Mutex<NonceState>,
-    model_hash: String,
-}
+    /// Append a verdict to the audit log, archive the proof and count it in
+    /// the metrics. Returns the verdict to send: an approval that can't be
+    /// logged is withheld and becomes a 500. This fsyncs and rewrites the
+    /// proof envelope, so call it through [`handlers::verify::record`] off
+    /// the workers.
+    fn record_blocking(&self, endpoint: &str, req: &VerifyRequest, verdict: Verdict) -> Verdict {
+        let (outcome, _) = verdict.outcome();
+        let response: Option<VerifyResponse> = serde_json::from_str(&verdict.body).ok();
+        let response = response.as_ref();
+        let record = AuditRecord {
+            seq: 0,
+            time: unix_now(),
+            endpoint: endpoint.to_string(),
+            outcome: outcome.to_string(),
+            status: verdict.status.as_u16(),
+            reason_code: verdict.reason_code.map(str::to_string),
+            reason: response.and_then(|r| r.reason.clone()),
+            request_digest: idempotency::request_digest(
+                &serde_json::to_vec(req).expect("VerifyRequest serializes"),
+            ),
+            proof_hash: hex::decode(&req.proof)
+                .ok()
+                .map(|bytes| archive::proof_hash(&bytes)),
+            model_hash: req.model_hash.clone(),
+            from: req.tx.from.clone(),
+            to: req.tx.to.clone(),
+            amount: req.tx.amount.clone(),
+            token: req.tx.token.clone(),
+            chain_id: req.tx.chain_id,
+            nonce: response.and_then(|r| r.nonce),
+            signature: response.and_then(|r| r.signature.clone()),
+            timestamp: response.and_then(|r| r.timestamp),
+            key_id: response.and_then(|r| r.key_id.clone()),
+            policy_version: response.and_then(|r| r.policy_version.clone()),
+            policy_rule: response.and_then(|r| r.policy_rule.clone()),
+            denylist: response.and_then(|r| r.denylist.clone()),
+            prev_hash: String::new(),
+        };
+        let leaf = match (outcome, response) {
+            (
+                "approved",
+                Some(VerifyResponse {
+                    nonce: Some(nonce),
+                    timestamp: Some(timestamp),
+                    signature: Some(signature),
+                    ..
+                }),
+            ) => Some(ApprovalLeaf {
+                chain_id: req.tx.chain_id,
+                from: req.tx.from.clone(),
+                to: req.tx.to.clone(),
+                amount: req.tx.amount.clone(),
+                token: req.tx.token.clone(),
+                nonce: *nonce,
+                timestamp: *timestamp,
+                signature: signature.clone(),
+            }),
+            _ => None,
+        };
 
-fn keccak256(data: &[u8]) -> [u8; 32] {
-    let mut hasher = Keccak::v256();
-    let mut output = [0u8; 32];
-    hasher.update(data);
-    hasher.finalize(&mut output);
-    output
+        let archived = record.proof_hash.is_some().then(|| ArchivedDecision {
+            time: record.time,
+            outcome: record.outcome.clone(),
+            status: record.status,
+            reason: record.reason.clone(),
+            audit_seq: None,
+            model_hash: record.model_hash.clone(),
+            program_io: None,
+            chain_id: record.chain_id,
+            from: record.from.clone(),
+            to: record.to.clone(),
+            amount: record.amount.clone(),
+            token: record.token.clone(),
+            nonce: record.nonce,
+            timestamp: record.timestamp,
+            signature: record.signature.clone(),
+            key_id: record.key_id.clone(),
+        });
+
+        let mut audit = self.audit.lock().unwrap_or_else(|e| e.into_inner());
+        let audit_seq = match audit.append(record) {
+            Ok(entry) => Some(entry.record.seq),
+            Err(e) if matches!(outcome, "approved" | "replayed") => {
+                log::error!("Failed to append to audit log; withholding the approval: {e}");
+                let withheld = Verdict::rejected(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "audit_log",
+                    format!("Internal error: failed to record the decision: {e}"),
+                );
+                self.observe(endpoint, &withheld);
+                return withheld;
+            }
+            Err(e) => {
+                log::error!("Failed to append to audit log: {e}");
+                None
+            }
+        };
+        drop(audit);
+        self.observe(endpoint, &verdict);
+
+        if let Some(decision) = archived {
+            let decision = ArchivedDecision {
+                audit_seq,
+                ..decision
+            };
+            if let Err(e) = self.archive.record(&req.proof, &req.program_io, decision) {
+                log::error!("Failed to archive proof: {e}");
+            }
+        }
+
+        if let Some(leaf) = leaf {
+            let mut log = self.transparency.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = log.append(&leaf) {
+                log::error!("Failed to append to transparency log: {e}");
+            }
+        }
+        verdict
+    }
+
+    /// Append an admin operation to the audit log.
+    fn record_admin(&self, outcome: &str, reason: String, key_id: Option<String>) {
+        let record = AuditRecord {
+            seq: 0,
+            time: unix_now(),
+            endpoint: "admin".to_string(),
+            outcome: outcome.to_string(),
+            status: 200,
+            reason_code: None,
+            reason: Some(reason),
+            request_digest: String::new(),
+            proof_hash: None,
+            model_hash: self.model_hash.clone(),
+            from: String::new(),
+            to: String::new(),
+            amount: String::new(),
+            token: String::new(),
+            chain_id: 0,
+            nonce: None,
+            signature: None,
+            timestamp: None,
+            key_id,
+            policy_version: None,
+            policy_rule: None,
+            denylist: None,
+            prev_hash: String::new(),
+        };
+        let mut audit = self.audit.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = audit.append(record) {
+            log::error!("Failed to append to audit log: {e}");
+        }
+    }
+
+    /// Sign and publish the transparency root if new approvals were logged.
+    fn publish_root(&self) {
+        let mut log = self.transparency.lock().unwrap_or_else(|e| e.into_inner());
+        let ring = self.key_ring.read().unwrap_or_else(|e| e.into_inner());
+        let published = log.publish(unix_now(), |digest| {
+            let (key_id, signer) = ring.primary();
+            let signature = signer.sign(digest).map_err(std::io::Error::other)?;
+            Ok((key_id.to_string(), signature))
+        });
+        match published {
+            Ok(Some(root)) => log::info!(
+                "Published transparency root {} at tree size {}",
+                root.root_hash,
+                root.tree_size
+            ),
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to publish transparency root: {e}"),
+        }
+    }
 }
 
 /// Compute SHA256 hash of a file, returned as hex string.
@@ -124,168 +384,134 @@ fn sha256_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
     Ok(hex::encode(hasher.finalize()))
 }
 
-async fn verify_proof(data: web::Data<AppState>, req: web::Json<VerifyRequest>) -> HttpResponse {
-    // 0. Check model hash matches
-    if req.model_hash != data.model_hash {
-        return HttpResponse::BadRequest().json(VerifyResponse {
-            approved: false,
-            signature: None,
-            nonce: None,
-            timestamp: None,
-            reason: Some(format!(
-                "Model hash mismatch: prover={}, cosigner={}",
-                req.model_hash, data.model_hash
-            )),
-        });
-    }
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
-    // 1. Deserialize proof
-    let proof_bytes = match hex::decode(&req.proof) {
-        Ok(b) => b,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(VerifyResponse {
-                approved: false,
-                signature: None,
-                nonce: None,
-                timestamp: None,
-                reason: Some(format!("Invalid proof hex: {e}")),
-            });
-        }
-    };
+/// Outcome of evaluating a verify request: an HTTP status plus the serialized
+/// `VerifyResponse`, shared by `/verify` and asynchronous verification jobs.
+struct Verdict {
+    status: StatusCode,
+    body: String,
+    /// Set on 503s so clients know when to retry.
+    retry_after: Option<u64>,
+    /// True when `body` was replayed from an idempotency record.
+    replayed: bool,
+    /// Low-cardinality rejection code for metrics; `None` for approvals.
+    reason_code: Option<&'static str>,
+}
 
-    let snark = match JoltSNARK::<Fr, PCS, KeccakTranscript>::deserialize_compressed(
-        proof_bytes.as_slice(),
-    ) {
-        Ok(s) => s,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(VerifyResponse {
-                approved: false,
-                signature: None,
-                nonce: None,
-                timestamp: None,
-                reason: Some(format!("Failed to deserialize proof: {e}")),
-            });
+impl Verdict {
+    fn approved(body: String) -> Self {
+        Verdict {
+            status: StatusCode::OK,
+            body,
+            retry_after: None,
+            replayed: false,
+            reason_code: None,
         }
-    };
+    }
 
-    // 2. Deserialize program_io
-    let program_io: ProgramIO = match serde_json::from_str(&req.program_io) {
-        Ok(p) => p,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(VerifyResponse {
-                approved: false,
-                signature: None,
-                nonce: None,
-                timestamp: None,
-                reason: Some(format!("Failed to deserialize program_io: {e}")),
-            });
+    /// Refusal with `response` as the body.
+    fn refusal(status: StatusCode, code: &'static str, response: VerifyResponse) -> Self {
+        Verdict {
+            status,
+            body: serde_json::to_string(&response).expect("VerifyResponse serializes"),
+            retry_after: None,
+            replayed: false,
+            reason_code: Some(code),
         }
-    };
+    }
 
-    // 3. Check output indicates AUTHORIZED (class 0 has highest value)
-    // NOTE: ProgramIO contains fixed-point i32 values after Jolt circuit execution,
-    // so integer cmp is correct here. The ONNX model originally outputs floats, but
-    // those are converted to fixed-point integers during circuit execution.
-    let output_data: Vec<i32> = program_io.output.iter().cloned().collect();
-    if output_data.is_empty() {
-        return HttpResponse::Forbidden().json(VerifyResponse {
-            approved: false,
-            signature: None,
-            nonce: None,
-            timestamp: None,
-            reason: Some("Empty model output".to_string()),
-        });
+    fn rejected(status: StatusCode, code: &'static str, reason: impl Into<String>) -> Self {
+        Verdict::refusal(status, code, VerifyResponse::refused(reason))
     }
-    let (pred_idx, _) = output_data
-        .iter()
-        .enumerate()
-        .max_by(|a, b| a.1.cmp(b.1))
-        .unwrap(); // safe: checked non-empty above
-    if pred_idx != 0 {
-        return HttpResponse::Forbidden().json(VerifyResponse {
-            approved: false,
-            signature: None,
-            nonce: None,
-            timestamp: None,
-            reason: Some("Model output is DENIED (class != 0)".to_string()),
-        });
+
+    /// Refusal by a spending policy rule.
+    fn policy_refused(violation: Violation) -> Self {
+        let reason = format!(
+            "Refused by policy rule {}: {}",
+            violation.rule, violation.detail
+        );
+        Verdict::refusal(
+            StatusCode::FORBIDDEN,
+            "policy",
+            VerifyResponse {
+                policy_version: Some(violation.version),
+                policy_rule: Some(violation.rule.to_string()),
+                ..VerifyResponse::refused(reason)
+            },
+        )
     }
 
-    // 4. Verify the SNARK proof
-    log::info!("Verifying SNARK proof...");
-    let verify_start = std::time::Instant::now();
-    if let Err(e) = snark.verify(&data.verifier_preprocessing, program_io, None) {
-        return HttpResponse::Forbidden().json(VerifyResponse {
-            approved: false,
-            signature: None,
-            nonce: None,
-            timestamp: None,
-            reason: Some(format!("Proof verification failed: {e}")),
-        });
+    /// Refusal because `address` is on `list`.
+    fn denylisted(list: &AddressList, role: &str, address: &str) -> Self {
+        let reason = format!("The {role} {address} is on denylist {}", list.label());
+        Verdict::refusal(
+            StatusCode::FORBIDDEN,
+            "denylisted",
+            VerifyResponse {
+                denylist: Some(list.label()),
+                ..VerifyResponse::refused(reason)
+            },
+        )
     }
-    log::info!("Proof verified in {:?}", verify_start.elapsed());
 
-    // 5. Generate nonce and sign approval
-    let nonce = {
-        let mut state = data.nonce_state.lock().unwrap_or_else(|e| e.into_inner());
-        match state.next_nonce() {
-            Ok(n) => n,
-            Err(e) => {
-                log::error!("Failed to persist nonce state: {e}");
-                return HttpResponse::InternalServerError().json(VerifyResponse {
-                    approved: false,
-                    signature: None,
-                    nonce: None,
-                    timestamp: None,
-                    reason: Some(format!("Internal error: failed to persist nonce: {e}")),
-                });
-            }
+    /// `(outcome, reason)` labels for the request metrics.
+    fn outcome(&self) -> (&'static str, &'static str) {
+        match self.reason_code {
+            _ if self.replayed => ("replayed", "none"),
+            None => ("approved", "none"),
+            Some(code) if self.status.is_server_error() => ("error", code),
+            Some(code) => ("rejected", code),
         }
-    };
-
-    // Include timestamp for signature expiration
-    // NOTE: The client/contract should reject signatures older than a configurable
-    // threshold (e.g., 300 seconds) to prevent replay of stale approvals.
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    // Sign: keccak256(to || amount || token || nonce || timestamp)
-    let mut msg = Vec::new();
-    msg.extend_from_slice(req.tx.to.as_bytes());
-    msg.extend_from_slice(req.tx.amount.as_bytes());
-    msg.extend_from_slice(req.tx.token.as_bytes());
-    msg.extend_from_slice(&nonce.to_be_bytes());
-    msg.extend_from_slice(&timestamp.to_be_bytes());
-    let hash = keccak256(&msg);
-
-    let (sig, _) = data.signing_key.sign(&hash);
-    let sig_hex = hex::encode(sig.to_bytes());
-
-    HttpResponse::Ok().json(VerifyResponse {
-        approved: true,
-        signature: Some(sig_hex),
-        nonce: Some(nonce),
-        timestamp: Some(timestamp),
-        reason: None,
-    })
-}
+    }
 
-async fn health() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+    fn into_response(self) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status);
+        resp.content_type("application/json");
+        if let Some(secs) = self.retry_after {
+            resp.insert_header((RETRY_AFTER, secs.to_string()));
+        }
+        if self.replayed {
+            resp.insert_header((idempotency::REPLAYED_HEADER, "true"));
+        }
+        resp.body(self.body)
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    // Parse signing key first so we fail fast before expensive model preprocessing
-    let cosigner_key_hex =
-        std::env::var("COSIGNER_PRIVATE_KEY").expect("COSIGNER_PRIVATE_KEY env var must be set");
-    let key_bytes = hex::decode(&cosigner_key_hex).expect("Invalid COSIGNER_PRIVATE_KEY hex");
-    let signing_key =
-        SigningKey::from_bytes((&key_bytes[..]).into()).expect("Invalid secp256k1 private key");
+    // Load signing keys first so we fail fast before expensive model preprocessing
+    let signers = signer::from_env().map_err(|e| {
+        log::error!("{e}");
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+    })?;
+    let key_ring_path = std::env::var("KEY_RING_PATH")
+        .unwrap_or_else(|_| keyring::DEFAULT_KEY_RING_PATH.to_string());
+    let key_ring = KeyRing::open(&key_ring_path, signers, unix_now()).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to open key ring {key_ring_path}: {e}"),
+        )
+    })?;
+    let (primary_id, primary) = key_ring.primary();
+    log::info!(
+        "Signing as {} (key {primary_id}, {})",
+        primary.address(),
+        primary.describe()
+    );
+    let admin_api_token = std::env::var("ADMIN_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty());
+    let cosign_api_token = std::env::var("COSIGN_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty());
 
     let models_dir = std::env::var("MODELS_DIR").unwrap_or_else(|_| {
         let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
@@ -296,26 +522,409 @@ async fn main() -> std::io::Result<()> {
 
     let model_hash = sha256_file(&model_path).expect("Failed to compute model hash");
     log::info!("Model SHA256: {model_hash}");
+    let vocab = Vocab::load(Path::new(&format!("{models_dir}/vocab.json")))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let trust_issuers = std::env::var("TRUST_ISSUERS")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| {
+            s.split(',')
+                .map(|a| a.trim().to_lowercase())
+                .filter(|a| !a.is_empty())
+                .collect::<Vec<_>>()
+        });
+    if let Some(issuers) = &trust_issuers {
+        log::info!("Requiring trust credentials from {}", issuers.join(", "));
+    }
+    let merchant_directory = match std::env::var("MERCHANT_DIRECTORY_PATH") {
+        Ok(path) => {
+            let signers: Vec<String> = std::env::var("MERCHANT_DIRECTORY_SIGNERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|a| a.trim().to_lowercase())
+                .filter(|a| !a.is_empty())
+                .collect();
+            if signers.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "MERCHANT_DIRECTORY_PATH requires MERCHANT_DIRECTORY_SIGNERS",
+                ));
+            }
+            let directory =
+                Reloadable::open(&path, move |p: &Path| MerchantDirectory::load(p, &signers))
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let current = directory.current();
+            log::info!(
+                "Loaded merchant directory {} from {path} ({} merchants)",
+                current.version(),
+                current.entries.merchants.len()
+            );
+            Some(directory)
+        }
+        Err(_) => None,
+    };
+    let mut denylists = Vec::new();
+    for path in std::env::var("DENYLIST_PATHS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        let list = Reloadable::open(path, AddressList::load)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let current = list.current();
+        log::info!(
+            "Screening against denylist {} from {path} ({} addresses)",
+            current.label(),
+            current.len()
+        );
+        denylists.push(list);
+    }
+    let config_reload_secs = std::env::var("CONFIG_RELOAD_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30u64);
+    let input_binding = match std::env::var("INPUT_BINDING_PATH") {
+        Ok(path) => {
+            let binding = InputBinding::load(Path::new(&path), &vocab)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            log::info!("Binding model inputs to transfers as configured in {path}");
+            Some(binding)
+        }
+        Err(_) => {
+            log::warn!(
+                "INPUT_BINDING_PATH not set; model inputs are not checked against transfers"
+            );
+            None
+        }
+    };
 
-    let nonce_state_path =
-        std::env::var("NONCE_STATE_PATH").unwrap_or_else(|_| "./nonce_state.json".to_string());
-    let nonce_state = NonceState::load(&nonce_state_path);
-    log::info!("Loaded nonce state (counter={})", nonce_state.counter);
+    // Refuse to start on unreadable nonce state: resetting would re-issue old nonces
+    let nonce_backend =
+        NonceBackend::parse(&std::env::var("NONCE_STORE").unwrap_or_else(|_| "file".to_string()))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let nonce_state_path = std::env::var("NONCE_STATE_PATH")
+        .unwrap_or_else(|_| nonce_backend.default_path().to_string());
+    let nonce_state = nonce_backend.open(&nonce_state_path).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to load nonce state from {nonce_state_path}: {e}"),
+        )
+    })?;
+    log::info!(
+        "Loaded {nonce_backend:?} nonce state from {nonce_state_path} ({} domains)",
+        nonce_state.domain_count()?
+    );
 
-    log::info!("Preprocessing authorization model for verifier...");
-    let model_fn = || model(&PathBuf::from(&model_path));
-    let preprocessing =
-        JoltSNARK::<Fr, PCS, KeccakTranscript>::prover_preprocess(model_fn, JOLT_TABLE_SIZE);
-    let verifier_preprocessing: JoltVerifierPreprocessing<Fr, PCS> = (&preprocessing).into();
-    log::info!("Verifier preprocessing ready");
+    let idempotency_ttl_secs = std::env::var("IDEMPOTENCY_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(idempotency::DEFAULT_TTL_SECS);
+
+    // Proof verification runs on its own threads; beyond the queue depth,
+    // /verify answers 503 so the actix workers stay free for /health
+    let verify_concurrency = std::env::var("VERIFY_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+    let verify_queue_depth = std::env::var("VERIFY_QUEUE_DEPTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(16);
+    let verify_retry_after_secs = std::env::var("VERIFY_RETRY_AFTER_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+    let verify_pool = VerifyPool::new(verify_concurrency, verify_queue_depth);
+    log::info!(
+        "Verification pool: {} workers, queue depth {}",
+        verify_pool.workers(),
+        verify_pool.queue_depth()
+    );
+
+    // Async verification jobs are persisted so a restart resumes them
+    let jobs_dir = std::env::var("JOBS_DIR").unwrap_or_else(|_| jobs::DEFAULT_JOBS_DIR.to_string());
+    let jobs = JobStore::open(&jobs_dir).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to load verification jobs from {jobs_dir}: {e}"),
+        )
+    })?;
+    let jobs_max_pending = std::env::var("VERIFY_JOBS_MAX_PENDING")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(256);
+    let jobs_retention_secs = std::env::var("VERIFY_JOBS_RETENTION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(jobs::DEFAULT_RETENTION_SECS);
+
+    let batch_max_items = std::env::var("VERIFY_BATCH_MAX_ITEMS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(16);
+
+    let metrics = Arc::new(Metrics::new());
+
+    let transparency_log_path = std::env::var("TRANSPARENCY_LOG_PATH")
+        .unwrap_or_else(|_| transparency::DEFAULT_TRANSPARENCY_LOG_PATH.to_string());
+    let transparency_log = TransparencyLog::open(&transparency_log_path).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to open transparency log {transparency_log_path}: {e}"),
+        )
+    })?;
+    let transparency_interval_secs = std::env::var("TRANSPARENCY_ROOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+
+    let archive_dir = std::env::var("PROOF_ARCHIVE_DIR")
+        .unwrap_or_else(|_| archive::DEFAULT_PROOF_ARCHIVE_DIR.to_string());
+    let proof_archive = ProofArchive::open(&archive_dir).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to open proof archive {archive_dir}: {e}"),
+        )
+    })?;
+    let archive_retention_secs = std::env::var("PROOF_ARCHIVE_RETENTION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(archive::DEFAULT_RETENTION_SECS);
+
+    let audit_api_token = std::env::var("AUDIT_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty());
+    if audit_api_token.is_none() {
+        log::info!("AUDIT_API_TOKEN not set; audit query API disabled");
+    }
+
+    // Refuse to start on a broken audit chain rather than extend it
+    let audit_log_path = std::env::var("AUDIT_LOG_PATH")
+        .unwrap_or_else(|_| audit::DEFAULT_AUDIT_LOG_PATH.to_string());
+    let audit_log = AuditLog::open(&audit_log_path).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to open audit log {audit_log_path}: {e}"),
+        )
+    })?;
+    log::info!(
+        "Audit log {audit_log_path} verified ({} entries)",
+        audit_log.head().seq
+    );
+
+    let policy = match std::env::var("POLICY_PATH") {
+        Ok(path) => {
+            let policy = Policy::load(Path::new(&path))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            log::info!("Spending policy {} loaded from {path}", policy.version());
+            Some(policy)
+        }
+        Err(_) => None,
+    };
+
+    // The ledger keeps as much history as the policy's longest cap and the
+    // input binding's longest window need
+    let spend_ledger_path = std::env::var("SPEND_LEDGER_PATH")
+        .unwrap_or_else(|_| ledger::DEFAULT_SPEND_LEDGER_PATH.to_string());
+    let lookback_secs = policy
+        .as_ref()
+        .map_or(7 * 24 * 60 * 60, Policy::lookback_secs)
+        .max(
+            input_binding
+                .as_ref()
+                .map_or(0, InputBinding::lookback_secs),
+        );
+    let now = unix_now();
+    let new_ledger = !Path::new(&spend_ledger_path).exists();
+    let mut spend_ledger =
+        SpendLedger::open(&spend_ledger_path, lookback_secs, now).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Failed to open spend ledger {spend_ledger_path}: {e}"),
+            )
+        })?;
+    if new_ledger {
+        // Start a new ledger from the approvals already in the audit log, so
+        // enabling it doesn't reset the caps
+        let since = now.saturating_sub(lookback_secs);
+        let entries: Vec<SpendEntry> = audit::read_log(audit_log.path())?
+            .into_iter()
+            .map(|entry| entry.record)
+            .filter(|r| r.outcome == "approved" && r.time >= since)
+            .filter_map(|r| {
+                let amount = policy::parse_amount(&r.amount)?;
+                Some(SpendEntry {
+                    time: r.time,
+                    sender: r.from.to_lowercase(),
+                    recipient: r.to.to_lowercase(),
+                    token: r.token.to_lowercase(),
+                    amount,
+                    refund: false,
+                })
+            })
+            .collect();
+        spend_ledger.append(&entries)?;
+    }
+    log::info!(
+        "Spend ledger {spend_ledger_path} opened ({} approvals in the last {}s)",
+        spend_ledger.len(),
+        lookback_secs
+    );
+
+    let two_party = match std::env::var("TWO_PARTY_KEY_DIR") {
+        Ok(path) => {
+            let max_sessions = std::env::var("TWO_PARTY_MAX_SESSIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(sessions::DEFAULT_MAX_SESSIONS);
+            let session_ttl_secs = std::env::var("TWO_PARTY_SESSION_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(sessions::DEFAULT_SESSION_TTL_SECS);
+            let invalid = |e: keys::KeyError| {
+                log::error!("{e}");
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+            };
+            let passphrase = keys::keystore_passphrase(&path).map_err(invalid)?;
+            let sessions = Sessions::new(max_sessions, Duration::from_secs(session_ttl_secs));
+            let two_party =
+                TwoParty::open(PathBuf::from(&path), passphrase, sessions).map_err(invalid)?;
+            log::info!(
+                "Two-party signing enabled with {} key shares in {path}",
+                two_party.addresses().len()
+            );
+            Some(two_party)
+        }
+        Err(_) => None,
+    };
 
     let state = web::Data::new(AppState {
-        verifier_preprocessing,
-        signing_key,
+        verifier: OnceLock::new(),
+        started_at: Instant::now(),
+        verify_pool,
+        verify_retry_after_secs,
         nonce_state: Mutex::new(nonce_state),
         model_hash,
+        idempotency_ttl_secs,
+        jobs,
+        jobs_max_pending,
+        jobs_retention_secs,
+        batch_max_items,
+        metrics: Arc::clone(&metrics),
+        audit: Mutex::new(audit_log),
+        audit_api_token,
+        key_ring: RwLock::new(key_ring),
+        admin_api_token,
+        cosign_api_token,
+        transparency: Mutex::new(transparency_log),
+        archive: proof_archive,
+        archive_retention_secs,
+        vocab,
+        input_binding,
+        trust_issuers,
+        merchant_directory,
+        denylists,
+        policy,
+        spend_ledger: Mutex::new(spend_ledger),
+        two_party,
+    });
+
+    // Publish a signed transparency root whenever new approvals have landed
+    let publisher_state = state.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(Duration::from_secs(transparency_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            let state = publisher_state.clone();
+            if let Err(e) = web::block(move || state.publish_root()).await {
+                log::error!("Failed to publish transparency root: {e}");
+            }
+        }
+    });
+
+    // Pick up edits to reloadable config files
+    let reload_state = state.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(Duration::from_secs(config_reload_secs.max(1)));
+        loop {
+            interval.tick().await;
+            let state = reload_state.clone();
+            if let Err(e) = web::block(move || state.reload_config()).await {
+                log::warn!("Failed to reload config: {e}");
+            }
+        }
+    });
+
+    // Drop archived proofs whose last decision is past retention
+    let pruner_state = state.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let state = pruner_state.clone();
+            let pruned = web::block(move || {
+                let expire_before = unix_now().saturating_sub(state.archive_retention_secs);
+                state.archive.prune(expire_before)
+            })
+            .await;
+            match pruned {
+                Ok(Ok(0)) => {}
+                Ok(Ok(n)) => log::info!("Pruned {n} expired proofs from the archive"),
+                Ok(Err(e)) => log::warn!("Failed to prune proof archive: {e}"),
+                Err(e) => log::warn!("Failed to prune proof archive: {e}"),
+            }
+        }
     });
 
+    // Preprocessing takes a while; run it off the main thread so the server
+    // binds immediately and /livez, /readyz and /v1/status can answer meanwhile
+    let preprocess_state = state.clone();
+    std::thread::Builder::new()
+        .name("preprocess".to_string())
+        .spawn(move || {
+            log::info!("Preprocessing authorization model for verifier...");
+            let start = Instant::now();
+            let result = std::panic::catch_unwind(|| {
+                let model_fn = || model(&PathBuf::from(&model_path));
+                let preprocessing = JoltSNARK::<Fr, PCS, KeccakTranscript>::prover_preprocess(
+                    model_fn,
+                    JOLT_TABLE_SIZE,
+                );
+                let verifier: JoltVerifierPreprocessing<Fr, PCS> = (&preprocessing).into();
+                verifier
+            });
+            match result {
+                Ok(verifier) => {
+                    let prepared_in = start.elapsed();
+                    log::info!("Verifier preprocessing ready in {prepared_in:?}");
+                    let _ = preprocess_state.verifier.set(Verifier {
+                        snark: Arc::new(verifier),
+                        prepared_in,
+                    });
+                }
+                Err(_) => {
+                    // Never becoming ready is worse than a restart
+                    log::error!("Verifier preprocessing failed; exiting");
+                    std::process::exit(1);
+                }
+            }
+        })?;
+
+    let pending = state.jobs.pending();
+    if !pending.is_empty() {
+        log::info!("Resuming {} pending verification jobs", pending.len());
+    }
+    for id in pending {
+        actix_web::rt::spawn(handlers::jobs::run_job(state.clone(), id));
+    }
+
     let port: u16 = std::env::var("PORT")
         .ok()
         .and_then(|p| p.parse().ok())
@@ -336,28 +945,120 @@ async fn main() -> std::io::Result<()> {
         .error_handler(|err, _req| {
             actix_web::error::InternalError::from_response(
                 err,
-                HttpResponse::BadRequest().json(VerifyResponse {
-                    approved: false,
-                    signature: None,
-                    nonce: None,
-                    timestamp: None,
-                    reason: Some("JSON payload error".to_string()),
-                }),
+                HttpResponse::BadRequest().json(VerifyResponse::refused("JSON payload error")),
             )
             .into()
         });
 
     HttpServer::new(move || {
+        let rate_limit_metrics = Arc::clone(&metrics);
         App::new()
+            // Count rate-limited requests; the governor answers them itself
+            .wrap_fn(move |req, srv| {
+                let metrics = Arc::clone(&rate_limit_metrics);
+                let fut = srv.call(req);
+                async move {
+                    let res = fut.await?;
+                    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                        metrics.rate_limited.inc();
+                    }
+                    Ok(res)
+                }
+            })
             .app_data(state.clone())
             .app_data(json_config.clone())
-            .route("/health", web::get().to(health))
+            .route("/health", web::get().to(handlers::probes::health))
+            .route("/livez", web::get().to(handlers::probes::livez))
+            .route("/readyz", web::get().to(handlers::probes::readyz))
+            .route("/v1/status", web::get().to(handlers::probes::status))
+            .route(
+                "/metrics",
+                web::get().to(handlers::probes::metrics_endpoint),
+            )
+            .route(
+                "/v1/audit/decisions",
+                web::get().to(handlers::audit::list_audit),
+            )
+            .route(
+                "/v1/audit/export",
+                web::get().to(handlers::audit::export_audit),
+            )
+            .route(
+                "/v1/transparency/root",
+                web::get().to(handlers::transparency::transparency_root),
+            )
+            .route(
+                "/v1/transparency/roots/{tree_size}",
+                web::get().to(handlers::transparency::transparency_root_at),
+            )
+            .route(
+                "/v1/transparency/inclusion",
+                web::get().to(handlers::transparency::transparency_inclusion),
+            )
+            .route(
+                "/v1/transparency/consistency",
+                web::get().to(handlers::transparency::transparency_consistency),
+            )
+            .route(
+                "/v1/proofs/{hash}",
+                web::get().to(handlers::audit::get_proof),
+            )
+            .route("/v1/keys", web::get().to(handlers::admin::list_keys))
+            .route(
+                "/v1/admin/keys/promote",
+                web::post().to(handlers::admin::promote_key),
+            )
+            .route(
+                "/v1/two-party/keygen",
+                web::post()
+                    .to(handlers::two_party::open_two_party_keygen)
+                    .wrap(Governor::new(&governor_conf)),
+            )
+            .route(
+                "/v1/two-party/sessions",
+                web::post()
+                    .to(handlers::two_party::open_two_party_session)
+                    .wrap(Governor::new(&governor_conf)),
+            )
+            .route(
+                "/v1/two-party/sessions/{id}",
+                web::post().to(handlers::two_party::continue_two_party_session),
+            )
+            .route("/v1/cosign", web::post().to(handlers::cosign::cosign))
+            .route(
+                "/nonce/{chain_id}/{sender}",
+                web::get().to(handlers::verify::next_nonce),
+            )
             .route(
                 "/verify",
                 web::post()
-                    .to(verify_proof)
+                    .to(handlers::verify::verify_proof)
                     .wrap(Governor::new(&governor_conf)),
             )
+            .service(
+                // Each item carries a ~110KB proof, so size the limit per item
+                web::resource("/verify/batch")
+                    .app_data(
+                        json_config
+                            .clone()
+                            .limit(batch_max_items.max(1) * 512 * 1024),
+                    )
+                    .route(
+                        web::post()
+                            .to(handlers::batch::verify_batch)
+                            .wrap(Governor::new(&governor_conf)),
+                    ),
+            )
+            .route(
+                "/v1/verifications",
+                web::post()
+                    .to(handlers::jobs::create_verification)
+                    .wrap(Governor::new(&governor_conf)),
+            )
+            .route(
+                "/v1/verifications/{id}",
+                web::get().to(handlers::jobs::get_verification),
+            )
     })
     .bind((
         std::env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string()),
@@ -371,26 +1072,6 @@ async fn main() -> std::io::Result<()> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_keccak256_known_input() {
-        // keccak256("") = c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470
-        let hash = keccak256(b"");
-        assert_eq!(
-            hex::encode(hash),
-            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
-        );
-    }
-
-    #[test]
-    fn test_keccak256_hello() {
-        // keccak256("hello") known value
-        let hash = keccak256(b"hello");
-        assert_eq!(
-            hex::encode(hash),
-            "1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8"
-        );
-    }
-
     #[test]
     fn test_argmax_class_0_wins() {
         let output_data: Vec<i32> = vec![100, 50, 30];
@@ -421,42 +1102,29 @@ mod tests {
     }
 
     #[test]
-    fn test_nonce_state_persistence() {
-        let dir = std::env::temp_dir();
-        let path = dir.join("test_nonce_state.json");
-        let path_str = path.to_str().unwrap();
-
-        // Clean up
-        let _ = std::fs::remove_file(path_str);
-
-        let mut state = NonceState::load(path_str);
-        assert_eq!(state.counter, 0);
-
-        let n1 = state.next_nonce().unwrap();
-        assert_eq!(n1, 1);
-
-        let n2 = state.next_nonce().unwrap();
-        assert_eq!(n2, 2);
-
-        // Reload from file - counter should be preserved
-        let reloaded = NonceState::load(path_str);
-        assert_eq!(reloaded.counter, 2);
-
-        let _ = std::fs::remove_file(path_str);
+    fn test_verdict_outcome_labels() {
+        assert_eq!(
+            Verdict::approved("{}".to_string()).outcome(),
+            ("approved", "none")
+        );
+        assert_eq!(
+            Verdict::rejected(StatusCode::FORBIDDEN, "denied", "no").outcome(),
+            ("rejected", "denied")
+        );
+        assert_eq!(
+            Verdict::rejected(StatusCode::SERVICE_UNAVAILABLE, "queue_full", "busy").outcome(),
+            ("error", "queue_full")
+        );
     }
 
-    #[actix_web::test]
-    async fn test_health_endpoint() {
-        use actix_web::test;
-
-        let app = test::init_service(App::new().route("/health", web::get().to(health))).await;
-
-        let req = test::TestRequest::get().uri("/health").to_request();
-        let resp = test::call_service(&app, req).await;
-
-        assert_eq!(resp.status(), 200);
-
-        let body: serde_json::Value = test::read_body_json(resp).await;
-        assert_eq!(body, serde_json::json!({"status": "ok"}));
+    #[test]
+    fn test_rejected_verdict_carries_retry_after() {
+        let verdict = Verdict {
+            retry_after: Some(5),
+            ..Verdict::rejected(StatusCode::SERVICE_UNAVAILABLE, "queue_full", "busy")
+        };
+        let resp = verdict.into_response();
+        assert_eq!(resp.status(), 503);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "5");
     }
 }
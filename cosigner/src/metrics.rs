@@ -0,0 +1,157 @@
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+/// Proof timings span milliseconds (malformed bytes) to tens of seconds.
+const SECONDS_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Cosigner metrics, served in Prometheus text format on `/metrics`.
+/// Label values are fixed codes, never request data, to keep cardinality low.
+pub struct Metrics {
+    registry: Registry,
+    /// Verify outcomes by endpoint, outcome and rejection reason code.
+    pub requests: IntCounterVec,
+    pub deserialize_seconds: Histogram,
+    pub verify_seconds: Histogram,
+    pub nonces_issued: IntCounter,
+    /// Requests refused by the per-IP rate limiter (HTTP 429).
+    pub rate_limited: IntCounter,
+    /// SNARK checks queued or running on the verification pool.
+    pub in_flight: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "cosigner_verify_requests_total",
+                "Verification requests by endpoint, outcome and rejection reason",
+            ),
+            &["endpoint", "outcome", "reason"],
+        )
+        .expect("valid metric");
+        let deserialize_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "cosigner_proof_deserialize_seconds",
+                "Time to deserialize a SNARK proof",
+            )
+            .buckets(SECONDS_BUCKETS.to_vec()),
+        )
+        .expect("valid metric");
+        let verify_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "cosigner_proof_verify_seconds",
+                "Time to verify a deserialized SNARK proof",
+            )
+            .buckets(SECONDS_BUCKETS.to_vec()),
+        )
+        .expect("valid metric");
+        let nonces_issued = IntCounter::new(
+            "cosigner_nonces_issued_total",
+            "Nonces issued for signed approvals",
+        )
+        .expect("valid metric");
+        let rate_limited = IntCounter::new(
+            "cosigner_rate_limited_total",
+            "Requests rejected by the rate limiter",
+        )
+        .expect("valid metric");
+        let in_flight = IntGauge::new(
+            "cosigner_verifications_in_flight",
+            "SNARK verifications queued or running",
+        )
+        .expect("valid metric");
+
+        registry
+            .register(Box::new(requests.clone()))
+            .expect("metric registers once");
+        registry
+            .register(Box::new(deserialize_seconds.clone()))
+            .expect("metric registers once");
+        registry
+            .register(Box::new(verify_seconds.clone()))
+            .expect("metric registers once");
+        registry
+            .register(Box::new(nonces_issued.clone()))
+            .expect("metric registers once");
+        registry
+            .register(Box::new(rate_limited.clone()))
+            .expect("metric registers once");
+        registry
+            .register(Box::new(in_flight.clone()))
+            .expect("metric registers once");
+
+        Metrics {
+            registry,
+            requests,
+            deserialize_seconds,
+            verify_seconds,
+            nonces_issued,
+            rate_limited,
+            in_flight,
+        }
+    }
+
+    pub fn observe_request(&self, endpoint: &str, outcome: &str, reason: &str) {
+        self.requests
+            .with_label_values(&[endpoint, outcome, reason])
+            .inc();
+    }
+
+    /// Count a verification as in flight until the returned guard is dropped.
+    pub fn track_in_flight(&self) -> InFlight {
+        self.in_flight.inc();
+        InFlight(self.in_flight.clone())
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("text encoding cannot fail");
+        String::from_utf8(buf).expect("text encoding is UTF-8")
+    }
+}
+
+/// Decrements the in-flight gauge on drop.
+pub struct InFlight(IntGauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_metrics() {
+        let metrics = Metrics::new();
+        metrics.observe_request("verify", "rejected", "invalid_proof");
+        metrics.nonces_issued.inc_by(2);
+        metrics.verify_seconds.observe(0.3);
+
+        let text = metrics.render();
+        assert!(text.contains(
+            r#"cosigner_verify_requests_total{endpoint="verify",outcome="rejected",reason="invalid_proof"} 1"#
+        ));
+        assert!(text.contains("cosigner_nonces_issued_total 2"));
+        assert!(text.contains("cosigner_proof_verify_seconds_count 1"));
+    }
+
+    #[test]
+    fn test_in_flight_guard() {
+        let metrics = Metrics::new();
+        let a = metrics.track_in_flight();
+        let b = metrics.track_in_flight();
+        assert_eq!(metrics.in_flight.get(), 2);
+        drop(a);
+        drop(b);
+        assert_eq!(metrics.in_flight.get(), 0);
+    }
+}
@@ -0,0 +1,239 @@
+//! M-of-N approvals: a coordinator sends one verify request to N
+//! independently keyed cosigners under a nonce and timestamp it picks, and
+//! bundles their signatures over the same approval digest once M approve.
+//! A bundle is checked offline against the signer set with [`verify_bundle`].
+
+use crate::{
+    approval::{approval_digest, parse_address, signed_by, TxDetails},
+    credential::TrustCredential,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Body of a cosigner's `POST /v1/cosign`: a verify request whose nonce and
+/// timestamp were assigned by the coordinator.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CosignRequest {
+    pub proof: String,
+    pub program_io: String,
+    pub tx: TxDetails,
+    pub model_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust_credential: Option<TrustCredential>,
+    pub nonce: u64,
+    pub timestamp: u64,
+}
+
+/// One cosigner's approval in a bundle.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BundleSignature {
+    /// Address of the cosigner key that signed.
+    pub signer: String,
+    /// Key ring id the cosigner reported, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Hex `r || s` over `digest`, as returned by `/verify`.
+    pub signature: String,
+}
+
+/// Signatures from several cosigners over one approval.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApprovalBundle {
+    pub tx: TxDetails,
+    pub nonce: u64,
+    pub timestamp: u64,
+    /// Hex `approval_digest(tx, nonce, timestamp)`.
+    pub digest: String,
+    /// Approvals the coordinator required; informational, since verifiers
+    /// apply their own threshold.
+    pub threshold: usize,
+    pub signatures: Vec<BundleSignature>,
+}
+
+impl ApprovalBundle {
+    /// An empty bundle for `tx` under `nonce` and `timestamp`.
+    pub fn new(
+        tx: TxDetails,
+        nonce: u64,
+        timestamp: u64,
+        threshold: usize,
+    ) -> Result<Self, String> {
+        let digest = hex::encode(approval_digest(&tx, nonce, timestamp)?);
+        Ok(ApprovalBundle {
+            tx,
+            nonce,
+            timestamp,
+            digest,
+            threshold,
+            signatures: Vec::new(),
+        })
+    }
+}
+
+/// Check that `threshold` is a usable M for `signers` cosigners.
+pub fn check_threshold(threshold: usize, signers: usize) -> Result<(), String> {
+    if threshold == 0 || threshold > signers {
+        return Err(format!(
+            "Threshold must be between 1 and the number of signers ({signers}), got {threshold}"
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `bundle` carries valid signatures from at least `threshold`
+/// distinct members of `signers` (addresses). Signatures from keys outside
+/// the set are ignored.
+pub fn verify_bundle(
+    bundle: &ApprovalBundle,
+    signers: &[String],
+    threshold: usize,
+) -> Result<(), String> {
+    check_threshold(threshold, signers.len())?;
+    let digest = approval_digest(&bundle.tx, bundle.nonce, bundle.timestamp)?;
+    if !bundle.digest.eq_ignore_ascii_case(&hex::encode(digest)) {
+        return Err("Bundle digest does not match its tx, nonce and timestamp".to_string());
+    }
+    let allowed: HashSet<String> = signers.iter().map(|s| s.to_lowercase()).collect();
+    let mut approved = HashSet::new();
+    for sig in &bundle.signatures {
+        let signer = sig.signer.to_lowercase();
+        if allowed.contains(&signer)
+            && !approved.contains(&signer)
+            && signed_by(&digest, &sig.signature, &signer)
+        {
+            approved.insert(signer);
+        }
+    }
+    if approved.len() < threshold {
+        return Err(format!(
+            "Only {} of the required {threshold} signers approved",
+            approved.len()
+        ));
+    }
+    Ok(())
+}
+
+/// A cosigner the coordinator fans out to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CosignerSpec {
+    /// Address its approvals must be signed by (lowercase).
+    pub address: String,
+    /// Base URL, e.g. `http://cosigner-a:3001`.
+    pub url: String,
+}
+
+/// Parse a comma-separated list of `<0x address>@<base url>`.
+pub fn parse_cosigners(spec: &str) -> Result<Vec<CosignerSpec>, String> {
+    let mut cosigners: Vec<CosignerSpec> = Vec::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((address, url)) = item.split_once('@') else {
+            return Err(format!("Expected <address>@<url>, got {item}"));
+        };
+        let address = address.trim().to_lowercase();
+        parse_address(&address).map_err(|e| format!("Invalid cosigner address: {e}"))?;
+        if cosigners.iter().any(|c| c.address == address) {
+            return Err(format!("Cosigner {address} is listed twice"));
+        }
+        let url = url.trim().trim_end_matches('/');
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("Cosigner URL must be http(s), got {url}"));
+        }
+        cosigners.push(CosignerSpec {
+            address,
+            url: url.to_string(),
+        });
+    }
+    if cosigners.is_empty() {
+        return Err("No cosigners configured".to_string());
+    }
+    Ok(cosigners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::address;
+    use k256::ecdsa::{signature::Signer, SigningKey};
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32].into()).unwrap()
+    }
+
+    fn sign(key: &SigningKey, bundle: &ApprovalBundle) -> BundleSignature {
+        let (sig, _) = key.sign(&hex::decode(&bundle.digest).unwrap());
+        BundleSignature {
+            signer: address(key.verifying_key()),
+            key_id: None,
+            signature: hex::encode(sig.to_bytes()),
+        }
+    }
+
+    fn tx() -> TxDetails {
+        TxDetails {
+            from: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            to: "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC".to_string(),
+            amount: "1000000".to_string(),
+            token: "0x0000000000000000000000000000000000000001".to_string(),
+            chain_id: 9745,
+        }
+    }
+
+    #[test]
+    fn test_verify_bundle() {
+        let keys = [key(1), key(2), key(3)];
+        let signers: Vec<String> = keys.iter().map(|k| address(k.verifying_key())).collect();
+        let mut bundle = ApprovalBundle::new(tx(), 7, 1_700_000_000, 2).unwrap();
+
+        bundle.signatures.push(sign(&keys[0], &bundle));
+        // The same signer twice counts once
+        bundle.signatures.push(sign(&keys[0], &bundle));
+        // Keys outside the set are ignored
+        bundle.signatures.push(sign(&key(4), &bundle));
+        assert!(verify_bundle(&bundle, &signers, 2).is_err());
+
+        bundle.signatures.push(sign(&keys[2], &bundle));
+        assert!(verify_bundle(&bundle, &signers, 2).is_ok());
+        assert!(verify_bundle(&bundle, &signers, 3).is_err());
+        assert!(verify_bundle(&bundle, &signers, 4).is_err());
+
+        // Signatures don't carry over to a different nonce
+        let mut replayed = bundle.clone();
+        replayed.nonce = 8;
+        assert!(verify_bundle(&replayed, &signers, 2).is_err());
+        replayed.digest = ApprovalBundle::new(tx(), 8, 1_700_000_000, 2)
+            .unwrap()
+            .digest;
+        assert!(verify_bundle(&replayed, &signers, 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_cosigners() {
+        let a = "0x".to_string() + &"aa".repeat(20);
+        let b = "0x".to_string() + &"BB".repeat(20);
+        let parsed = parse_cosigners(&format!(
+            "{a}@http://cosigner-a:3001/, {b}@https://b.example"
+        ))
+        .unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                CosignerSpec {
+                    address: a.clone(),
+                    url: "http://cosigner-a:3001".to_string()
+                },
+                CosignerSpec {
+                    address: b.to_lowercase(),
+                    url: "https://b.example".to_string()
+                },
+            ]
+        );
+        assert!(parse_cosigners("").is_err());
+        assert!(parse_cosigners(&a).is_err());
+        assert!(parse_cosigners("0x1234@http://a").is_err());
+        assert!(parse_cosigners(&format!("{a}@ftp://a")).is_err());
+        assert!(parse_cosigners(&format!("{a}@http://a,{a}@http://b")).is_err());
+        assert!(check_threshold(2, 2).is_ok());
+        assert!(check_threshold(0, 2).is_err());
+        assert!(check_threshold(3, 2).is_err());
+    }
+}
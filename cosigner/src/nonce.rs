@@ -0,0 +1,868 @@
+use crate::{
+    idempotency::{IdempotencyRecord, Reservation},
+    persist::write_atomic,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use zkml_cosigner::approval::parse_address;
+
+/// A nonce domain: approvals are sequenced per (chain id, sender address),
+/// so a contract can track replay per account without shared state.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NonceDomain {
+    pub chain_id: u64,
+    /// Lowercase 0x-prefixed 20-byte hex address.
+    pub sender: String,
+}
+
+impl NonceDomain {
+    /// Build a domain from a chain id and a 0x-prefixed hex address.
+    /// The address is normalized to lowercase so checksummed and plain
+    /// spellings of the same account share one sequence.
+    pub fn new(chain_id: u64, sender: &str) -> Result<Self, String> {
+        let address = parse_address(sender).map_err(|e| format!("Invalid sender address: {e}"))?;
+        Ok(NonceDomain {
+            chain_id,
+            sender: format!("0x{}", hex::encode(address)),
+        })
+    }
+
+    /// Key used by the storage backends, e.g. `9745:0xabc...`.
+    fn key(&self) -> String {
+        format!("{}:{}", self.chain_id, self.sender)
+    }
+}
+
+/// Durable storage for per-domain nonce counters and the idempotency records
+/// of the approvals they were issued for.
+/// Any nonce <= a domain's counter is implicitly "used"; a counter must never
+/// move backwards, so backends report corrupt state as an error rather than
+/// starting over.
+pub trait NonceStore: Send {
+    /// Last nonce issued in `domain` (0 if none has been issued yet).
+    fn current(&self, domain: &NonceDomain) -> std::io::Result<u64>;
+
+    /// Number of domains that have been issued at least one nonce.
+    fn domain_count(&self) -> std::io::Result<usize>;
+
+    /// Total nonces issued across all domains.
+    fn total_issued(&self) -> std::io::Result<u64>;
+
+    /// Allocate and durably record the next nonce in `domain`.
+    fn next_nonce(&mut self, domain: &NonceDomain) -> std::io::Result<u64>;
+
+    /// Allocate the next nonce in `domain` and, in the same durable write,
+    /// record it under idempotency `key` as reserved at `timestamp`, pruning
+    /// records created before `expire_before`.
+    fn next_nonce_with_record(
+        &mut self,
+        domain: &NonceDomain,
+        key: &str,
+        request_digest: &str,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> std::io::Result<u64>;
+
+    /// Allocate one nonce per entry of `domains` (repeats get consecutive
+    /// nonces) in a single durable write: either all are recorded or none.
+    fn next_nonces(&mut self, domains: &[NonceDomain]) -> std::io::Result<Vec<u64>>;
+
+    /// Durably record `nonce` as issued in `domain` if it is above the
+    /// domain's counter, moving the counter up to it (skipped nonces are
+    /// never issued). Returns false, changing nothing, if it was already used.
+    fn claim_nonce(&mut self, domain: &NonceDomain, nonce: u64) -> std::io::Result<bool>;
+
+    /// Response previously recorded under idempotency `key`, if any.
+    fn idempotent_response(&self, key: &str) -> std::io::Result<Option<IdempotencyRecord>>;
+
+    /// Durably record `record` under `key`, pruning records created before
+    /// `expire_before` (unix seconds).
+    fn record_response(
+        &mut self,
+        key: &str,
+        record: &IdempotencyRecord,
+        expire_before: u64,
+    ) -> std::io::Result<()>;
+}
+
+/// Storage backend selected with `NONCE_STORE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceBackend {
+    File,
+    Sqlite,
+}
+
+impl NonceBackend {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "file" => Ok(NonceBackend::File),
+            "sqlite" => Ok(NonceBackend::Sqlite),
+            other => Err(format!(
+                "Unknown NONCE_STORE '{other}' (expected 'file' or 'sqlite')"
+            )),
+        }
+    }
+
+    pub fn default_path(self) -> &'static str {
+        match self {
+            NonceBackend::File => "./nonce_state.json",
+            NonceBackend::Sqlite => "./nonce_state.db",
+        }
+    }
+
+    pub fn open(self, path: &str) -> std::io::Result<Box<dyn NonceStore>> {
+        Ok(match self {
+            NonceBackend::File => Box::new(FileNonceStore::open(path)?),
+            NonceBackend::Sqlite => Box::new(SqliteNonceStore::open(path)?),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct NonceFile {
+    domains: BTreeMap<String, u64>,
+    /// Last nonce of the counter every sender shared before nonces were
+    /// scoped per domain. Every domain continues above it.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    legacy_counter: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Every layout the nonce state file has had, newest first.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredNonceFile {
+    Current(NonceFile),
+    /// Idempotency records kept alongside the counters.
+    WithIdempotency {
+        domains: BTreeMap<String, u64>,
+        idempotency: BTreeMap<String, IdempotencyRecord>,
+    },
+    /// One counter shared by every sender.
+    Global {
+        counter: u64,
+    },
+}
+
+type IdempotencyFile = BTreeMap<String, IdempotencyRecord>;
+
+/// Idempotency records live next to the state file, so issuing a nonce
+/// doesn't rewrite every stored response.
+fn idempotency_path(path: &Path) -> PathBuf {
+    path.with_extension("idempotency.json")
+}
+
+/// Parse `path`, or `None` if it does not exist yet.
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> std::io::Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| {
+            std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Corrupt nonce state in {}: {e}", path.display()),
+            )
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    let json = serde_json::to_vec(value).map_err(std::io::Error::other)?;
+    write_atomic(path, &json)
+}
+
+/// Nonce counters in one JSON file and idempotency records in another, each
+/// replaced atomically on every write. A nonce is always written before the
+/// record that reserves it, so a crash in between burns the nonce instead of
+/// handing it out twice.
+pub struct FileNonceStore {
+    state: NonceFile,
+    path: PathBuf,
+    idempotency: IdempotencyFile,
+    idempotency_path: PathBuf,
+}
+
+impl FileNonceStore {
+    /// Open the state files, starting empty only if they do not exist yet.
+    /// An unreadable or unparseable file is an error. Older layouts are
+    /// migrated in place.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let idempotency_path = idempotency_path(&path);
+        let mut idempotency = read_json(&idempotency_path)?.unwrap_or_default();
+        let state = match read_json(&path)? {
+            None => NonceFile::default(),
+            Some(StoredNonceFile::Current(state)) => state,
+            Some(StoredNonceFile::WithIdempotency {
+                domains,
+                idempotency: records,
+            }) => {
+                idempotency = records;
+                write_json(&idempotency_path, &idempotency)?;
+                let state = NonceFile {
+                    domains,
+                    legacy_counter: 0,
+                };
+                write_json(&path, &state)?;
+                state
+            }
+            Some(StoredNonceFile::Global { counter }) => {
+                let state = NonceFile {
+                    domains: BTreeMap::new(),
+                    legacy_counter: counter,
+                };
+                write_json(&path, &state)?;
+                state
+            }
+        };
+        Ok(FileNonceStore {
+            state,
+            path,
+            idempotency,
+            idempotency_path,
+        })
+    }
+
+    /// Write `next` to disk and only then adopt it in memory.
+    fn commit(&mut self, next: NonceFile) -> std::io::Result<()> {
+        write_json(&self.path, &next)?;
+        self.state = next;
+        Ok(())
+    }
+
+    /// Write `next` idempotency records and only then adopt them in memory.
+    fn commit_idempotency(&mut self, next: IdempotencyFile) -> std::io::Result<()> {
+        write_json(&self.idempotency_path, &next)?;
+        self.idempotency = next;
+        Ok(())
+    }
+
+    /// Add one to `domain`'s counter in `state`, returning the new nonce.
+    fn bump(state: &mut NonceFile, domain: &NonceDomain) -> u64 {
+        let counter = state
+            .domains
+            .entry(domain.key())
+            .or_insert(state.legacy_counter);
+        *counter += 1;
+        *counter
+    }
+}
+
+impl NonceStore for FileNonceStore {
+    fn current(&self, domain: &NonceDomain) -> std::io::Result<u64> {
+        Ok(self
+            .state
+            .domains
+            .get(&domain.key())
+            .copied()
+            .unwrap_or(self.state.legacy_counter))
+    }
+
+    fn domain_count(&self) -> std::io::Result<usize> {
+        Ok(self.state.domains.len())
+    }
+
+    fn total_issued(&self) -> std::io::Result<u64> {
+        let floor = self.state.legacy_counter;
+        Ok(floor + self.state.domains.values().map(|c| c - floor).sum::<u64>())
+    }
+
+    fn next_nonce(&mut self, domain: &NonceDomain) -> std::io::Result<u64> {
+        let mut next = self.state.clone();
+        let nonce = Self::bump(&mut next, domain);
+        self.commit(next)?;
+        Ok(nonce)
+    }
+
+    fn next_nonce_with_record(
+        &mut self,
+        domain: &NonceDomain,
+        key: &str,
+        request_digest: &str,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> std::io::Result<u64> {
+        let mut next = self.state.clone();
+        let nonce = Self::bump(&mut next, domain);
+        self.commit(next)?;
+        let mut records = self.idempotency.clone();
+        records.retain(|_, r| r.created_at >= expire_before);
+        records.insert(
+            key.to_string(),
+            IdempotencyRecord::reserved(request_digest, Reservation { nonce, timestamp }),
+        );
+        self.commit_idempotency(records)?;
+        Ok(nonce)
+    }
+
+    fn next_nonces(&mut self, domains: &[NonceDomain]) -> std::io::Result<Vec<u64>> {
+        let mut next = self.state.clone();
+        let nonces = domains
+            .iter()
+            .map(|domain| Self::bump(&mut next, domain))
+            .collect();
+        self.commit(next)?;
+        Ok(nonces)
+    }
+
+    fn claim_nonce(&mut self, domain: &NonceDomain, nonce: u64) -> std::io::Result<bool> {
+        if nonce <= self.current(domain)? {
+            return Ok(false);
+        }
+        let mut next = self.state.clone();
+        next.domains.insert(domain.key(), nonce);
+        self.commit(next)?;
+        Ok(true)
+    }
+
+    fn idempotent_response(&self, key: &str) -> std::io::Result<Option<IdempotencyRecord>> {
+        Ok(self.idempotency.get(key).cloned())
+    }
+
+    fn record_response(
+        &mut self,
+        key: &str,
+        record: &IdempotencyRecord,
+        expire_before: u64,
+    ) -> std::io::Result<()> {
+        let mut next = self.idempotency.clone();
+        next.retain(|_, r| r.created_at >= expire_before);
+        next.insert(key.to_string(), record.clone());
+        self.commit_idempotency(next)
+    }
+}
+
+/// Nonce state in an embedded SQLite database.
+pub struct SqliteNonceStore {
+    conn: Connection,
+}
+
+fn sql_err(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+impl SqliteNonceStore {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let conn = Connection::open(path).map_err(sql_err)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = FULL;
+             CREATE TABLE IF NOT EXISTS nonces (
+                 domain  TEXT PRIMARY KEY,
+                 counter INTEGER NOT NULL CHECK (counter >= 0)
+             );
+             CREATE TABLE IF NOT EXISTS idempotency (
+                 key            TEXT PRIMARY KEY,
+                 request_digest TEXT NOT NULL,
+                 status         INTEGER NOT NULL,
+                 body           TEXT NOT NULL,
+                 created_at     INTEGER NOT NULL
+             );",
+        )
+        .map_err(sql_err)?;
+        // Added after the first release; older databases lack them
+        let has_reserved: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('idempotency')
+                 WHERE name = 'reserved_nonce'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(sql_err)?
+            > 0;
+        if !has_reserved {
+            conn.execute_batch(
+                "ALTER TABLE idempotency ADD COLUMN reserved_nonce INTEGER;
+                 ALTER TABLE idempotency ADD COLUMN reserved_timestamp INTEGER;",
+            )
+            .map_err(sql_err)?;
+        }
+        let integrity: String = conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(sql_err)?;
+        if integrity != "ok" {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Corrupt nonce database: {integrity}"),
+            ));
+        }
+        Ok(SqliteNonceStore { conn })
+    }
+}
+
+impl NonceStore for SqliteNonceStore {
+    fn current(&self, domain: &NonceDomain) -> std::io::Result<u64> {
+        let counter: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT counter FROM nonces WHERE domain = ?1",
+                params![domain.key()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_err)?;
+        Ok(counter.unwrap_or(0) as u64)
+    }
+
+    fn domain_count(&self) -> std::io::Result<usize> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM nonces", [], |row| row.get(0))
+            .map_err(sql_err)?;
+        Ok(count as usize)
+    }
+
+    fn total_issued(&self) -> std::io::Result<u64> {
+        let total: i64 = self
+            .conn
+            .query_row("SELECT COALESCE(SUM(counter), 0) FROM nonces", [], |row| {
+                row.get(0)
+            })
+            .map_err(sql_err)?;
+        Ok(total as u64)
+    }
+
+    fn next_nonce(&mut self, domain: &NonceDomain) -> std::io::Result<u64> {
+        let counter: i64 = self
+            .conn
+            .query_row(
+                "INSERT INTO nonces (domain, counter) VALUES (?1, 1)
+                 ON CONFLICT (domain) DO UPDATE SET counter = counter + 1
+                 RETURNING counter",
+                params![domain.key()],
+                |row| row.get(0),
+            )
+            .map_err(sql_err)?;
+        Ok(counter as u64)
+    }
+
+    fn next_nonce_with_record(
+        &mut self,
+        domain: &NonceDomain,
+        key: &str,
+        request_digest: &str,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> std::io::Result<u64> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        let counter: i64 = tx
+            .query_row(
+                "INSERT INTO nonces (domain, counter) VALUES (?1, 1)
+                 ON CONFLICT (domain) DO UPDATE SET counter = counter + 1
+                 RETURNING counter",
+                params![domain.key()],
+                |row| row.get(0),
+            )
+            .map_err(sql_err)?;
+        insert_record(
+            &tx,
+            key,
+            &IdempotencyRecord::reserved(
+                request_digest,
+                Reservation {
+                    nonce: counter as u64,
+                    timestamp,
+                },
+            ),
+            expire_before,
+        )?;
+        tx.commit().map_err(sql_err)?;
+        Ok(counter as u64)
+    }
+
+    fn next_nonces(&mut self, domains: &[NonceDomain]) -> std::io::Result<Vec<u64>> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        let mut nonces = Vec::with_capacity(domains.len());
+        for domain in domains {
+            let counter: i64 = tx
+                .query_row(
+                    "INSERT INTO nonces (domain, counter) VALUES (?1, 1)
+                     ON CONFLICT (domain) DO UPDATE SET counter = counter + 1
+                     RETURNING counter",
+                    params![domain.key()],
+                    |row| row.get(0),
+                )
+                .map_err(sql_err)?;
+            nonces.push(counter as u64);
+        }
+        tx.commit().map_err(sql_err)?;
+        Ok(nonces)
+    }
+
+    fn claim_nonce(&mut self, domain: &NonceDomain, nonce: u64) -> std::io::Result<bool> {
+        let nonce = i64::try_from(nonce)
+            .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "nonce out of range"))?;
+        let changed = self
+            .conn
+            .execute(
+                "INSERT INTO nonces (domain, counter) VALUES (?1, ?2)
+                 ON CONFLICT (domain) DO UPDATE SET counter = excluded.counter
+                 WHERE counter < excluded.counter",
+                params![domain.key(), nonce],
+            )
+            .map_err(sql_err)?;
+        Ok(changed == 1)
+    }
+
+    fn idempotent_response(&self, key: &str) -> std::io::Result<Option<IdempotencyRecord>> {
+        self.conn
+            .query_row(
+                "SELECT request_digest, status, body, created_at,
+                        reserved_nonce, reserved_timestamp
+                 FROM idempotency WHERE key = ?1",
+                params![key],
+                |row| {
+                    let nonce: Option<i64> = row.get(4)?;
+                    let timestamp: Option<i64> = row.get(5)?;
+                    Ok(IdempotencyRecord {
+                        request_digest: row.get(0)?,
+                        status: row.get(1)?,
+                        body: row.get(2)?,
+                        created_at: row.get::<_, i64>(3)? as u64,
+                        reserved: nonce.zip(timestamp).map(|(nonce, timestamp)| Reservation {
+                            nonce: nonce as u64,
+                            timestamp: timestamp as u64,
+                        }),
+                    })
+                },
+            )
+            .optional()
+            .map_err(sql_err)
+    }
+
+    fn record_response(
+        &mut self,
+        key: &str,
+        record: &IdempotencyRecord,
+        expire_before: u64,
+    ) -> std::io::Result<()> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        insert_record(&tx, key, record, expire_before)?;
+        tx.commit().map_err(sql_err)
+    }
+}
+
+/// Prune expired idempotency records and write `record` under `key`.
+fn insert_record(
+    tx: &rusqlite::Transaction<'_>,
+    key: &str,
+    record: &IdempotencyRecord,
+    expire_before: u64,
+) -> std::io::Result<()> {
+    tx.execute(
+        "DELETE FROM idempotency WHERE created_at < ?1",
+        params![expire_before as i64],
+    )
+    .map_err(sql_err)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO idempotency
+             (key, request_digest, status, body, created_at, reserved_nonce, reserved_timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            key,
+            record.request_digest,
+            record.status,
+            record.body,
+            record.created_at as i64,
+            record.reserved.map(|r| r.nonce as i64),
+            record.reserved.map(|r| r.timestamp as i64),
+        ],
+    )
+    .map_err(sql_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+    const BOB: &str = "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc";
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(idempotency_path(&path));
+        path
+    }
+
+    #[test]
+    fn test_domain_normalizes_sender() {
+        let a = NonceDomain::new(9745, ALICE).unwrap();
+        let b = NonceDomain::new(9745, &ALICE.to_lowercase()).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.sender, "0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+    }
+
+    #[test]
+    fn test_domain_rejects_malformed_sender() {
+        assert!(NonceDomain::new(1, "70997970C51812dc3A010C7d01b50e0d17dc79C8").is_err());
+        assert!(NonceDomain::new(1, "0x1234").is_err());
+        assert!(NonceDomain::new(1, "0xzz997970C51812dc3A010C7d01b50e0d17dc79C8").is_err());
+        assert!(NonceDomain::new(1, "0X70997970C51812dc3A010C7d01b50e0d17dc79C8").is_err());
+    }
+
+    #[test]
+    fn test_nonce_state_persistence() {
+        let path = temp_path("test_nonce_state.json");
+
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+        let mut state = FileNonceStore::open(&path).unwrap();
+        assert_eq!(state.current(&alice).unwrap(), 0);
+
+        let n1 = state.next_nonce(&alice).unwrap();
+        assert_eq!(n1, 1);
+
+        let n2 = state.next_nonce(&alice).unwrap();
+        assert_eq!(n2, 2);
+
+        // Reload from file - counter should be preserved
+        let reloaded = FileNonceStore::open(&path).unwrap();
+        assert_eq!(reloaded.current(&alice).unwrap(), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn check_domains_are_independent(backend: NonceBackend, path: &Path) {
+        let alice_plasma = NonceDomain::new(9745, ALICE).unwrap();
+        let alice_sepolia = NonceDomain::new(11155111, ALICE).unwrap();
+        let bob_plasma = NonceDomain::new(9745, BOB).unwrap();
+
+        let mut state = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(state.next_nonce(&alice_plasma).unwrap(), 1);
+        assert_eq!(state.next_nonce(&alice_plasma).unwrap(), 2);
+        assert_eq!(state.next_nonce(&alice_sepolia).unwrap(), 1);
+        assert_eq!(state.next_nonce(&bob_plasma).unwrap(), 1);
+        assert_eq!(state.domain_count().unwrap(), 3);
+        assert_eq!(state.total_issued().unwrap(), 4);
+        drop(state);
+
+        let reloaded = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(reloaded.current(&alice_plasma).unwrap(), 2);
+        assert_eq!(reloaded.current(&alice_sepolia).unwrap(), 1);
+        assert_eq!(reloaded.current(&bob_plasma).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_file_store_domains_are_independent() {
+        let path = temp_path("test_nonce_state_domains.json");
+        check_domains_are_independent(NonceBackend::File, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_store_domains_are_independent() {
+        let path = temp_path("test_nonce_state_domains.db");
+        check_domains_are_independent(NonceBackend::Sqlite, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn check_batch_allocation(backend: NonceBackend, path: &Path) {
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+        let bob = NonceDomain::new(9745, BOB).unwrap();
+
+        let mut state = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(state.next_nonce(&alice).unwrap(), 1);
+        let nonces = state
+            .next_nonces(&[alice.clone(), bob.clone(), alice.clone()])
+            .unwrap();
+        assert_eq!(nonces, vec![2, 1, 3]);
+        assert!(state.next_nonces(&[]).unwrap().is_empty());
+        drop(state);
+
+        let reloaded = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(reloaded.current(&alice).unwrap(), 3);
+        assert_eq!(reloaded.current(&bob).unwrap(), 1);
+    }
+
+    fn check_claimed_nonces(backend: NonceBackend, path: &Path) {
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+        let bob = NonceDomain::new(9745, BOB).unwrap();
+
+        let mut state = backend.open(path.to_str().unwrap()).unwrap();
+        assert!(state.claim_nonce(&alice, 1).unwrap());
+        assert!(!state.claim_nonce(&alice, 1).unwrap());
+        // Skipping ahead burns the nonces in between
+        assert!(state.claim_nonce(&alice, 5).unwrap());
+        assert!(!state.claim_nonce(&alice, 3).unwrap());
+        assert_eq!(state.next_nonce(&alice).unwrap(), 6);
+        assert!(state.claim_nonce(&bob, 2).unwrap());
+        drop(state);
+
+        let reloaded = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(reloaded.current(&alice).unwrap(), 6);
+        assert_eq!(reloaded.current(&bob).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_file_store_claimed_nonces() {
+        let path = temp_path("test_nonce_state_claim.json");
+        check_claimed_nonces(NonceBackend::File, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_store_claimed_nonces() {
+        let path = temp_path("test_nonce_state_claim.db");
+        check_claimed_nonces(NonceBackend::Sqlite, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_file_store_batch_allocation() {
+        let path = temp_path("test_nonce_state_batch.json");
+        check_batch_allocation(NonceBackend::File, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_store_batch_allocation() {
+        let path = temp_path("test_nonce_state_batch.db");
+        check_batch_allocation(NonceBackend::Sqlite, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn check_idempotency_records_persist(backend: NonceBackend, path: &Path) {
+        let record = |created_at| IdempotencyRecord {
+            request_digest: "ab".repeat(32),
+            status: 200,
+            body: "{\"approved\":true,\"nonce\":1}".to_string(),
+            created_at,
+            reserved: None,
+        };
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+
+        let mut state = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(state.idempotent_response("retry-1").unwrap(), None);
+        state.record_response("old", &record(100), 0).unwrap();
+        state.record_response("retry-1", &record(500), 200).unwrap();
+        let nonce = state
+            .next_nonce_with_record(&alice, "retry-2", "cd", 600, 200)
+            .unwrap();
+        assert_eq!(nonce, 1);
+        drop(state);
+
+        let reloaded = backend.open(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            reloaded.idempotent_response("retry-1").unwrap(),
+            Some(record(500))
+        );
+        // The reservation was written with its nonce
+        assert_eq!(reloaded.current(&alice).unwrap(), 1);
+        assert_eq!(
+            reloaded.idempotent_response("retry-2").unwrap(),
+            Some(IdempotencyRecord::reserved(
+                "cd",
+                Reservation {
+                    nonce: 1,
+                    timestamp: 600
+                }
+            ))
+        );
+        // Pruned when retry-1 was recorded
+        assert_eq!(reloaded.idempotent_response("old").unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_store_idempotency_records_persist() {
+        let path = temp_path("test_nonce_state_idempotency.json");
+        check_idempotency_records_persist(NonceBackend::File, &path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(idempotency_path(&path));
+    }
+
+    #[test]
+    fn test_sqlite_store_idempotency_records_persist() {
+        let path = temp_path("test_nonce_state_idempotency.db");
+        check_idempotency_records_persist(NonceBackend::Sqlite, &path);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_file_store_rejects_corrupt_state() {
+        let path = temp_path("test_nonce_state_corrupt.json");
+        std::fs::write(&path, b"{\"domains\": {\"9745:0xab").unwrap();
+
+        let err = FileNonceStore::open(&path).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_file_store_migrates_legacy_global_counter() {
+        let path = temp_path("test_nonce_state_legacy.json");
+        std::fs::write(&path, b"{\"counter\": 42}").unwrap();
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+        let bob = NonceDomain::new(9745, BOB).unwrap();
+
+        let mut state = FileNonceStore::open(&path).unwrap();
+        assert_eq!(state.current(&alice).unwrap(), 42);
+        assert_eq!(state.next_nonce(&alice).unwrap(), 43);
+        assert!(!state.claim_nonce(&bob, 42).unwrap());
+        assert_eq!(state.total_issued().unwrap(), 43);
+        drop(state);
+
+        let mut reloaded = FileNonceStore::open(&path).unwrap();
+        assert_eq!(reloaded.next_nonce(&bob).unwrap(), 43);
+        assert_eq!(reloaded.next_nonce(&alice).unwrap(), 44);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_file_store_migrates_inline_idempotency_records() {
+        let path = temp_path("test_nonce_state_inline.json");
+        let alice = NonceDomain::new(9745, ALICE).unwrap();
+        let record = IdempotencyRecord::reserved(
+            "cd",
+            Reservation {
+                nonce: 3,
+                timestamp: 600,
+            },
+        );
+        let inline = serde_json::json!({
+            "domains": { alice.key(): 3 },
+            "idempotency": { "retry-1": record },
+        });
+        std::fs::write(&path, serde_json::to_vec(&inline).unwrap()).unwrap();
+
+        let mut state = FileNonceStore::open(&path).unwrap();
+        assert_eq!(state.current(&alice).unwrap(), 3);
+        assert_eq!(
+            state.idempotent_response("retry-1").unwrap(),
+            Some(record.clone())
+        );
+        // Issuing a nonce no longer rewrites the records
+        state.next_nonce(&alice).unwrap();
+        let on_disk: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert!(on_disk.get("idempotency").is_none());
+        drop(state);
+
+        let reloaded = FileNonceStore::open(&path).unwrap();
+        assert_eq!(
+            reloaded.idempotent_response("retry-1").unwrap(),
+            Some(record)
+        );
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(idempotency_path(&path));
+    }
+
+    #[test]
+    fn test_backend_parse() {
+        assert_eq!(NonceBackend::parse("file").unwrap(), NonceBackend::File);
+        assert_eq!(NonceBackend::parse("sqlite").unwrap(), NonceBackend::Sqlite);
+        assert!(NonceBackend::parse("redis").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+/// Replace `path` with `contents` so that a crash leaves either the old or the
+/// new file on disk, never a torn write: write a sibling temp file, fsync it,
+/// rename it over the target, then fsync the directory to persist the rename.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Not a file path: {}", path.display()),
+        )
+    })?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+    drop(f);
+    fs::rename(&tmp_path, path)?;
+
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let path = std::env::temp_dir().join("test_write_atomic.json");
+        let _ = fs::remove_file(&path);
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!path.with_file_name("test_write_atomic.json.tmp").exists());
+
+        let _ = fs::remove_file(&path);
+    }
+}
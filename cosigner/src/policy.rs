@@ -0,0 +1,560 @@
+//! Spending policy checked after a proof verifies and before its approval is
+//! signed. Loaded from a versioned JSON file (`POLICY_PATH`):
+//!
+//! ```json
+//! {
+//!   "version": "2026-06-01",
+//!   "deny_recipients": ["0x..."],
+//!   "allow_recipients": ["0x..."],
+//!   "tokens": {
+//!     "0x...": {
+//!       "max_transfer": "1000000000",
+//!       "sender_daily_cap": "5000000000",
+//!       "sender_weekly_cap": "20000000000",
+//!       "sender_rolling_caps": { "24h": "5000000000", "7d": "20000000000" },
+//!       "recipient_daily_caps": { "0x...": "100000000" }
+//!     }
+//!   },
+//!   "time_windows": [{ "days": ["mon", "tue", "wed", "thu", "fri"], "start": "08:00", "end": "20:00" }]
+//! }
+//! ```
+//!
+//! Every section is optional. Amounts are base-unit integers written as
+//! strings; caps apply per token. Days are UTC calendar days, weeks start on
+//! Monday, and time windows are in UTC. Rolling caps count the spend in the
+//! trailing window (`<n>m`, `<n>h` or `<n>d`) ending now. Every cap is
+//! measured against the durable [`SpendLedger`]. Each refusal names the rule
+//! that fired.
+
+use crate::ledger::SpendLedger;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use zkml_cosigner::approval::TxDetails;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+const WEEK_SECS: u64 = 7 * DAY_SECS;
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    version: String,
+    #[serde(default)]
+    deny_recipients: Vec<String>,
+    allow_recipients: Option<Vec<String>>,
+    #[serde(default)]
+    tokens: HashMap<String, TokenLimitsFile>,
+    #[serde(default)]
+    time_windows: Vec<TimeWindowFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenLimitsFile {
+    max_transfer: Option<String>,
+    sender_daily_cap: Option<String>,
+    sender_weekly_cap: Option<String>,
+    #[serde(default)]
+    sender_rolling_caps: HashMap<String, String>,
+    #[serde(default)]
+    recipient_daily_caps: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeWindowFile {
+    #[serde(default)]
+    days: Vec<String>,
+    start: String,
+    end: String,
+}
+
+struct TokenLimits {
+    max_transfer: Option<u128>,
+    sender_daily_cap: Option<u128>,
+    sender_weekly_cap: Option<u128>,
+    /// `(window, label, cap)`, shortest window first.
+    sender_rolling_caps: Vec<(u64, String, u128)>,
+    recipient_daily_caps: HashMap<String, u128>,
+}
+
+/// Minutes since UTC midnight in `[start, end)` on the given weekdays; a
+/// window with `end < start` runs past midnight.
+struct TimeWindow {
+    /// Bit 0 is Monday; 0 means every day.
+    days: u8,
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    fn contains(&self, now: u64) -> bool {
+        let weekday = weekday(now);
+        let minute = ((now % DAY_SECS) / 60) as u32;
+        let on = |day: u32| self.days == 0 || self.days & (1 << day) != 0;
+        if self.start <= self.end {
+            on(weekday) && (self.start..self.end).contains(&minute)
+        } else if minute >= self.start {
+            on(weekday)
+        } else {
+            // Early-morning part of a window that opened the day before
+            on((weekday + 6) % 7) && minute < self.end
+        }
+    }
+}
+
+/// Day of the week of a unix time, 0 for Monday (1970-01-01 was a Thursday).
+pub fn weekday(time: u64) -> u32 {
+    ((time / DAY_SECS + 3) % 7) as u32
+}
+
+pub use zkml_cosigner::approval::parse_amount;
+
+/// Parse a rolling window length such as `30m`, `24h` or `7d` into seconds.
+pub fn parse_window(s: &str) -> Option<u64> {
+    let unit = match s.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => DAY_SECS,
+        _ => return None,
+    };
+    let n: u64 = s[..s.len() - 1].parse().ok()?;
+    n.checked_mul(unit).filter(|&secs| secs > 0)
+}
+
+fn parse_minutes(s: &str) -> Option<u32> {
+    let (h, m) = s.split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+/// A loaded policy file.
+pub struct Policy {
+    version: String,
+    deny_recipients: HashSet<String>,
+    allow_recipients: Option<HashSet<String>>,
+    tokens: HashMap<String, TokenLimits>,
+    time_windows: Vec<TimeWindow>,
+}
+
+impl Policy {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let file: PolicyFile = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        if file.version.trim().is_empty() {
+            return Err("version must not be empty".to_string());
+        }
+        let lower = |list: Vec<String>| list.into_iter().map(|a| a.to_lowercase()).collect();
+        let amount = |field: &str, v: Option<String>| match v {
+            Some(v) => parse_amount(&v)
+                .map(Some)
+                .ok_or_else(|| format!("{field} must be a base-unit integer, got {v:?}")),
+            None => Ok(None),
+        };
+
+        let mut tokens = HashMap::new();
+        for (token, limits) in file.tokens {
+            let mut recipient_daily_caps = HashMap::new();
+            for (recipient, cap) in limits.recipient_daily_caps {
+                let cap = amount("recipient_daily_caps", Some(cap))?.expect("cap is set");
+                recipient_daily_caps.insert(recipient.to_lowercase(), cap);
+            }
+            let mut sender_rolling_caps = Vec::new();
+            for (window, cap) in limits.sender_rolling_caps {
+                let secs = parse_window(&window).ok_or_else(|| {
+                    format!("Rolling window must be <n>m, <n>h or <n>d, got {window:?}")
+                })?;
+                let cap = amount("sender_rolling_caps", Some(cap))?.expect("cap is set");
+                sender_rolling_caps.push((secs, window, cap));
+            }
+            sender_rolling_caps.sort();
+            tokens.insert(
+                token.to_lowercase(),
+                TokenLimits {
+                    max_transfer: amount("max_transfer", limits.max_transfer)?,
+                    sender_daily_cap: amount("sender_daily_cap", limits.sender_daily_cap)?,
+                    sender_weekly_cap: amount("sender_weekly_cap", limits.sender_weekly_cap)?,
+                    sender_rolling_caps,
+                    recipient_daily_caps,
+                },
+            );
+        }
+
+        let mut time_windows = Vec::new();
+        for window in file.time_windows {
+            let mut days = 0u8;
+            for day in &window.days {
+                let idx = DAY_NAMES
+                    .iter()
+                    .position(|d| d.eq_ignore_ascii_case(day))
+                    .ok_or_else(|| format!("Unknown day {day:?}; use mon..sun"))?;
+                days |= 1 << idx;
+            }
+            let minutes =
+                |s: &str| parse_minutes(s).ok_or_else(|| format!("Time must be HH:MM, got {s:?}"));
+            time_windows.push(TimeWindow {
+                days,
+                start: minutes(&window.start)?,
+                end: minutes(&window.end)?,
+            });
+        }
+
+        Ok(Policy {
+            version: file.version,
+            deny_recipients: lower(file.deny_recipients),
+            allow_recipients: file.allow_recipients.map(lower),
+            tokens,
+            time_windows,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&json).map_err(|e| format!("Invalid policy {}: {e}", path.display()))
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// How far back the caps look: a calendar week, or the longest rolling
+    /// window if that is longer.
+    pub fn lookback_secs(&self) -> u64 {
+        self.tokens
+            .values()
+            .flat_map(|l| l.sender_rolling_caps.iter().map(|&(window, _, _)| window))
+            .fold(WEEK_SECS, u64::max)
+    }
+
+    fn violation(&self, rule: &'static str, detail: String) -> Violation {
+        Violation {
+            version: self.version.clone(),
+            rule,
+            detail,
+        }
+    }
+
+    fn check_cap(
+        &self,
+        rule: &'static str,
+        cap: Option<u128>,
+        spent: u128,
+        amount: u128,
+        period: &str,
+    ) -> Result<(), Violation> {
+        match cap {
+            Some(cap) if spent.saturating_add(amount) > cap => Err(self.violation(
+                rule,
+                format!(
+                    "{amount} on top of {spent} already approved {period} exceeds the cap of {cap}"
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check `tx` at time `now` against every rule, measuring the caps
+    /// against the approvals in `ledger`; returns the parsed amount to record
+    /// once the approval is issued.
+    pub fn check(&self, tx: &TxDetails, ledger: &SpendLedger, now: u64) -> Result<u128, Violation> {
+        let sender = tx.from.to_lowercase();
+        let recipient = tx.to.to_lowercase();
+        let token = tx.token.to_lowercase();
+
+        if self.deny_recipients.contains(&recipient) {
+            return Err(self.violation("deny_recipients", format!("Recipient {} is denied", tx.to)));
+        }
+        if let Some(allowed) = &self.allow_recipients {
+            if !allowed.contains(&recipient) {
+                return Err(self.violation(
+                    "allow_recipients",
+                    format!("Recipient {} is not on the allow list", tx.to),
+                ));
+            }
+        }
+        if !self.time_windows.is_empty() && !self.time_windows.iter().any(|w| w.contains(now)) {
+            return Err(self.violation(
+                "time_windows",
+                "Transfers are not allowed at this time of day".to_string(),
+            ));
+        }
+        let amount = parse_amount(&tx.amount).ok_or_else(|| {
+            self.violation(
+                "invalid_amount",
+                format!("Amount {:?} is not a base-unit integer", tx.amount),
+            )
+        })?;
+
+        let Some(limits) = self.tokens.get(&token) else {
+            return Ok(amount);
+        };
+        if let Some(max) = limits.max_transfer {
+            if amount > max {
+                return Err(self.violation(
+                    "max_transfer",
+                    format!("Amount {amount} exceeds the per-transfer cap of {max}"),
+                ));
+            }
+        }
+        let day_start = now - now % DAY_SECS;
+        let week_start = day_start - u64::from(weekday(now)) * DAY_SECS;
+        self.check_cap(
+            "sender_daily_cap",
+            limits.sender_daily_cap,
+            ledger.spent(&sender, &token, None, day_start),
+            amount,
+            "today",
+        )?;
+        self.check_cap(
+            "sender_weekly_cap",
+            limits.sender_weekly_cap,
+            ledger.spent(&sender, &token, None, week_start),
+            amount,
+            "this week",
+        )?;
+        for (window, label, cap) in &limits.sender_rolling_caps {
+            self.check_cap(
+                "sender_rolling_cap",
+                Some(*cap),
+                ledger.spent(&sender, &token, None, now.saturating_sub(*window)),
+                amount,
+                &format!("in the last {label}"),
+            )?;
+        }
+        self.check_cap(
+            "recipient_daily_cap",
+            limits.recipient_daily_caps.get(&recipient).copied(),
+            ledger.spent(&sender, &token, Some(&recipient), day_start),
+            amount,
+            &format!("to {} today", tx.to),
+        )?;
+        Ok(amount)
+    }
+}
+
+/// The policy rule that refused a transfer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub version: String,
+    /// `deny_recipients`, `allow_recipients`, `time_windows`, `invalid_amount`,
+    /// `max_transfer`, `sender_daily_cap`, `sender_weekly_cap`,
+    /// `sender_rolling_cap` or `recipient_daily_cap`.
+    pub rule: &'static str,
+    pub detail: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::SpendEntry;
+
+    const TOKEN: &str = "0x0000000000000000000000000000000000000001";
+    const ALICE: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+    const SHOP: &str = "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC";
+    const MALLORY: &str = "0x90F79bf6EB2c4f870365E785982E1f101E93b906";
+    /// Wednesday 2024-05-01 12:00 UTC.
+    const WED_NOON: u64 = 1_714_564_800;
+
+    fn tx(to: &str, amount: &str) -> TxDetails {
+        TxDetails {
+            from: ALICE.to_string(),
+            to: to.to_string(),
+            amount: amount.to_string(),
+            token: TOKEN.to_string(),
+            chain_id: 9745,
+        }
+    }
+
+    /// A policy with an empty ledger.
+    struct Engine {
+        policy: Policy,
+        ledger: SpendLedger,
+    }
+
+    impl Engine {
+        fn check(&self, tx: &TxDetails, now: u64) -> Result<u128, Violation> {
+            self.policy.check(tx, &self.ledger, now)
+        }
+
+        fn record(&mut self, to: &str, amount: u128, time: u64) {
+            let tx = tx(to, &amount.to_string());
+            self.ledger
+                .append(&[SpendEntry::new(&tx, amount, time)])
+                .unwrap();
+        }
+    }
+
+    fn engine(name: &str, json: &str) -> Engine {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let policy = Policy::parse(json.as_bytes()).unwrap();
+        let ledger = SpendLedger::open(&path, policy.lookback_secs(), 0).unwrap();
+        Engine { policy, ledger }
+    }
+
+    fn rule(engine: &Engine, tx: &TxDetails, now: u64) -> Option<&'static str> {
+        engine.check(tx, now).err().map(|v| v.rule)
+    }
+
+    #[test]
+    fn test_recipient_lists_and_amounts() {
+        let engine = engine(
+            "test_policy_recipients.jsonl",
+            &format!(
+                r#"{{"version": "v1", "deny_recipients": ["{}"], "allow_recipients": ["{SHOP}"],
+                "tokens": {{"{TOKEN}": {{"max_transfer": "100"}}}}}}"#,
+                MALLORY.to_lowercase()
+            ),
+        );
+        assert_eq!(engine.check(&tx(SHOP, "100"), WED_NOON), Ok(100));
+        assert_eq!(
+            rule(&engine, &tx(MALLORY, "1"), WED_NOON),
+            Some("deny_recipients")
+        );
+        assert_eq!(
+            rule(&engine, &tx(ALICE, "1"), WED_NOON),
+            Some("allow_recipients")
+        );
+        assert_eq!(
+            rule(&engine, &tx(SHOP, "101"), WED_NOON),
+            Some("max_transfer")
+        );
+        assert_eq!(
+            rule(&engine, &tx(SHOP, "1.5"), WED_NOON),
+            Some("invalid_amount")
+        );
+        assert_eq!(
+            engine
+                .check(&tx(SHOP, "101"), WED_NOON)
+                .unwrap_err()
+                .version,
+            "v1"
+        );
+    }
+
+    #[test]
+    fn test_daily_weekly_and_recipient_caps() {
+        let mut engine = engine(
+            "test_policy_caps.jsonl",
+            &format!(
+                r#"{{"version": "v1", "tokens": {{"{TOKEN}": {{
+                "sender_daily_cap": "100", "sender_weekly_cap": "250",
+                "recipient_daily_caps": {{"{SHOP}": "60"}}}}}}}}"#
+            ),
+        );
+        engine.record(SHOP, 50, WED_NOON - 60);
+        assert_eq!(
+            rule(&engine, &tx(SHOP, "11"), WED_NOON),
+            Some("recipient_daily_cap")
+        );
+        assert_eq!(engine.check(&tx(MALLORY, "50"), WED_NOON), Ok(50));
+        assert_eq!(
+            rule(&engine, &tx(MALLORY, "51"), WED_NOON),
+            Some("sender_daily_cap")
+        );
+
+        // Monday's spend still counts on Wednesday, but not the next Monday
+        engine.record(MALLORY, 190, WED_NOON - 2 * DAY_SECS);
+        assert_eq!(
+            rule(&engine, &tx(MALLORY, "11"), WED_NOON),
+            Some("sender_weekly_cap")
+        );
+        assert_eq!(
+            engine.check(&tx(MALLORY, "100"), WED_NOON + 5 * DAY_SECS),
+            Ok(100)
+        );
+    }
+
+    #[test]
+    fn test_rolling_caps() {
+        let mut engine = engine(
+            "test_policy_rolling.jsonl",
+            &format!(
+                r#"{{"version": "v1", "tokens": {{"{TOKEN}": {{
+                "sender_rolling_caps": {{"24h": "100", "10d": "300"}}}}}}}}"#
+            ),
+        );
+        assert_eq!(engine.policy.lookback_secs(), 10 * DAY_SECS);
+
+        // Many small approvals add up: yesterday evening counts until this evening
+        for i in 0..9 {
+            engine.record(SHOP, 10, WED_NOON - 20 * 3600 + i * 60);
+        }
+        assert_eq!(engine.check(&tx(SHOP, "10"), WED_NOON), Ok(10));
+        engine.record(SHOP, 10, WED_NOON);
+        let refused = engine.check(&tx(SHOP, "1"), WED_NOON).unwrap_err();
+        assert_eq!(refused.rule, "sender_rolling_cap");
+        assert!(refused.detail.contains("last 24h"), "{}", refused.detail);
+        assert_eq!(
+            engine.check(&tx(SHOP, "90"), WED_NOON + 4 * 3600 + 600),
+            Ok(90)
+        );
+
+        // The longer window still sees them
+        engine.record(SHOP, 100, WED_NOON + DAY_SECS);
+        engine.record(SHOP, 95, WED_NOON + 2 * DAY_SECS);
+        let later = WED_NOON + 3 * DAY_SECS + 13 * 3600;
+        let refused = engine.check(&tx(SHOP, "10"), later).unwrap_err();
+        assert!(refused.detail.contains("last 10d"), "{}", refused.detail);
+        assert_eq!(engine.check(&tx(SHOP, "5"), later), Ok(5));
+
+        // Other senders have their own totals
+        let mut bob = tx(SHOP, "100");
+        bob.from = MALLORY.to_string();
+        assert_eq!(engine.check(&bob, WED_NOON), Ok(100));
+    }
+
+    #[test]
+    fn test_time_windows() {
+        let engine = engine(
+            "test_policy_windows.jsonl",
+            r#"{"version": "v1", "time_windows": [
+                {"days": ["mon", "tue", "wed", "thu", "fri"], "start": "08:00", "end": "20:00"},
+                {"days": ["sat"], "start": "22:00", "end": "02:00"}]}"#,
+        );
+        assert!(engine.check(&tx(SHOP, "1"), WED_NOON).is_ok());
+        assert_eq!(
+            rule(&engine, &tx(SHOP, "1"), WED_NOON + 9 * 3600),
+            Some("time_windows")
+        );
+        // The Saturday window runs into Sunday morning
+        let sat_midnight = WED_NOON + 3 * DAY_SECS + 12 * 3600;
+        assert!(engine
+            .check(&tx(SHOP, "1"), sat_midnight - 3 * 3600)
+            .is_err());
+        assert!(engine.check(&tx(SHOP, "1"), sat_midnight - 3600).is_ok());
+        assert!(engine.check(&tx(SHOP, "1"), sat_midnight + 3600).is_ok());
+        assert!(engine
+            .check(&tx(SHOP, "1"), sat_midnight + 3 * 3600)
+            .is_err());
+        assert!(engine
+            .check(&tx(SHOP, "1"), sat_midnight - DAY_SECS - 3600)
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_policy_files() {
+        assert!(Policy::parse(br#"{"version": ""}"#).is_err());
+        assert!(Policy::parse(br#"{"version": "v1", "caps": {}}"#).is_err());
+        assert!(
+            Policy::parse(br#"{"version": "v1", "tokens": {"0x1": {"max_transfer": "1e6"}}}"#)
+                .is_err()
+        );
+        assert!(Policy::parse(
+            br#"{"version": "v1", "time_windows": [{"start": "25:00", "end": "01:00"}]}"#
+        )
+        .is_err());
+        assert!(Policy::parse(
+            br#"{"version": "v1", "time_windows": [{"days": ["funday"], "start": "01:00", "end": "02:00"}]}"#
+        )
+        .is_err());
+        for window in ["24", "0h", "1w", "h", "-1d"] {
+            let json = format!(
+                r#"{{"version": "v1", "tokens": {{"0x1": {{"sender_rolling_caps": {{"{window}": "1"}}}}}}}}"#
+            );
+            assert!(Policy::parse(json.as_bytes()).is_err(), "{window}");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Config files reloaded when they change on disk, so lists can be updated
+//! without a restart. A file that fails to load leaves the last good version
+//! in place.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+type Loader<T> = Box<dyn Fn(&Path) -> Result<T, String> + Send + Sync>;
+
+pub struct Reloadable<T> {
+    path: PathBuf,
+    load: Loader<T>,
+    current: RwLock<Arc<T>>,
+    /// Modification time of the last version seen, whether or not it loaded.
+    seen: Mutex<Option<SystemTime>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl<T> Reloadable<T> {
+    /// Load `path` with `load`. Unlike a reload, failing here is an error.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        load: impl Fn(&Path) -> Result<T, String> + Send + Sync + 'static,
+    ) -> Result<Self, String> {
+        let path = path.into();
+        // Read the time first, so a change made while loading is seen later
+        let seen = modified(&path);
+        let current = load(&path)?;
+        Ok(Reloadable {
+            path,
+            load: Box::new(load),
+            current: RwLock::new(Arc::new(current)),
+            seen: Mutex::new(seen),
+        })
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Reload the file if it changed since it was last seen. Returns the new
+    /// version, or `None` when the file is unchanged.
+    pub fn refresh(&self) -> Result<Option<Arc<T>>, String> {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let now = modified(&self.path);
+        if now == *seen {
+            return Ok(None);
+        }
+        // Don't retry a bad version until it changes again
+        *seen = now;
+        let loaded = Arc::new((self.load)(&self.path)?);
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::clone(&loaded);
+        Ok(Some(loaded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn write(path: &Path, contents: &str, mtime: SystemTime) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_refresh_keeps_last_good_version() {
+        let path = std::env::temp_dir().join("test_reloadable.txt");
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let parse = |p: &Path| {
+            let s = std::fs::read_to_string(p).map_err(|e| e.to_string())?;
+            s.trim().parse::<u32>().map_err(|e| e.to_string())
+        };
+
+        write(&path, "1", t0);
+        let value = Reloadable::open(&path, parse).unwrap();
+        assert_eq!(*value.current(), 1);
+        assert_eq!(value.refresh(), Ok(None));
+
+        write(&path, "2", t0 + Duration::from_secs(1));
+        assert_eq!(value.refresh(), Ok(Some(Arc::new(2))));
+        assert_eq!(*value.current(), 2);
+
+        write(&path, "oops", t0 + Duration::from_secs(2));
+        assert!(value.refresh().is_err());
+        assert_eq!(*value.current(), 2);
+        assert_eq!(value.refresh(), Ok(None));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(value.refresh().is_err());
+        assert_eq!(*value.current(), 2);
+    }
+}
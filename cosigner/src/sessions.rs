@@ -0,0 +1,203 @@
+//! Two-party ECDSA state: the cosigner's key shares, and the protocol runs in
+//! progress between API calls.
+
+use rand_core::{OsRng, RngCore};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+use zkml_cosigner::{
+    approval::parse_address,
+    keys::KeyError,
+    two_party::{self, KeyShare, Party, RecoverableSignature},
+};
+
+/// Default cap on sessions in progress at once.
+pub const DEFAULT_MAX_SESSIONS: usize = 64;
+
+/// Default time a session may sit idle between messages.
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 120;
+
+/// The cosigner's side of one protocol run.
+pub enum Protocol {
+    /// Generating a key for the wallet `owner`.
+    Keygen {
+        owner: String,
+        party: Party<KeyShare>,
+    },
+    Signing(Party<RecoverableSignature>),
+}
+
+pub struct Session {
+    pub protocol: Protocol,
+    /// Bearer token for the client's exchanges, handed out when the session
+    /// opens.
+    token: String,
+    idle_since: Instant,
+}
+
+/// A newly opened session: its id, and the token the client must present
+/// to exchange messages in it.
+pub struct Ticket {
+    pub id: String,
+    pub token: String,
+}
+
+/// Sessions by id. A session is taken out while a batch is exchanged, so a
+/// second exchange on it at the same time finds nothing.
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+    max: usize,
+    ttl: Duration,
+}
+
+impl Sessions {
+    pub fn new(max: usize, ttl: Duration) -> Self {
+        Sessions {
+            sessions: Mutex::new(HashMap::new()),
+            max,
+            ttl,
+        }
+    }
+
+    /// Start a session under a new id and token, or return `None` if `max`
+    /// are in progress. Expired sessions are dropped first, which stops their
+    /// threads.
+    pub fn open(&self, start: impl FnOnce(&str) -> Protocol) -> Option<Ticket> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|_, s| s.idle_since.elapsed() < self.ttl);
+        if sessions.len() >= self.max {
+            return None;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut token = [0u8; 32];
+        OsRng.fill_bytes(&mut token);
+        let token = hex::encode(token);
+        let session = Session {
+            protocol: start(&id),
+            token: token.clone(),
+            idle_since: Instant::now(),
+        };
+        sessions.insert(id.clone(), session);
+        Some(Ticket { id, token })
+    }
+
+    /// Whether `token` is the token of the unexpired session `id`, compared
+    /// in constant time. A session taken out for an exchange has no token.
+    pub fn authorized(&self, id: &str, token: &str) -> bool {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.get(id).is_some_and(|s| {
+            s.idle_since.elapsed() < self.ttl
+                && bool::from(s.token.as_bytes().ct_eq(token.as_bytes()))
+        })
+    }
+
+    /// Take out the unexpired session `id`.
+    pub fn take(&self, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions
+            .remove(id)
+            .filter(|s| s.idle_since.elapsed() < self.ttl)
+    }
+
+    /// Return a session that is waiting for the client's next batch.
+    pub fn put_back(&self, id: String, session: Session) {
+        let session = Session {
+            idle_since: Instant::now(),
+            ..session
+        };
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.insert(id, session);
+    }
+}
+
+/// Two-party ECDSA, enabled by `TWO_PARTY_KEY_DIR`.
+pub struct TwoParty {
+    /// Where the cosigner's key shares are kept, one `<address>.json` per
+    /// shared address, encrypted like a keystore.
+    pub dir: PathBuf,
+    passphrase: Zeroizing<String>,
+    shares: RwLock<HashMap<[u8; 20], Arc<KeyShare>>>,
+    pub sessions: Sessions,
+}
+
+impl TwoParty {
+    /// Load every key share in `dir`, creating it if missing.
+    pub fn open(
+        dir: PathBuf,
+        passphrase: Zeroizing<String>,
+        sessions: Sessions,
+    ) -> Result<Self, KeyError> {
+        let unreadable = |e: io::Error| KeyError::Keystore(format!("{}: {e}", dir.display()));
+        fs::create_dir_all(&dir).map_err(unreadable)?;
+        let mut shares = HashMap::new();
+        for entry in fs::read_dir(&dir).map_err(unreadable)? {
+            let path = entry.map_err(unreadable)?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let share = two_party::load_share(&path, &passphrase)?;
+            let address = two_party::shared_address(&share);
+            if path != share_path(&dir, &address) {
+                return Err(KeyError::InvalidKey(format!(
+                    "{}: share is for {address}",
+                    path.display()
+                )));
+            }
+            shares.insert(
+                parse_address(&address).expect("a shared address"),
+                Arc::new(share),
+            );
+        }
+        Ok(TwoParty {
+            dir,
+            passphrase,
+            shares: RwLock::new(shares),
+            sessions,
+        })
+    }
+
+    /// Shared addresses with a key share, in no particular order.
+    pub fn addresses(&self) -> Vec<String> {
+        let shares = self.shares.read().unwrap_or_else(|e| e.into_inner());
+        shares
+            .keys()
+            .map(|a| format!("0x{}", hex::encode(a)))
+            .collect()
+    }
+
+    /// The cosigner's share of the key with address `address`.
+    pub fn share(&self, address: &str) -> Option<Arc<KeyShare>> {
+        let address = parse_address(address).ok()?;
+        let shares = self.shares.read().unwrap_or_else(|e| e.into_inner());
+        shares.get(&address).cloned()
+    }
+
+    /// Save a newly generated share and sign with it from now on. Refuses to
+    /// replace an existing share, whose key may hold funds.
+    pub fn install(&self, share: KeyShare) -> io::Result<String> {
+        let address = two_party::shared_address(&share);
+        let path = share_path(&self.dir, &address);
+        let mut shares = self.shares.write().unwrap_or_else(|e| e.into_inner());
+        let key = parse_address(&address).expect("a shared address");
+        if shares.contains_key(&key) || path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A key share for {address} already exists"),
+            ));
+        }
+        two_party::save_share(&path, &share, &self.passphrase)?;
+        shares.insert(key, Arc::new(share));
+        Ok(address)
+    }
+}
+
+/// Where the share of the key with (lowercase) address `address` is kept.
+pub fn share_path(dir: &Path, address: &str) -> PathBuf {
+    dir.join(format!("{address}.json"))
+}
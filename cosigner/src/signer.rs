@@ -0,0 +1,582 @@
+//! Signing backends. The cosigner only needs an address and a way to sign a
+//! message; the key itself can live in memory, in an encrypted keystore file,
+//! or in a separate signer process reached over HTTP.
+//!
+//! # Remote signer protocol
+//!
+//! HTTP/1.1 with JSON bodies, one request per connection, over TCP
+//! (`http://host:port`) or a Unix socket (`unix:/path/to/socket`):
+//!
+//! - `GET /v1/address` returns `{"address": "0x..."}`, the signer's Ethereum
+//!   address (lowercase hex).
+//! - `POST /v1/sign` with `{"address": "0x...", "message": "<hex>"}` returns
+//!   `{"signature": "<hex r || s>"}`: a 64-byte secp256k1 ECDSA signature over
+//!   SHA-256(message) with RFC 6979 nonces and low-S normalization. The
+//!   request names the address it expects, and the signer must refuse with
+//!   an error if it holds a different key.
+//!
+//! Errors are non-2xx responses with `{"error": "..."}`. The cosigner checks
+//! every returned signature against the expected address before using it.
+//!
+//! Anyone who can reach the signer can have it sign, so access must be
+//! restricted. Over TCP every request carries `Authorization: Bearer <token>`
+//! with the shared `COSIGNER_REMOTE_SIGNER_TOKEN`, and the signer answers 401
+//! to any other request; the token crosses the network in the clear, so keep
+//! TCP signers on a private network. A Unix socket is guarded by its file
+//! permissions: `remote-signer` creates it readable and writable by its own
+//! user only, and the cosigner must run as that user (or be granted access by
+//! the operator). A token, when set, is checked on Unix sockets too.
+
+use crate::{
+    approval,
+    keys::{self, KeyError},
+};
+use k256::ecdsa::{signature::Signer as _, RecoveryId, Signature, SigningKey};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// How long to wait on a remote signer before failing the request.
+pub const REMOTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request or response body either side of the protocol accepts.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Environment variable holding the bearer token both sides share.
+pub const TOKEN_ENV: &str = "COSIGNER_REMOTE_SIGNER_TOKEN";
+
+#[derive(Debug)]
+pub struct SignerError(pub String);
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+/// A secp256k1 key that can sign cosigner messages.
+pub trait Signer: Send + Sync {
+    /// Ethereum address of the key (lowercase `0x` hex).
+    fn address(&self) -> &str;
+
+    /// Sign `message` (ECDSA over SHA-256) and return the hex `r || s`.
+    fn sign(&self, message: &[u8]) -> Result<String, SignerError>;
+
+    /// Where the key lives, for logs.
+    fn describe(&self) -> String;
+}
+
+/// A key held in this process's memory.
+pub struct LocalSigner {
+    key: SigningKey,
+    address: String,
+}
+
+impl LocalSigner {
+    pub fn new(key: SigningKey) -> Self {
+        let address = approval::address(key.verifying_key());
+        LocalSigner { key, address }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn address(&self) -> &str {
+        &self.address
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<String, SignerError> {
+        let (sig, _): (Signature, RecoveryId) = self.key.sign(message);
+        Ok(hex::encode(sig.to_bytes()))
+    }
+
+    fn describe(&self) -> String {
+        "in-memory key".to_string()
+    }
+}
+
+/// A key decrypted from a keystore v3 file at startup.
+pub struct KeystoreSigner {
+    inner: LocalSigner,
+    path: PathBuf,
+}
+
+impl KeystoreSigner {
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self, KeyError> {
+        Ok(KeystoreSigner {
+            inner: LocalSigner::new(keys::key_from_keystore(path, passphrase)?),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Signer for KeystoreSigner {
+    fn address(&self) -> &str {
+        self.inner.address()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<String, SignerError> {
+        self.inner.sign(message)
+    }
+
+    fn describe(&self) -> String {
+        format!("keystore {}", self.path.display())
+    }
+}
+
+/// Where a remote signer listens.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    /// `host:port`
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Parse `http://host:port` or `unix:/path`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if let Some(addr) = s.strip_prefix("http://") {
+            let addr = addr.trim_end_matches('/');
+            if addr.is_empty() || addr.contains('/') {
+                return Err(format!("Invalid remote signer URL {s}"));
+            }
+            Ok(Endpoint::Tcp(addr.to_string()))
+        } else if let Some(path) = s.strip_prefix("unix:") {
+            Ok(Endpoint::Unix(PathBuf::from(path)))
+        } else {
+            Err(format!(
+                "Remote signer must be http://host:port or unix:/path, got {s}"
+            ))
+        }
+    }
+
+    fn connect(&self, timeout: Duration) -> io::Result<Box<dyn Stream>> {
+        match self {
+            Endpoint::Tcp(addr) => {
+                let stream = connect_tcp(addr, timeout)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            )),
+        }
+    }
+}
+
+/// Connect to the first address `addr` resolves to that answers within
+/// `timeout`.
+fn connect_tcp(addr: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("{addr} did not resolve"))
+    }))
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => write!(f, "http://{addr}"),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+/// One HTTP/1.1 message, with the only headers the protocol uses.
+struct Message {
+    start: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Read one HTTP/1.1 message: its start line, `Authorization` header and
+/// `Content-Length` body.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Message> {
+    let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut start = String::new();
+    reader.read_line(&mut start)?;
+    let mut authorization = None;
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad("connection closed in headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad("invalid Content-Length"))?;
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(bad("body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Message {
+        start: start.trim_end().to_string(),
+        authorization,
+        body,
+    })
+}
+
+fn write_message(out: &mut impl Write, start: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        out,
+        "{start}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    out.write_all(body)?;
+    out.flush()
+}
+
+/// A key held by a separate signer process, reached over HTTP.
+pub struct RemoteSigner {
+    endpoint: Endpoint,
+    address: String,
+    /// Bearer token sent with every request.
+    token: Option<Zeroizing<String>>,
+    timeout: Duration,
+}
+
+impl RemoteSigner {
+    /// Connect to `endpoint` and learn which address it signs for. TCP
+    /// endpoints require a `token`.
+    pub fn connect(
+        endpoint: Endpoint,
+        token: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self, SignerError> {
+        if matches!(endpoint, Endpoint::Tcp(_)) && token.is_none() {
+            return Err(SignerError(format!(
+                "{endpoint} is reached over TCP; set {TOKEN_ENV} on the cosigner and the signer"
+            )));
+        }
+        let mut signer = RemoteSigner {
+            endpoint,
+            address: String::new(),
+            token: token.map(|t| Zeroizing::new(t.to_string())),
+            timeout,
+        };
+        let resp = signer.call("GET", "/v1/address", None)?;
+        signer.address = resp["address"]
+            .as_str()
+            .ok_or_else(|| SignerError(format!("{} returned no address", signer.endpoint)))?
+            .to_lowercase();
+        Ok(signer)
+    }
+
+    fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, SignerError> {
+        let fail =
+            |e: &dyn fmt::Display| SignerError(format!("Remote signer {}: {e}", self.endpoint));
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let mut stream = self.endpoint.connect(self.timeout).map_err(|e| fail(&e))?;
+        let mut start = Zeroizing::new(format!("{method} {path} HTTP/1.1\r\nHost: signer"));
+        if let Some(token) = &self.token {
+            start.push_str(&format!("\r\nAuthorization: Bearer {}", token.as_str()));
+        }
+        write_message(&mut stream, &start, body.as_bytes()).map_err(|e| fail(&e))?;
+        let Message {
+            start: status_line,
+            body,
+            ..
+        } = read_message(&mut BufReader::new(stream)).map_err(|e| fail(&e))?;
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| fail(&format!("bad status line {status_line:?}")))?;
+        let value: Value = serde_json::from_slice(&body).map_err(|e| fail(&e))?;
+        if !(200..300).contains(&status) {
+            let error = value["error"].as_str().unwrap_or("no error message");
+            return Err(fail(&format!("HTTP {status}: {error}")));
+        }
+        Ok(value)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn address(&self) -> &str {
+        &self.address
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<String, SignerError> {
+        let resp = self.call(
+            "POST",
+            "/v1/sign",
+            Some(&json!({"address": self.address, "message": hex::encode(message)})),
+        )?;
+        let signature = resp["signature"]
+            .as_str()
+            .ok_or_else(|| SignerError(format!("{} returned no signature", self.endpoint)))?;
+        // Never hand out a signature the key ring can't stand behind
+        if !approval::signed_by(message, signature, &self.address) {
+            return Err(SignerError(format!(
+                "{} returned a signature that does not verify for {}",
+                self.endpoint, self.address
+            )));
+        }
+        Ok(signature.to_string())
+    }
+
+    fn describe(&self) -> String {
+        format!("remote signer {}", self.endpoint)
+    }
+}
+
+/// Whether `authorization` is `Bearer <expected>`, compared in constant time.
+fn bearer_matches(authorization: Option<&str>, expected: &str) -> bool {
+    authorization
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+/// Serve one remote signer protocol request on `stream` with `signer`. When
+/// `token` is set, requests without it are refused with 401.
+pub fn serve_connection(
+    stream: impl Read + Write,
+    signer: &dyn Signer,
+    token: Option<&str>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let Message {
+        start: request_line,
+        authorization,
+        body,
+    } = read_message(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let authorized = token.is_none_or(|t| bearer_matches(authorization.as_deref(), t));
+    let (status, value) = match (method, path) {
+        _ if !authorized => ("401 Unauthorized", json!({"error": "unauthorized"})),
+        ("GET", "/v1/address") => ("200 OK", json!({"address": signer.address()})),
+        ("POST", "/v1/sign") => match sign_request(&body, signer) {
+            Ok(signature) => ("200 OK", json!({"signature": signature})),
+            Err(e) => ("400 Bad Request", json!({"error": e})),
+        },
+        _ => ("404 Not Found", json!({"error": "unknown endpoint"})),
+    };
+    write_message(
+        reader.get_mut(),
+        &format!("HTTP/1.1 {status}"),
+        value.to_string().as_bytes(),
+    )
+}
+
+fn sign_request(body: &[u8], signer: &dyn Signer) -> Result<String, String> {
+    let req: Value = serde_json::from_slice(body).map_err(|e| format!("Invalid JSON: {e}"))?;
+    let address = req["address"].as_str().unwrap_or("");
+    if !address.eq_ignore_ascii_case(signer.address()) {
+        return Err(format!(
+            "This signer holds {}, not {address}",
+            signer.address()
+        ));
+    }
+    let message = hex::decode(req["message"].as_str().unwrap_or(""))
+        .map_err(|e| format!("Invalid message hex: {e}"))?;
+    signer.sign(&message).map_err(|e| e.to_string())
+}
+
+/// Local signers from `COSIGNER_KEYSTORE` (comma-separated keystore v3 files,
+/// one passphrase) or, failing that, a plaintext `COSIGNER_PRIVATE_KEY`.
+pub fn local_from_env() -> Result<Vec<Arc<dyn Signer>>, KeyError> {
+    if let Ok(keystores) = std::env::var("COSIGNER_KEYSTORE") {
+        let passphrase = keys::keystore_passphrase(&keystores)?;
+        return keystores
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                KeystoreSigner::open(Path::new(p), &passphrase)
+                    .map(|s| Arc::new(s) as Arc<dyn Signer>)
+            })
+            .collect();
+    }
+    match std::env::var("COSIGNER_PRIVATE_KEY") {
+        Ok(hex_key) => {
+            let hex_key = Zeroizing::new(hex_key);
+            log::warn!(
+                "Using plaintext COSIGNER_PRIVATE_KEY; use COSIGNER_KEYSTORE or COSIGNER_REMOTE_SIGNER outside development"
+            );
+            Ok(vec![Arc::new(LocalSigner::new(keys::key_from_hex(
+                &hex_key,
+            )?))])
+        }
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// The shared remote signer token, if `COSIGNER_REMOTE_SIGNER_TOKEN` is set.
+pub fn token_from_env() -> Option<Zeroizing<String>> {
+    std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.is_empty())
+        .map(Zeroizing::new)
+}
+
+/// Every signer the cosigner is configured with: the local ones from
+/// [`local_from_env`], then one per `COSIGNER_REMOTE_SIGNER` endpoint
+/// (comma-separated), authenticated with [`token_from_env`].
+pub fn from_env() -> Result<Vec<Arc<dyn Signer>>, KeyError> {
+    let mut signers = local_from_env()?;
+    let token = token_from_env();
+    if let Ok(endpoints) = std::env::var("COSIGNER_REMOTE_SIGNER") {
+        for endpoint in endpoints
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let endpoint = Endpoint::parse(endpoint).map_err(KeyError::Remote)?;
+            let remote = RemoteSigner::connect(
+                endpoint,
+                token.as_deref().map(String::as_str),
+                REMOTE_TIMEOUT,
+            )
+            .map_err(|e| KeyError::Remote(e.to_string()))?;
+            signers.push(Arc::new(remote));
+        }
+    }
+    if signers.is_empty() {
+        return Err(KeyError::NotConfigured);
+    }
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn local() -> LocalSigner {
+        LocalSigner::new(SigningKey::from_bytes((&[7u8; 32]).into()).unwrap())
+    }
+
+    const TOKEN: &str = "signer-token";
+
+    /// Serve `count` connections from `listener` on a background thread.
+    fn spawn_server(listener: TcpListener, signer: Arc<dyn Signer>, count: usize) {
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                serve_connection(stream.unwrap(), signer.as_ref(), Some(TOKEN)).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(
+            Endpoint::parse("http://127.0.0.1:4000/").unwrap(),
+            Endpoint::Tcp("127.0.0.1:4000".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("unix:/run/signer.sock").unwrap(),
+            Endpoint::Unix(PathBuf::from("/run/signer.sock"))
+        );
+        assert!(Endpoint::parse("https://signer").is_err());
+        assert!(Endpoint::parse("http://host/path").is_err());
+    }
+
+    #[test]
+    fn test_remote_signer_matches_local() {
+        let signer = local();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+        let expected = signer.sign(b"approval").unwrap();
+        spawn_server(listener, Arc::new(signer), 2);
+
+        let remote = RemoteSigner::connect(endpoint, Some(TOKEN), REMOTE_TIMEOUT).unwrap();
+        assert_eq!(remote.address(), local().address());
+        // RFC 6979 signatures are deterministic, so the wire adds nothing
+        assert_eq!(remote.sign(b"approval").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_remote_signer_rejects_wrong_key() {
+        // A server holding a different key than the client expects
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+        spawn_server(listener, Arc::new(local()), 1);
+        let remote = RemoteSigner {
+            endpoint,
+            address: "0x0000000000000000000000000000000000000001".to_string(),
+            token: Some(Zeroizing::new(TOKEN.to_string())),
+            timeout: REMOTE_TIMEOUT,
+        };
+        let err = remote.sign(b"approval").unwrap_err();
+        assert!(err.0.contains("HTTP 400"), "{err}");
+    }
+
+    #[test]
+    fn test_remote_signer_requires_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+        // TCP without a token is refused before connecting
+        assert!(RemoteSigner::connect(endpoint.clone(), None, REMOTE_TIMEOUT).is_err());
+
+        spawn_server(listener, Arc::new(local()), 1);
+        let remote = RemoteSigner {
+            endpoint,
+            address: local().address().to_string(),
+            token: Some(Zeroizing::new("wrong-token".to_string())),
+            timeout: REMOTE_TIMEOUT,
+        };
+        let err = remote.sign(b"approval").unwrap_err();
+        assert!(err.0.contains("HTTP 401"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_signer_over_unix_socket() {
+        let path = std::env::temp_dir().join("test_remote_signer.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || {
+            let signer = local();
+            let (stream, _) = listener.accept().unwrap();
+            serve_connection(stream, &signer, None).unwrap();
+        });
+
+        let remote =
+            RemoteSigner::connect(Endpoint::Unix(path.clone()), None, REMOTE_TIMEOUT).unwrap();
+        assert_eq!(remote.address(), local().address());
+        let _ = std::fs::remove_file(&path);
+    }
+}
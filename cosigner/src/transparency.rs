@@ -0,0 +1,500 @@
+//! Transparency log over issued approvals: an RFC 6962 Merkle tree whose
+//! roots the cosigner periodically signs and publishes. Inclusion proofs show
+//! an approval is in a published tree; consistency proofs show a later tree
+//! extends an earlier one, so approvals can't be issued off the record.
+
+use crate::persist::write_atomic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Default location of the leaf log; signed roots go to `<path>.roots.json`.
+pub const DEFAULT_TRANSPARENCY_LOG_PATH: &str = "./transparency_log.jsonl";
+
+pub type Hash = [u8; 32];
+
+/// An issued approval, as committed to the tree.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApprovalLeaf {
+    pub chain_id: u64,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+    pub token: String,
+    pub nonce: u64,
+    pub timestamp: u64,
+    /// Hex-encoded cosigner signature over the approval digest.
+    pub signature: String,
+}
+
+impl ApprovalLeaf {
+    /// Leaf hash: `SHA-256(0x00 || JSON(leaf))`.
+    pub fn leaf_hash(&self) -> Hash {
+        leaf_hash(&serde_json::to_vec(self).expect("ApprovalLeaf serializes"))
+    }
+}
+
+/// A tree head signed with the cosigner key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedRoot {
+    pub tree_size: u64,
+    /// Hex-encoded Merkle tree hash.
+    pub root_hash: String,
+    pub timestamp: u64,
+    /// Hex-encoded signature over [`root_digest`].
+    pub signature: String,
+    /// Cosigner key that made the signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut h = Sha256::new();
+    h.update([0u8]);
+    h.update(data);
+    h.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut h = Sha256::new();
+    h.update([1u8]);
+    h.update(left);
+    h.update(right);
+    h.finalize().into()
+}
+
+/// Message the cosigner signs when publishing a root:
+/// `keccak256("zkml-cosigner tree head" || tree_size || root || timestamp)`,
+/// integers big-endian.
+pub fn root_digest(tree_size: u64, root: &Hash, timestamp: u64) -> Hash {
+    let mut hasher = Keccak::v256();
+    hasher.update(b"zkml-cosigner tree head");
+    hasher.update(&tree_size.to_be_bytes());
+    hasher.update(root);
+    hasher.update(&timestamp.to_be_bytes());
+    let mut out = [0u8; 32];
+    hasher.finalize(&mut out);
+    out
+}
+
+/// Largest power of two strictly less than `n` (n >= 2).
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+/// Merkle tree hash of `leaves` (RFC 6962 §2.1).
+pub fn tree_hash(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&tree_hash(&leaves[..k]), &tree_hash(&leaves[k..]))
+        }
+    }
+}
+
+/// Audit path for leaf `index` in the tree over `leaves` (RFC 6962 §2.1.1).
+pub fn inclusion_proof(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    let n = leaves.len();
+    if n <= 1 {
+        return Vec::new();
+    }
+    let k = split(n);
+    if index < k {
+        let mut path = inclusion_proof(index, &leaves[..k]);
+        path.push(tree_hash(&leaves[k..]));
+        path
+    } else {
+        let mut path = inclusion_proof(index - k, &leaves[k..]);
+        path.push(tree_hash(&leaves[..k]));
+        path
+    }
+}
+
+/// Consistency proof between the first `first` leaves and all of `leaves`
+/// (RFC 6962 §2.1.2).
+pub fn consistency_proof(first: usize, leaves: &[Hash]) -> Vec<Hash> {
+    fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+        let n = leaves.len();
+        if m == n {
+            return if complete {
+                Vec::new()
+            } else {
+                vec![tree_hash(leaves)]
+            };
+        }
+        let k = split(n);
+        if m <= k {
+            let mut p = subproof(m, &leaves[..k], complete);
+            p.push(tree_hash(&leaves[k..]));
+            p
+        } else {
+            let mut p = subproof(m - k, &leaves[k..], false);
+            p.push(tree_hash(&leaves[..k]));
+            p
+        }
+    }
+    if first == 0 || first >= leaves.len() {
+        return Vec::new();
+    }
+    subproof(first, leaves, true)
+}
+
+/// Check an inclusion proof (RFC 9162 §2.1.3.2).
+pub fn verify_inclusion(
+    leaf: &Hash,
+    index: u64,
+    tree_size: u64,
+    proof: &[Hash],
+    root: &Hash,
+) -> bool {
+    if index >= tree_size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (index, tree_size - 1);
+    let mut r = *leaf;
+    for p in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && r == *root
+}
+
+/// Check a consistency proof (RFC 9162 §2.1.4.2).
+pub fn verify_consistency(
+    first: u64,
+    second: u64,
+    first_root: &Hash,
+    second_root: &Hash,
+    proof: &[Hash],
+) -> bool {
+    if first > second {
+        return false;
+    }
+    if first == second {
+        return proof.is_empty() && first_root == second_root;
+    }
+    if first == 0 {
+        return proof.is_empty();
+    }
+    let mut path: Vec<Hash> = proof.to_vec();
+    if first.is_power_of_two() {
+        path.insert(0, *first_root);
+    }
+    let Some((head, rest)) = path.split_first() else {
+        return false;
+    };
+    let (mut fn_, mut sn) = (first - 1, second - 1);
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let (mut fr, mut sr) = (*head, *head);
+    for c in rest {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && fr == *first_root && sr == *second_root
+}
+
+fn roots_path(log_path: &Path) -> PathBuf {
+    let mut name = log_path.as_os_str().to_os_string();
+    name.push(".roots.json");
+    PathBuf::from(name)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Approvals appended one JSON leaf per line, with the published signed
+/// roots kept beside them. Leaf hashes are held in memory.
+pub struct TransparencyLog {
+    file: File,
+    roots_path: PathBuf,
+    leaves: Vec<Hash>,
+    /// `(chain_id, sender, nonce)` to leaf index.
+    index: HashMap<(u64, String, u64), usize>,
+    roots: Vec<SignedRoot>,
+}
+
+impl TransparencyLog {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut leaves = Vec::new();
+        let mut index = HashMap::new();
+        match File::open(path) {
+            Ok(f) => {
+                for (i, line) in BufReader::new(f).lines().enumerate() {
+                    let leaf: ApprovalLeaf = serde_json::from_str(&line?)
+                        .map_err(|e| invalid(format!("line {}: unreadable leaf: {e}", i + 1)))?;
+                    index.insert((leaf.chain_id, leaf.from.to_lowercase(), leaf.nonce), i);
+                    leaves.push(leaf.leaf_hash());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let roots_path = roots_path(path);
+        let roots: Vec<SignedRoot> = match fs::read(&roots_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| invalid(format!("Corrupt signed roots: {e}")))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        // Every published root must still be reproducible from the leaves
+        for root in &roots {
+            let size = root.tree_size as usize;
+            if size > leaves.len() || hex::encode(tree_hash(&leaves[..size])) != root.root_hash {
+                return Err(invalid(format!(
+                    "published root for tree size {size} no longer matches the leaves"
+                )));
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(TransparencyLog {
+            file,
+            roots_path,
+            leaves,
+            index,
+            roots,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn append(&mut self, leaf: &ApprovalLeaf) -> io::Result<usize> {
+        let mut line = serde_json::to_vec(leaf).map_err(io::Error::other)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        let i = self.leaves.len();
+        self.leaves.push(leaf.leaf_hash());
+        self.index
+            .insert((leaf.chain_id, leaf.from.to_lowercase(), leaf.nonce), i);
+        Ok(i)
+    }
+
+    /// Leaf index of the approval for `sender`'s `nonce` on `chain_id`.
+    pub fn find(&self, chain_id: u64, sender: &str, nonce: u64) -> Option<usize> {
+        self.index
+            .get(&(chain_id, sender.to_lowercase(), nonce))
+            .copied()
+    }
+
+    pub fn leaf(&self, index: usize) -> Option<&Hash> {
+        self.leaves.get(index)
+    }
+
+    pub fn latest_root(&self) -> Option<&SignedRoot> {
+        self.roots.last()
+    }
+
+    pub fn root_at(&self, tree_size: u64) -> Option<&SignedRoot> {
+        self.roots.iter().find(|r| r.tree_size == tree_size)
+    }
+
+    pub fn inclusion_proof(&self, index: usize, tree_size: usize) -> Vec<Hash> {
+        inclusion_proof(index, &self.leaves[..tree_size])
+    }
+
+    pub fn consistency_proof(&self, first: usize, second: usize) -> Vec<Hash> {
+        consistency_proof(first, &self.leaves[..second])
+    }
+
+    /// Sign and persist the current root if the tree grew since the last
+    /// published one. `sign` maps a [`root_digest`] to the signing key id and
+    /// a hex signature.
+    pub fn publish(
+        &mut self,
+        now: u64,
+        sign: impl FnOnce(&Hash) -> io::Result<(String, String)>,
+    ) -> io::Result<Option<SignedRoot>> {
+        let tree_size = self.leaves.len() as u64;
+        let published = self.roots.last().map_or(0, |r| r.tree_size);
+        if tree_size == 0 || tree_size == published {
+            return Ok(None);
+        }
+        let root = tree_hash(&self.leaves);
+        let (key_id, signature) = sign(&root_digest(tree_size, &root, now))?;
+        let signed = SignedRoot {
+            tree_size,
+            root_hash: hex::encode(root),
+            timestamp: now,
+            signature,
+            key_id: Some(key_id),
+        };
+        let mut roots = self.roots.clone();
+        roots.push(signed.clone());
+        let json = serde_json::to_vec_pretty(&roots).map_err(io::Error::other)?;
+        write_atomic(&self.roots_path, &json)?;
+        self.roots = roots;
+        Ok(Some(signed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| leaf_hash(&i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn test_empty_and_single_leaf_roots() {
+        // SHA-256 of the empty string
+        assert_eq!(
+            hex::encode(tree_hash(&[])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let one = leaves(1);
+        assert_eq!(tree_hash(&one), one[0]);
+    }
+
+    #[test]
+    fn test_inclusion_proofs_verify() {
+        for n in 1..=20 {
+            let all = leaves(n);
+            let root = tree_hash(&all);
+            for (i, leaf) in all.iter().enumerate() {
+                let proof = inclusion_proof(i, &all);
+                assert!(verify_inclusion(leaf, i as u64, n as u64, &proof, &root));
+                if n > 1 {
+                    // Claiming a different position fails
+                    let other = ((i + 1) % n) as u64;
+                    assert!(!verify_inclusion(leaf, other, n as u64, &proof, &root));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_consistency_proofs_verify() {
+        for n in 1..=20 {
+            let all = leaves(n);
+            let second_root = tree_hash(&all);
+            for m in 1..=n {
+                let first_root = tree_hash(&all[..m]);
+                let proof = consistency_proof(m, &all);
+                assert!(
+                    verify_consistency(m as u64, n as u64, &first_root, &second_root, &proof),
+                    "m={m} n={n}"
+                );
+                if m < n {
+                    let forked = leaf_hash(b"rewritten history");
+                    assert!(!verify_consistency(
+                        m as u64,
+                        n as u64,
+                        &forked,
+                        &second_root,
+                        &proof
+                    ));
+                }
+            }
+        }
+    }
+
+    fn approval(nonce: u64) -> ApprovalLeaf {
+        ApprovalLeaf {
+            chain_id: 9745,
+            from: "0x70997970c51812dc3a010c7d01b50e0d17dc79c8".to_string(),
+            to: "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc".to_string(),
+            amount: "1000000".to_string(),
+            token: "0x0000000000000000000000000000000000000001".to_string(),
+            nonce,
+            timestamp: 1_700_000_000,
+            signature: "00".repeat(64),
+        }
+    }
+
+    #[test]
+    fn test_log_publishes_and_reloads() {
+        let path = std::env::temp_dir().join("test_transparency_log.jsonl");
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(roots_path(&path));
+
+        let mut log = TransparencyLog::open(&path).unwrap();
+        assert!(log
+            .publish(1, |_| Ok(("k".into(), "sig".into())))
+            .unwrap()
+            .is_none());
+        log.append(&approval(1)).unwrap();
+        log.append(&approval(2)).unwrap();
+        let first = log
+            .publish(10, |_| Ok(("k".into(), "sig".into())))
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.tree_size, 2);
+        // Nothing new: no new root
+        assert!(log
+            .publish(20, |_| Ok(("k".into(), "sig".into())))
+            .unwrap()
+            .is_none());
+        log.append(&approval(3)).unwrap();
+        drop(log);
+
+        let mut log = TransparencyLog::open(&path).unwrap();
+        assert_eq!(log.size(), 3);
+        let index = log
+            .find(9745, "0x70997970C51812dc3A010C7d01b50e0d17dc79C8", 2)
+            .unwrap();
+        let second = log
+            .publish(30, |_| Ok(("k".into(), "sig".into())))
+            .unwrap()
+            .unwrap();
+        let root = <Hash>::try_from(hex::decode(&second.root_hash).unwrap()).unwrap();
+        let proof = log.inclusion_proof(index, 3);
+        assert!(verify_inclusion(
+            log.leaf(index).unwrap(),
+            index as u64,
+            3,
+            &proof,
+            &root
+        ));
+        assert_eq!(log.root_at(2), Some(&first));
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(roots_path(&path));
+    }
+}
@@ -0,0 +1,718 @@
+//! Two-party ECDSA: the client and the cosigner each hold a share of one
+//! secp256k1 key, and a transfer from its address needs both. The cosigner
+//! only runs its signing rounds once the transfer has passed the same checks
+//! as `/verify`, and the result is an ordinary Ethereum signature, so the
+//! guardrail covers plain accounts as well as contracts that check
+//! approvals.
+//!
+//! Key generation, auxiliary info and signing are the CGGMP21 protocols from
+//! the `cggmp21` crate, run with two parties: the client is [`CLIENT`] and the
+//! cosigner [`COSIGNER`]. Each run of a protocol is a [`Party`] on its own
+//! thread, and the two sides swap batches of [`WireMessage`]s until both
+//! finish. CGGMP21 assumes private, authenticated channels, so the messages
+//! must only travel over TLS.
+//!
+//! The signed transaction is an EIP-1559 ERC-20 `transfer` built from the
+//! checked [`TxDetails`] and the gas fields in [`TransferTx`]; the cosigner
+//! never signs a hash it didn't compute itself.
+
+use crate::{
+    approval::{self, keccak256, parse_address, parse_amount, signed_by, TxDetails},
+    keys::KeyError,
+    signer::{Signer, SignerError},
+};
+use cggmp21::{
+    generic_ec::Scalar,
+    round_based::{
+        state_machine::{self, ProceedResult, StateMachine},
+        Incoming, MessageType,
+    },
+    supported_curves::Secp256k1,
+    DataToSign, ExecutionId, PregeneratedPrimes,
+};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use rand_core::OsRng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io,
+    path::Path,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+/// Domain tag of key generation requests; see
+/// [`approval_digest`](crate::approval::approval_digest).
+const KEYGEN_TAG: &[u8] = b"zkml-two-party-keygen-v1";
+
+/// Party index of the client.
+pub const CLIENT: u16 = 0;
+/// Party index of the cosigner.
+pub const COSIGNER: u16 = 1;
+const PARTIES: [u16; 2] = [CLIENT, COSIGNER];
+
+/// Longest a party may take to answer one batch. Generating the auxiliary
+/// info searches for safe primes, which takes about a minute.
+pub const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// One party's share of the two-party key.
+pub type KeyShare = cggmp21::KeyShare<Secp256k1>;
+
+/// One protocol message, as carried over the API.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WireMessage {
+    /// Sent to every party rather than only the peer. Both look the same with
+    /// two parties, but the protocols check which kind each message is.
+    pub broadcast: bool,
+    pub body: serde_json::Value,
+}
+
+/// The cosigner's answer to a batch: its messages, and whether its side of
+/// the protocol has finished.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Round {
+    pub messages: Vec<WireMessage>,
+    pub finished: bool,
+}
+
+/// Body of `POST /v1/two-party/sessions/{id}`: the client's messages.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Batch {
+    pub messages: Vec<WireMessage>,
+}
+
+/// Body of `POST /v1/two-party/keygen`: a wallet asking for a two-party key,
+/// signed by the wallet itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeygenRequest {
+    /// Address of the wallet the key is for.
+    pub owner: String,
+    /// Unix time the request was signed at.
+    pub timestamp: u64,
+    /// Hex `r || s` by `owner` over [`keygen_digest`], made the same way as
+    /// approval signatures.
+    pub signature: String,
+}
+
+/// Digest a wallet signs to ask for a key:
+/// keccak256(tag || owner || timestamp)
+/// with owner as its 20 address bytes and timestamp as an 8-byte big-endian
+/// integer.
+pub fn keygen_digest(owner: &str, timestamp: u64) -> Result<[u8; 32], String> {
+    let mut msg = Vec::with_capacity(KEYGEN_TAG.len() + 20 + 8);
+    msg.extend_from_slice(KEYGEN_TAG);
+    msg.extend_from_slice(&parse_address(owner)?);
+    msg.extend_from_slice(&timestamp.to_be_bytes());
+    Ok(keccak256(&msg))
+}
+
+impl KeygenRequest {
+    /// A request by `signer`'s address, signed at `timestamp`.
+    pub fn sign(signer: &dyn Signer, timestamp: u64) -> Result<Self, SignerError> {
+        let owner = signer.address().to_string();
+        let digest = keygen_digest(&owner, timestamp).map_err(SignerError)?;
+        Ok(KeygenRequest {
+            signature: signer.sign(&digest)?,
+            owner,
+            timestamp,
+        })
+    }
+
+    /// Check the request is signed by its `owner`.
+    pub fn verify(&self) -> Result<(), String> {
+        let digest = keygen_digest(&self.owner, self.timestamp)?;
+        if signed_by(&digest, &self.signature, &self.owner) {
+            Ok(())
+        } else {
+            Err(format!("Signature is not by owner {}", self.owner))
+        }
+    }
+}
+
+/// The cosigner's answer to opening a session: its id and first round.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Opened {
+    pub session_id: String,
+    /// Bearer token for `POST /v1/two-party/sessions/{id}` in this session.
+    pub session_token: String,
+    /// Hex hash the session signs; absent for key generation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_hash: Option<String>,
+    #[serde(flatten)]
+    pub round: Round,
+}
+
+/// What a party sent in answer to one batch, and its output once finished.
+pub struct Exchange<O> {
+    pub messages: Vec<WireMessage>,
+    pub output: Option<O>,
+}
+
+impl<O> Exchange<O> {
+    pub fn round(&self) -> Round {
+        Round {
+            messages: self.messages.clone(),
+            finished: self.output.is_some(),
+        }
+    }
+}
+
+const ABANDONED: &str = "Session abandoned";
+
+/// Runs one party's state machines, answering each batch from the peer with
+/// everything sent since the last answer.
+struct Driver<O> {
+    peer: u16,
+    batches: mpsc::Receiver<Vec<WireMessage>>,
+    answers: mpsc::Sender<Result<Exchange<O>, String>>,
+    queue: VecDeque<WireMessage>,
+    sent: Vec<WireMessage>,
+    // Message ids only need to be unique within the session
+    next_id: u64,
+}
+
+impl<O> Driver<O> {
+    fn run<T, E, M>(
+        &mut self,
+        mut machine: impl StateMachine<Output = Result<T, E>, Msg = M>,
+    ) -> Result<T, String>
+    where
+        E: fmt::Display,
+        M: Serialize + DeserializeOwned,
+    {
+        loop {
+            match machine.proceed() {
+                ProceedResult::SendMsg(outgoing) => {
+                    let body = serde_json::to_value(&outgoing.msg).map_err(|e| e.to_string())?;
+                    self.sent.push(WireMessage {
+                        broadcast: outgoing.recipient.is_broadcast(),
+                        body,
+                    });
+                }
+                ProceedResult::NeedsOneMoreMessage => {
+                    let wire = self.next_message()?;
+                    let msg = serde_json::from_value(wire.body)
+                        .map_err(|e| format!("Invalid protocol message: {e}"))?;
+                    let msg_type = if wire.broadcast {
+                        MessageType::Broadcast
+                    } else {
+                        MessageType::P2P
+                    };
+                    machine
+                        .received_msg(Incoming {
+                            id: self.next_id,
+                            sender: self.peer,
+                            msg_type,
+                            msg,
+                        })
+                        .map_err(|_| "Protocol message arrived out of turn".to_string())?;
+                    self.next_id += 1;
+                }
+                ProceedResult::Yielded => {}
+                ProceedResult::Output(output) => return output.map_err(|e| e.to_string()),
+                ProceedResult::Error(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    /// The peer's next message. With none queued, answer the last batch with
+    /// what this party has sent and wait for the next one.
+    fn next_message(&mut self) -> Result<WireMessage, String> {
+        loop {
+            if let Some(wire) = self.queue.pop_front() {
+                return Ok(wire);
+            }
+            let answer = Exchange {
+                messages: std::mem::take(&mut self.sent),
+                output: None,
+            };
+            self.answers
+                .send(Ok(answer))
+                .map_err(|_| ABANDONED.to_string())?;
+            let batch = self.batches.recv().map_err(|_| ABANDONED.to_string())?;
+            self.queue.extend(batch);
+        }
+    }
+}
+
+/// One run of a two-party protocol, on its own thread. Each batch of the
+/// peer's messages is answered with this party's next messages; the last
+/// answer carries its output. Dropping the `Party` stops the thread.
+pub struct Party<O> {
+    batches: mpsc::Sender<Vec<WireMessage>>,
+    answers: mpsc::Receiver<Result<Exchange<O>, String>>,
+}
+
+impl<O: Send + 'static> Party<O> {
+    fn spawn(
+        me: u16,
+        protocol: impl FnOnce(&mut Driver<O>) -> Result<O, String> + Send + 'static,
+    ) -> Self {
+        let (batches, inbox) = mpsc::channel();
+        let (outbox, answers) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Nothing runs until the first batch, which may be empty
+            let Ok(first) = inbox.recv() else { return };
+            let mut driver = Driver {
+                peer: PARTIES[usize::from(me == CLIENT)],
+                batches: inbox,
+                answers: outbox,
+                queue: VecDeque::from(first),
+                sent: Vec::new(),
+                next_id: 0,
+            };
+            let result = protocol(&mut driver).map(|output| Exchange {
+                messages: std::mem::take(&mut driver.sent),
+                output: Some(output),
+            });
+            let _ = driver.answers.send(result);
+        });
+        Party { batches, answers }
+    }
+
+    /// Hand the party a batch of the peer's messages without waiting for its
+    /// answer.
+    pub fn send(&self, messages: Vec<WireMessage>) -> Result<(), String> {
+        self.batches
+            .send(messages)
+            .map_err(|_| "Protocol has already stopped".to_string())
+    }
+
+    /// Wait for the party's answer to the last batch.
+    pub fn recv(&self, timeout: Duration) -> Result<Exchange<O>, String> {
+        self.answers.recv_timeout(timeout).map_err(|e| match e {
+            mpsc::RecvTimeoutError::Timeout => "Timed out waiting for the protocol".to_string(),
+            mpsc::RecvTimeoutError::Disconnected => "Protocol has already stopped".to_string(),
+        })?
+    }
+
+    pub fn exchange(&self, messages: Vec<WireMessage>) -> Result<Exchange<O>, String> {
+        self.send(messages)?;
+        self.recv(EXCHANGE_TIMEOUT)
+    }
+}
+
+fn execution_id(protocol: &str, session_id: &str, data: &[u8]) -> [u8; 32] {
+    let mut msg = format!("zkml-cosigner/two-party/{protocol}/{session_id}/").into_bytes();
+    msg.extend_from_slice(data);
+    keccak256(&msg)
+}
+
+/// Generate a new key: distributed key generation, then the auxiliary info
+/// (Paillier keys and ring-Pedersen parameters) that signing needs. Takes a
+/// minute or two, most of it finding safe primes.
+pub fn keygen(me: u16, session_id: &str) -> Party<KeyShare> {
+    let keygen_id = execution_id("keygen", session_id, &[]);
+    let aux_id = execution_id("aux-info", session_id, &[]);
+    Party::spawn(me, move |driver| {
+        let incomplete = driver.run(state_machine::wrap_protocol(|party| async move {
+            cggmp21::keygen::<Secp256k1>(ExecutionId::new(&keygen_id), me, 2)
+                .start(&mut OsRng, party)
+                .await
+        }))?;
+        let primes = PregeneratedPrimes::generate(&mut OsRng);
+        let aux = driver.run(state_machine::wrap_protocol(|party| async move {
+            cggmp21::aux_info_gen(ExecutionId::new(&aux_id), me, 2, primes)
+                .start(&mut OsRng, party)
+                .await
+        }))?;
+        KeyShare::from_parts((incomplete, aux)).map_err(|e| e.to_string())
+    })
+}
+
+/// Sign `hash` (already a digest, not hashed again) with the shared key.
+pub fn sign(
+    me: u16,
+    session_id: &str,
+    share: Arc<KeyShare>,
+    hash: [u8; 32],
+) -> Party<RecoverableSignature> {
+    let id = execution_id("signing", session_id, &hash);
+    Party::spawn(me, move |driver| {
+        let key = shared_key(&share);
+        let signature = driver.run(state_machine::wrap_protocol(|party| async move {
+            let data = DataToSign::from_scalar(Scalar::from_be_bytes_mod_order(hash));
+            cggmp21::signing(ExecutionId::new(&id), me, &PARTIES, &share)
+                .sign(&mut OsRng, party, data)
+                .await
+        }))?;
+        RecoverableSignature::new(signature, &hash, &key)
+    })
+}
+
+/// Run the client's side of `party` against the cosigner. `open` starts the
+/// cosigner's side and returns its first round; `send` delivers the client's
+/// messages and returns the cosigner's answer.
+pub fn run_client<O: Send + 'static>(
+    party: Party<O>,
+    open: impl FnOnce() -> Result<Round, String>,
+    mut send: impl FnMut(Vec<WireMessage>) -> Result<Round, String>,
+) -> Result<O, String> {
+    // Both sides' first rounds need no input, so they run at the same time
+    party.send(Vec::new())?;
+    let mut round = open()?;
+    let first = party.recv(EXCHANGE_TIMEOUT)?;
+    let mut outgoing = first.messages;
+    let mut output = first.output;
+    loop {
+        if output.is_none() && !round.messages.is_empty() {
+            let answer = party.exchange(std::mem::take(&mut round.messages))?;
+            outgoing.extend(answer.messages);
+            output = answer.output;
+            continue;
+        }
+        if round.finished {
+            return output.ok_or_else(|| "Cosigner finished before the client".to_string());
+        }
+        if outgoing.is_empty() {
+            return Err("Client and cosigner are both waiting for a message".to_string());
+        }
+        let answer = send(std::mem::take(&mut outgoing))?;
+        round.messages.extend(answer.messages);
+        round.finished = answer.finished;
+    }
+}
+
+/// A signature with the parity of its `R`, as Ethereum transactions carry.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecoverableSignature {
+    #[serde(with = "hex::serde")]
+    pub r: [u8; 32],
+    #[serde(with = "hex::serde")]
+    pub s: [u8; 32],
+    pub y_parity: u8,
+}
+
+impl RecoverableSignature {
+    fn new(
+        signature: cggmp21::Signature<Secp256k1>,
+        hash: &[u8; 32],
+        key: &VerifyingKey,
+    ) -> Result<Self, String> {
+        let mut bytes = [0u8; 64];
+        signature.normalize_s().write_to_slice(&mut bytes);
+        let sig = Signature::from_slice(&bytes).map_err(|e| e.to_string())?;
+        let id = RecoveryId::trial_recovery_from_prehash(key, hash, &sig)
+            .map_err(|_| "Signature does not verify under the shared key".to_string())?;
+        Ok(RecoverableSignature {
+            r: bytes[..32].try_into().expect("32 bytes"),
+            s: bytes[32..].try_into().expect("32 bytes"),
+            y_parity: u8::from(id.is_y_odd()),
+        })
+    }
+}
+
+/// The shared public key.
+pub fn shared_key(share: &KeyShare) -> VerifyingKey {
+    VerifyingKey::from_sec1_bytes(&share.shared_public_key.to_bytes(true))
+        .expect("a key share's public key is a non-zero point")
+}
+
+/// Address of the shared key: the account the shares sign for.
+pub fn shared_address(share: &KeyShare) -> String {
+    approval::address(&shared_key(share))
+}
+
+/// Encrypt `share` into a keystore v3 file at `path`, the format signing keys
+/// are kept in, replacing it atomically.
+pub fn save_share(path: &Path, share: &KeyShare, passphrase: &str) -> io::Result<()> {
+    let json = Zeroizing::new(serde_json::to_vec(share)?);
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Not a file path: {}", path.display()),
+            )
+        })?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_name = tmp_name.to_string_lossy().into_owned();
+    eth_keystore::encrypt_key(
+        dir,
+        &mut OsRng,
+        json.as_slice(),
+        passphrase,
+        Some(&tmp_name),
+    )
+    .map_err(io::Error::other)?;
+    File::open(dir.join(&tmp_name))?.sync_all()?;
+    fs::rename(dir.join(&tmp_name), path)?;
+    File::open(dir)?.sync_all()
+}
+
+/// Decrypt a key share saved by [`save_share`].
+pub fn load_share(path: &Path, passphrase: &str) -> Result<KeyShare, KeyError> {
+    let json = Zeroizing::new(
+        eth_keystore::decrypt_key(path, passphrase)
+            .map_err(|e| KeyError::Keystore(format!("{}: {e}", path.display())))?,
+    );
+    serde_json::from_slice(&json)
+        .map_err(|e| KeyError::InvalidKey(format!("{}: not a key share: {e}", path.display())))
+}
+
+/// Nonce and gas of the EIP-1559 transaction carrying a transfer. With the
+/// [`TxDetails`] it fixes every signed byte.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransferTx {
+    /// Account nonce of the shared address. The chain accepts each nonce
+    /// once, so a signature can't be replayed.
+    pub nonce: u64,
+    /// Wei per gas, as decimal strings.
+    pub max_priority_fee_per_gas: String,
+    pub max_fee_per_gas: String,
+    pub gas_limit: u64,
+}
+
+/// Selector of `transfer(address,uint256)`.
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+impl TransferTx {
+    /// RLP items of the transaction calling `transfer(tx.to, tx.amount)` on
+    /// `tx.token`, before the signature.
+    fn fields(&self, tx: &TxDetails) -> Result<Vec<Vec<u8>>, String> {
+        tx.validate()?;
+        let amount = parse_amount(&tx.amount).expect("validated");
+        let fee = |field: &str, value: &str| {
+            parse_amount(value)
+                .ok_or_else(|| format!("Invalid {field} {value:?}: expected decimal wei"))
+        };
+        let priority_fee = fee("max_priority_fee_per_gas", &self.max_priority_fee_per_gas)?;
+        let max_fee = fee("max_fee_per_gas", &self.max_fee_per_gas)?;
+        if priority_fee > max_fee {
+            return Err("max_priority_fee_per_gas exceeds max_fee_per_gas".to_string());
+        }
+        let mut data = Vec::with_capacity(4 + 32 + 32);
+        data.extend_from_slice(&TRANSFER_SELECTOR);
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(&parse_address(&tx.to)?);
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&amount.to_be_bytes());
+        Ok(vec![
+            rlp_uint(&tx.chain_id.to_be_bytes()),
+            rlp_uint(&self.nonce.to_be_bytes()),
+            rlp_uint(&priority_fee.to_be_bytes()),
+            rlp_uint(&max_fee.to_be_bytes()),
+            rlp_uint(&self.gas_limit.to_be_bytes()),
+            rlp_bytes(&parse_address(&tx.token)?),
+            rlp_uint(&[]), // no ether attached
+            rlp_bytes(&data),
+            rlp_list(&[]), // empty access list
+        ])
+    }
+
+    /// Hash the shared key signs: `keccak256(0x02 || rlp(fields))`.
+    pub fn signing_hash(&self, tx: &TxDetails) -> Result<[u8; 32], String> {
+        let mut payload = vec![0x02];
+        payload.extend(rlp_list(&self.fields(tx)?));
+        Ok(keccak256(&payload))
+    }
+
+    /// The signed transaction, as sent with `eth_sendRawTransaction`.
+    pub fn encode_signed(
+        &self,
+        tx: &TxDetails,
+        signature: &RecoverableSignature,
+    ) -> Result<Vec<u8>, String> {
+        let mut fields = self.fields(tx)?;
+        fields.push(rlp_uint(&[signature.y_parity]));
+        fields.push(rlp_uint(&signature.r));
+        fields.push(rlp_uint(&signature.s));
+        let mut encoded = vec![0x02];
+        encoded.extend(rlp_list(&fields));
+        Ok(encoded)
+    }
+}
+
+fn rlp_header(offset: u8, len: usize) -> Vec<u8> {
+    if len <= 55 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = len.to_be_bytes();
+    let skip = len_bytes.iter().take_while(|&&b| b == 0).count();
+    let mut header = vec![offset + 55 + (len_bytes.len() - skip) as u8];
+    header.extend_from_slice(&len_bytes[skip..]);
+    header
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if let [b] = bytes {
+        if *b < 0x80 {
+            return vec![*b];
+        }
+    }
+    let mut encoded = rlp_header(0x80, bytes.len());
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// A big-endian integer, without leading zeros.
+fn rlp_uint(be_bytes: &[u8]) -> Vec<u8> {
+    let skip = be_bytes.iter().take_while(|&&b| b == 0).count();
+    rlp_bytes(&be_bytes[skip..])
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(Vec::len).sum();
+    let mut encoded = rlp_header(0xc0, len);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    const PASSPHRASE: &str = "correct horse";
+
+    /// Shares of one key, generated with [`keygen`] and saved with
+    /// [`save_share`] under `PASSPHRASE`.
+    fn fixture_share(party: &str) -> KeyShare {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/two-party")
+            .join(format!("{party}.json"));
+        load_share(&path, PASSPHRASE).unwrap()
+    }
+
+    /// Run `client` against `cosigner` in process; returns both outputs.
+    fn run_pair<O: Send + 'static>(client: Party<O>, cosigner: Party<O>) -> Result<(O, O), String> {
+        let cosigner_output = RefCell::new(None);
+        let exchange = |messages| {
+            let answer = cosigner.exchange(messages)?;
+            let round = answer.round();
+            if let Some(output) = answer.output {
+                *cosigner_output.borrow_mut() = Some(output);
+            }
+            Ok(round)
+        };
+        let client_output = run_client(client, || exchange(Vec::new()), exchange)?;
+        let cosigner_output = cosigner_output
+            .into_inner()
+            .ok_or("Cosigner did not finish")?;
+        Ok((client_output, cosigner_output))
+    }
+
+    fn transfer() -> (TxDetails, TransferTx) {
+        let tx = TxDetails {
+            from: "0xf7a9a82f3a15fac28d142a1f23a5a82f9aece645".to_string(),
+            to: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            amount: "1000000".to_string(),
+            token: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+            chain_id: 1,
+        };
+        let transaction = TransferTx {
+            nonce: 7,
+            max_priority_fee_per_gas: "1000000000".to_string(),
+            max_fee_per_gas: "30000000000".to_string(),
+            gas_limit: 65_000,
+        };
+        (tx, transaction)
+    }
+
+    #[test]
+    fn test_transfer_tx_encoding() {
+        // Checked against alloy-consensus's TxEip1559
+        let (tx, transaction) = transfer();
+        assert_eq!(
+            hex::encode(transaction.signing_hash(&tx).unwrap()),
+            "8055f5d1da978e527e07b63e4c320b8e944c5bc2a053022a5dc2ff6f1ee30925"
+        );
+        let signature: RecoverableSignature = serde_json::from_str(
+            r#"{"r":"dfe3ad8eac0c6d00003e5b3197ec443f409831e56a284df82509066ff57ca9b8","s":"5059bbd06653f32df389f1ddbc3c0756f531779f03dc0c697a48c02ec8df0659","y_parity":1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(transaction.encode_signed(&tx, &signature).unwrap()),
+            "02f8b00107843b9aca008506fc23ac0082fde894a0b86991c6218b36c1d19d4a2e9eb0ce3606eb4880b844a9059cbb00000000000000000000000070997970c51812dc3a010c7d01b50e0d17dc79c800000000000000000000000000000000000000000000000000000000000f4240c001a0dfe3ad8eac0c6d00003e5b3197ec443f409831e56a284df82509066ff57ca9b8a05059bbd06653f32df389f1ddbc3c0756f531779f03dc0c697a48c02ec8df0659"
+        );
+
+        let overpaid = TransferTx {
+            max_priority_fee_per_gas: "30000000001".to_string(),
+            ..transaction
+        };
+        assert!(overpaid.signing_hash(&tx).is_err());
+    }
+
+    #[test]
+    fn test_sign_with_shares() {
+        let client = Arc::new(fixture_share("client"));
+        let cosigner = Arc::new(fixture_share("cosigner"));
+        let address = shared_address(&client);
+        assert_eq!(address, shared_address(&cosigner));
+
+        let (mut tx, transaction) = transfer();
+        tx.from = address;
+        let hash = transaction.signing_hash(&tx).unwrap();
+        let (signature, cosigned) = run_pair(
+            sign(CLIENT, "session", client.clone(), hash),
+            sign(COSIGNER, "session", cosigner.clone(), hash),
+        )
+        .unwrap();
+        assert_eq!(signature, cosigned);
+        let mut bytes = signature.r.to_vec();
+        bytes.extend_from_slice(&signature.s);
+        let recovered = VerifyingKey::recover_from_prehash(
+            &hash,
+            &Signature::from_slice(&bytes).unwrap(),
+            RecoveryId::from_byte(signature.y_parity).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(recovered, shared_key(&client));
+
+        // The two sides must agree on what they sign
+        let other = keccak256(b"another transaction");
+        let mismatched = run_pair(
+            sign(CLIENT, "session", client, hash),
+            sign(COSIGNER, "session", cosigner, other),
+        );
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn test_keygen_request() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let request =
+            KeygenRequest::sign(&crate::signer::LocalSigner::new(key), 1_700_000_000).unwrap();
+        assert!(request.verify().is_ok());
+
+        let replayed = KeygenRequest {
+            timestamp: request.timestamp + 1,
+            ..request.clone()
+        };
+        assert!(replayed.verify().is_err());
+        let claimed = KeygenRequest {
+            owner: "0x70997970c51812dc3a010c7d01b50e0d17dc79c8".to_string(),
+            ..request
+        };
+        assert!(claimed.verify().is_err());
+    }
+
+    #[test]
+    #[ignore = "generates safe primes; takes minutes"]
+    fn test_keygen() {
+        let (client, cosigner) =
+            run_pair(keygen(CLIENT, "keygen"), keygen(COSIGNER, "keygen")).unwrap();
+        assert_eq!(shared_address(&client), shared_address(&cosigner));
+
+        let path = std::env::temp_dir().join("test_two_party_keygen.json");
+        let _ = fs::remove_file(&path);
+        save_share(&path, &client, PASSPHRASE).unwrap();
+        let loaded = load_share(&path, PASSPHRASE).unwrap();
+        assert_eq!(shared_address(&loaded), shared_address(&client));
+        assert!(matches!(
+            load_share(&path, "wrong"),
+            Err(KeyError::Keystore(_))
+        ));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    fmt,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Why a job could not be run on the pool.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PoolError {
+    /// Every worker is busy and the queue is at capacity.
+    QueueFull,
+    /// The job panicked before producing a result.
+    WorkerPanicked,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::QueueFull => write!(f, "verification queue is full"),
+            PoolError::WorkerPanicked => write!(f, "verification worker panicked"),
+        }
+    }
+}
+
+/// Dedicated threads for CPU-heavy proof verification, so a burst of `/verify`
+/// calls can't tie up the actix workers that also serve `/health`.
+/// At most `workers` jobs run at once and at most `queue_depth` more wait;
+/// anything beyond that is refused immediately with [`PoolError::QueueFull`].
+pub struct VerifyPool {
+    sender: SyncSender<Job>,
+    workers: usize,
+    queue_depth: usize,
+}
+
+impl VerifyPool {
+    pub fn new(workers: usize, queue_depth: usize) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = sync_channel::<Job>(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("verify-{i}"))
+                .spawn(move || worker_loop(&receiver))
+                .expect("Failed to spawn verification worker");
+        }
+        VerifyPool {
+            sender,
+            workers,
+            queue_depth,
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// Run `f` on a pool thread and wait for its result without blocking the
+    /// calling executor.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, PoolError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let rx = self.submit(f)?;
+        // The sender is dropped without a value only if the job panicked
+        rx.await.map_err(|_| PoolError::WorkerPanicked)
+    }
+
+    /// Enqueue `f`, or refuse it right away if the queue is full.
+    pub fn submit<F, R>(&self, f: F) -> Result<oneshot::Receiver<R>, PoolError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = tx.send(f());
+        });
+        match self.sender.try_send(job) {
+            Ok(()) => Ok(rx),
+            Err(TrySendError::Full(_)) => Err(PoolError::QueueFull),
+            Err(TrySendError::Disconnected(_)) => Err(PoolError::WorkerPanicked),
+        }
+    }
+}
+
+fn worker_loop(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = {
+            let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+            match receiver.recv() {
+                Ok(job) => job,
+                Err(_) => return, // pool dropped
+            }
+        };
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            log::error!("Verification job panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[actix_web::test]
+    async fn test_run_returns_result() {
+        let pool = VerifyPool::new(2, 4);
+        assert_eq!(pool.run(|| 6 * 7).await, Ok(42));
+    }
+
+    #[actix_web::test]
+    async fn test_full_queue_is_refused() {
+        let pool = VerifyPool::new(1, 1);
+        let (release_tx, release_rx) = channel::<()>();
+        let (started_tx, started_rx) = channel::<()>();
+
+        // Occupy the only worker until released
+        let busy = pool
+            .submit(move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+
+        // One job fits in the queue, the next is refused
+        let queued = pool.submit(|| 1).unwrap();
+        assert_eq!(pool.submit(|| 2).err(), Some(PoolError::QueueFull));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(busy.await, Ok(()));
+        assert_eq!(queued.await, Ok(1));
+    }
+
+    #[actix_web::test]
+    async fn test_panicking_job_does_not_kill_worker() {
+        let pool = VerifyPool::new(1, 1);
+        let result: Result<(), _> = pool.run(|| panic!("boom")).await;
+        assert_eq!(result, Err(PoolError::WorkerPanicked));
+        assert_eq!(pool.run(|| "still alive").await, Ok("still alive"));
+    }
+}
@@ -1,6 +1,7 @@
 use ark_bn254::Fr;
 use ark_serialize::CanonicalSerialize;
 use jolt_core::{poly::commitment::dory::DoryCommitmentScheme, transcripts::KeccakTranscript};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
 use onnx_tracer::{model, tensor::Tensor};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -10,6 +11,7 @@ use std::{
     io::Read,
     path::{Path, PathBuf},
 };
+use tiny_keccak::{Hasher, Keccak};
 use zkml_jolt_core::jolt::JoltSNARK;
 
 /// Jolt lookup table size (2^14 = 16384 entries).
@@ -30,12 +32,119 @@ struct InputFeatures {
     time: usize,
 }
 
+/// An issuer's signed statement of the sender's trust bucket.
+#[derive(Deserialize)]
+struct TrustCredential {
+    subject: String,
+    trust: usize,
+    expires_at: u64,
+    issuer: String,
+    signature: String,
+}
+
 #[derive(Serialize)]
 struct ProverOutput {
     proof: String,
     program_io: String,
     decision: String,
     model_hash: String,
+    /// Hex digest of the trust credential the input was built from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credential_digest: Option<String>,
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Parse a `0x`-prefixed, 40-digit hex address in any case.
+fn parse_address(s: &str) -> Result<[u8; 20], String> {
+    s.strip_prefix("0x")
+        .filter(|h| h.len() == 40)
+        .and_then(|h| hex::decode(h).ok())
+        .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok())
+        .ok_or_else(|| format!("{s} is not an address"))
+}
+
+/// Digest an issuer signs for a trust credential, with the subject as its 20
+/// address bytes.
+// NOTE: Duplicate of credential_digest in cosigner/src/credential.rs, which
+// keeps a test vector that test_credential_vector checks here too.
+fn credential_digest(subject: &str, trust: u8, expires_at: u64) -> Result<[u8; 32], String> {
+    let mut msg = Vec::with_capacity(24 + 20 + 1 + 8);
+    msg.extend_from_slice(b"zkml-trust-credential-v1");
+    msg.extend_from_slice(&parse_address(subject)?);
+    msg.push(trust);
+    msg.extend_from_slice(&expires_at.to_be_bytes());
+    Ok(keccak256(&msg))
+}
+
+/// Whether `signature` (hex `r || s`, ECDSA over SHA-256 of `message`) was
+/// made by the key with Ethereum address `signer`.
+fn signed_by(message: &[u8], signature: &str, signer: &str) -> bool {
+    let Some(sig) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+    else {
+        return false;
+    };
+    [0u8, 1].into_iter().any(|id| {
+        RecoveryId::from_byte(id)
+            .and_then(|id| VerifyingKey::recover_from_msg(message, &sig, id).ok())
+            .is_some_and(|key| {
+                let point = key.to_encoded_point(false);
+                let address = format!(
+                    "0x{}",
+                    hex::encode(&keccak256(&point.as_bytes()[1..])[12..])
+                );
+                address.eq_ignore_ascii_case(signer)
+            })
+    })
+}
+
+/// Check `credential` is signed by one of `issuers`, unexpired at `now`, and
+/// states the `trust` the features claim. Returns its digest.
+fn check_credential(
+    credential: &TrustCredential,
+    features: &InputFeatures,
+    issuers: &[String],
+    now: u64,
+) -> Result<[u8; 32], String> {
+    let trust = u8::try_from(credential.trust)
+        .ok()
+        .filter(|&t| t <= 7)
+        .ok_or_else(|| format!("Credential trust {} out of range (0..=7)", credential.trust))?;
+    if !issuers
+        .iter()
+        .any(|i| i.eq_ignore_ascii_case(&credential.issuer))
+    {
+        return Err(format!(
+            "Credential issuer {} is not trusted",
+            credential.issuer
+        ));
+    }
+    let digest = credential_digest(&credential.subject, trust, credential.expires_at)
+        .map_err(|e| format!("Invalid credential subject: {e}"))?;
+    if !signed_by(&digest, &credential.signature, &credential.issuer) {
+        return Err(format!(
+            "Credential signature is not by issuer {}",
+            credential.issuer
+        ));
+    }
+    if credential.expires_at <= now {
+        return Err(format!("Credential expired at {}", credential.expires_at));
+    }
+    if features.trust != credential.trust {
+        return Err(format!(
+            "Feature 'trust' is {} but the credential states {}",
+            features.trust, credential.trust
+        ));
+    }
+    Ok(digest)
 }
 
 fn load_vocab(path: &str) -> Result<HashMap<String, usize>, Box<dyn std::error::Error>> {
@@ -119,13 +228,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        log::error!("Usage: zkml-prover '<json features>'");
+        log::error!("Usage: zkml-prover '<json features>' ['<json trust credential>']");
         std::process::exit(1);
     }
 
     let features: InputFeatures = serde_json::from_str(&args[1])?;
     validate_features(&features)?;
 
+    // With TRUST_ISSUERS set, `trust` must come from a credential they signed
+    let issuers: Vec<String> = std::env::var("TRUST_ISSUERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|a| a.trim().to_lowercase())
+        .filter(|a| !a.is_empty())
+        .collect();
+    let credential_digest = match args.get(2) {
+        Some(json) => {
+            let credential: TrustCredential = serde_json::from_str(json)?;
+            if issuers.is_empty() {
+                return Err("Set TRUST_ISSUERS to accept trust credentials".into());
+            }
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs();
+            Some(hex::encode(check_credential(
+                &credential,
+                &features,
+                &issuers,
+                now,
+            )?))
+        }
+        None if !issuers.is_empty() => {
+            return Err("TRUST_ISSUERS is set; pass a trust credential".into());
+        }
+        None => None,
+    };
+
     // Resolve paths relative to the binary or use MODELS_DIR env
     let models_dir = std::env::var("MODELS_DIR").unwrap_or_else(|_| {
         let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
@@ -169,6 +307,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             program_io: String::new(),
             decision: "DENIED".to_string(),
             model_hash,
+            credential_digest,
         };
         println!("{}", serde_json::to_string(&out)?);
         return Ok(());
@@ -198,6 +337,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         program_io: program_io_json,
         decision: "AUTHORIZED".to_string(),
         model_hash,
+        credential_digest,
     };
     println!("{}", serde_json::to_string(&out)?);
 
@@ -353,6 +493,76 @@ mod tests {
         assert!(validate_features(&bad).is_err());
     }
 
+    #[test]
+    fn test_check_credential() {
+        use k256::ecdsa::{signature::Signer, SigningKey};
+
+        let subject = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+        let key = SigningKey::from_bytes(&[1; 32].into()).unwrap();
+        let point = key.verifying_key().to_encoded_point(false);
+        let issuer = format!(
+            "0x{}",
+            hex::encode(&keccak256(&point.as_bytes()[1..])[12..])
+        );
+        let digest = credential_digest(subject, 5, 1_000).unwrap();
+        let (sig, _): (Signature, RecoveryId) = key.sign(&digest);
+        let credential = TrustCredential {
+            subject: subject.to_string(),
+            trust: 5,
+            expires_at: 1_000,
+            issuer: issuer.clone(),
+            signature: hex::encode(sig.to_bytes()),
+        };
+        let mut features = InputFeatures {
+            budget: 15,
+            trust: 5,
+            amount: 8,
+            category: 0,
+            velocity: 2,
+            day: 1,
+            time: 1,
+        };
+        let issuers = vec![issuer];
+
+        assert_eq!(
+            check_credential(&credential, &features, &issuers, 999),
+            Ok(digest)
+        );
+        assert!(check_credential(&credential, &features, &issuers, 1_000).is_err());
+        assert!(check_credential(&credential, &features, &[], 999).is_err());
+        features.trust = 7;
+        assert!(check_credential(&credential, &features, &issuers, 999).is_err());
+    }
+
+    #[test]
+    fn test_credential_vector() {
+        // Issued by the cosigner's test_credential_vector, with key [1; 32]
+        let credential = TrustCredential {
+            subject: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            trust: 5,
+            expires_at: 1_000,
+            issuer: "0x1a642f0e3c3af545e7acbd38b07251b3990914f1".to_string(),
+            signature: "b11804499a39541c44abf5ae1c879f925ea6f5ac9b163c467033be735545593e\
+                        0c42c0a23476811c96e9646ba531c39411544ff866f3dfa69b953201d12df734"
+                .to_string(),
+        };
+        let features = InputFeatures {
+            budget: 15,
+            trust: 5,
+            amount: 8,
+            category: 0,
+            velocity: 2,
+            day: 1,
+            time: 1,
+        };
+        let issuers = vec![credential.issuer.clone()];
+        let digest = check_credential(&credential, &features, &issuers, 999).unwrap();
+        assert_eq!(
+            hex::encode(digest),
+            "4225396515ba85cd4e75eb097b9aa7bf0133488d3fdbc8801a40c5e3399b2f91"
+        );
+    }
+
     #[test]
     fn test_validate_features_all_at_max() {
         let f = InputFeatures {
@@ -1,4 +1,5 @@
 import { verifyCosigner } from '../zk/cosigner-bridge.js';
+import { CHAIN_ID } from '../x402/config.js';
 
 /**
  * Decorator that wraps a FacilitatorAdapter with ZK proof verification.
@@ -16,7 +17,7 @@ export class ProofGatedAdapter {
    * Register a ZK proof for a pending settlement.
    * @param {string} settlementId
    * @param {object} proofData
-   * @param {object} [txDetails] - Optional { to, amount, token } for cosigner verification
+   * @param {object} [txDetails] - Optional { from, to, amount, token } for cosigner verification
    */
   registerProof(settlementId, proofData, txDetails) {
     this._pendingProofs.set(settlementId, { proofData, txDetails });
@@ -42,13 +43,14 @@ export class ProofGatedAdapter {
     const { proofData, txDetails } = entry;
 
     // Determine real tx details: prefer explicit txDetails, else extract from args
-    let to, amount, token;
+    let from, to, amount, token;
     if (txDetails) {
-      ({ to, amount, token } = txDetails);
+      ({ from, to, amount, token } = txDetails);
     } else {
       // For EIP-3009 transferWithAuthorization(from, to, value, ...),
       // args.args contains the function arguments in order
       const fnArgs = args.args || [];
+      from = fnArgs[0];
       to = fnArgs[1] || args.address;
       amount = fnArgs[2] != null ? String(fnArgs[2]) : '0';
       token = args.address; // writeContract is called on the token contract
@@ -57,7 +59,7 @@ export class ProofGatedAdapter {
     // Verify proof via cosigner
     const cosignerResult = await verifyCosigner(
       proofData,
-      { to, amount, token },
+      { from, to, amount, token, chain_id: CHAIN_ID },
       proofData.model_hash
     );
 
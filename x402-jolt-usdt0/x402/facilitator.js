@@ -45,7 +45,7 @@ app.post('/verify', async (req, res) => {
   try {
     const cosignerResult = await verifyCosigner(
       zkProof,
-      { to: payment.payTo, amount: String(payment.amount), token: USDT0_ADDRESS },
+      { from: payment.from, to: payment.payTo, amount: String(payment.amount), token: USDT0_ADDRESS, chain_id: CHAIN_ID },
       zkProof.model_hash
     );
     return res.json(cosignerResult);
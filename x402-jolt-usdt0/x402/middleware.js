@@ -164,7 +164,7 @@ export function createZk402Middleware(config) {
     try {
       const cosignerResult = await verifyCosigner(
         zkProof,
-        { to: payment.payTo, amount: String(payment.amount), token },
+        { from: payment.from, to: payment.payTo, amount: String(payment.amount), token, chain_id: chainId },
         zkProof.model_hash
       );
 
@@ -4,7 +4,7 @@ import { COSIGNER_URL } from '../x402/config.js';
  * Submit a proof to the cosigner service for proof verification.
  *
  * @param {{ proof, program_io, decision, model_hash }} proofResult
- * @param {{ to, amount, token }} txDetails
+ * @param {{ from, to, amount, token, chain_id }} txDetails
  * @param {string} modelHash
  * @returns {{ approved, signature, nonce, reason }}
  */